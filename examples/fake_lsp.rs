@@ -0,0 +1,368 @@
+//A scripted language server for trying out the editor's client without a real one.
+//Configure it with `rust = target/debug/examples/fake_lsp` in the [lsp] section.
+//Pass --full to have it ask for whole documents instead of incremental edits,
+//and --crash to have it die without a word once a document says `crash`.
+//
+//What it does, all by plain word matching:
+//  diagnostics: a warning on every TODO, an error on every `error`
+//  hover: the word under the cursor and how often it appears
+//  definition: the word's first appearance; references: all of them
+//  rename: every appearance, in the open document and the files like it next to it
+//  code actions: uppercase the word, and remove a TODO through a server command
+//  completion: every word of the document, replacing the whole word under the
+//  cursor, and a snippet for a function call
+use std::{
+    collections::HashMap,
+    env, fs,
+    io::{stdin, stdout, BufReader},
+    process,
+};
+
+use serde_json::{json, Value};
+use text_editor::lsp::{self, LspPosition, TextEdit};
+
+struct Server {
+    documents: HashMap<String, String>,
+    root: Option<String>,
+    next_id: u64,
+}
+fn main() {
+    let full = env::args().any(|arg| arg == "--full");
+    let crash = env::args().any(|arg| arg == "--crash");
+    let mut reader = BufReader::new(stdin());
+    let mut server = Server {
+        documents: HashMap::new(),
+        root: None,
+        next_id: 0,
+    };
+    while let Some(message) = lsp::read_message(&mut reader) {
+        let method = message["method"].as_str().unwrap_or_default();
+        let params = &message["params"];
+        let result = match method {
+            "initialize" => {
+                server.root = params["rootUri"].as_str().map(str::to_string);
+                json!({"capabilities": {
+                    "textDocumentSync": if full { 1 } else { 2 },
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                    "referencesProvider": true,
+                    "renameProvider": true,
+                    "codeActionProvider": true,
+                    "completionProvider": {},
+                    "executeCommandProvider": {"commands": ["fake.removeTodo"]},
+                }})
+            }
+            "initialized" => {
+                send(&json!({"jsonrpc": "2.0", "method": "window/showMessage",
+                    "params": {"type": 3, "message": "fake server ready"}}));
+                continue;
+            }
+            "textDocument/didOpen" => {
+                let document = &params["textDocument"];
+                let uri = document["uri"].as_str().unwrap_or_default().to_string();
+                let text = document["text"].as_str().unwrap_or_default().to_string();
+                server.documents.insert(uri.clone(), text);
+                server.publish(&uri, crash);
+                continue;
+            }
+            "textDocument/didChange" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+                let text = server.documents.entry(uri.to_string()).or_default();
+                for change in params["contentChanges"].as_array().into_iter().flatten() {
+                    let new = change["text"].as_str().unwrap_or_default();
+                    *text = match parse_range(&change["range"]) {
+                        Some((start, end)) => lsp::apply_edits(
+                            text,
+                            &[TextEdit {
+                                start,
+                                end,
+                                text: new.to_string(),
+                            }],
+                        ),
+                        None => new.to_string(),
+                    };
+                }
+                let uri = uri.to_string();
+                server.publish(&uri, crash);
+                continue;
+            }
+            "textDocument/didClose" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+                server.documents.remove(uri);
+                continue;
+            }
+            "textDocument/hover" => match server.word(params) {
+                Some(word) => {
+                    let count = server.occurrences(params, &word).len();
+                    json!({"contents": {"kind": "markdown",
+                        "value": format!("```\n{}\n```\n`{}` appears {} times", word, word, count)}})
+                }
+                None => Value::Null,
+            },
+            "textDocument/definition" => {
+                let uri = params["textDocument"]["uri"].clone();
+                server
+                    .word(params)
+                    .and_then(|word| server.occurrences(params, &word).into_iter().next())
+                    .map_or(Value::Null, |range| json!({"uri": uri, "range": range}))
+            }
+            "textDocument/references" => {
+                let uri = params["textDocument"]["uri"].clone();
+                let ranges = match server.word(params) {
+                    Some(word) => server.occurrences(params, &word),
+                    None => Vec::new(),
+                };
+                ranges
+                    .into_iter()
+                    .map(|range| json!({"uri": uri, "range": range}))
+                    .collect()
+            }
+            "textDocument/rename" => server.rename(params),
+            "textDocument/codeAction" => server.code_actions(params),
+            "textDocument/completion" => server.completions(params),
+            "workspace/executeCommand" => {
+                let arguments = &params["arguments"];
+                let uri = arguments[0].as_str().unwrap_or_default();
+                let line = arguments[1].as_u64().unwrap_or_default();
+                server.remove_todo(uri, line);
+                Value::Null
+            }
+            "shutdown" => Value::Null,
+            "exit" => return,
+            _ if message.get("id").is_some() && message.get("method").is_none() => continue,
+            _ => {
+                send(&json!({"jsonrpc": "2.0", "id": message["id"],
+                    "error": {"code": -32601, "message": format!("unknown method {}", method)}}));
+                continue;
+            }
+        };
+        send(&json!({"jsonrpc": "2.0", "id": message["id"], "result": result}));
+    }
+}
+impl Server {
+    fn publish(&self, uri: &str, crash: bool) {
+        let text = self.documents.get(uri).map_or("", String::as_str);
+        if crash
+            && text
+                .lines()
+                .flat_map(words)
+                .any(|(_, _, word)| word == "crash")
+        {
+            process::exit(101);
+        }
+        let mut diagnostics = Vec::new();
+        for (line, content) in text.lines().enumerate() {
+            for (start, end, word) in words(content) {
+                let (severity, message) = match word.as_str() {
+                    "TODO" => (2, "unfinished work"),
+                    "error" => (1, "something is wrong here"),
+                    _ => continue,
+                };
+                diagnostics.push(json!({
+                    "range": range(line, start, end),
+                    "severity": severity,
+                    "source": "fake",
+                    "message": message,
+                }));
+            }
+        }
+        send(
+            &json!({"jsonrpc": "2.0", "method": "textDocument/publishDiagnostics",
+            "params": {"uri": uri, "diagnostics": diagnostics}}),
+        );
+    }
+    fn text(&self, params: &Value) -> &str {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        self.documents.get(uri).map_or("", String::as_str)
+    }
+    //The word touching the requested position.
+    fn word(&self, params: &Value) -> Option<String> {
+        self.word_range(params).map(|(_, word)| word)
+    }
+    fn word_range(&self, params: &Value) -> Option<(Value, String)> {
+        let position = &params["position"];
+        let line = position["line"].as_u64()? as usize;
+        let character = position["character"].as_u64()? as usize;
+        let content = self.text(params).lines().nth(line)?;
+        words(content)
+            .into_iter()
+            .find(|&(start, end, _)| start <= character && character <= end)
+            .map(|(start, end, word)| (range(line, start, end), word))
+    }
+    fn occurrences(&self, params: &Value, word: &str) -> Vec<Value> {
+        occurrences(self.text(params), word)
+            .into_iter()
+            .map(|(line, start, end)| range(line, start, end))
+            .collect()
+    }
+    //Renames the word in the open document and in the files beside the project root.
+    fn rename(&self, params: &Value) -> Value {
+        let word = match self.word(params) {
+            Some(word) => word,
+            None => return Value::Null,
+        };
+        let new_name = params["newName"].as_str().unwrap_or_default();
+        let edits = |text: &str| -> Vec<Value> {
+            occurrences(text, &word)
+                .into_iter()
+                .map(|(line, start, end)| json!({"range": range(line, start, end), "newText": new_name}))
+                .collect()
+        };
+        let mut changes = serde_json::Map::new();
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        changes.insert(uri.to_string(), Value::Array(edits(self.text(params))));
+        let root = self.root.as_deref().and_then(lsp::path);
+        let entries = root.and_then(|root| fs::read_dir(root).ok());
+        for entry in entries.into_iter().flatten().flatten() {
+            let path = entry.path();
+            let other = lsp::uri(&path);
+            let same_kind = path.extension()
+                == lsp::path(uri)
+                    .and_then(|open| open.extension().map(ToOwned::to_owned))
+                    .as_deref();
+            if other == uri || !same_kind || self.documents.contains_key(&other) {
+                continue;
+            }
+            if let Ok(text) = fs::read_to_string(&path) {
+                let edits = edits(&text);
+                if !edits.is_empty() {
+                    changes.insert(other, Value::Array(edits));
+                }
+            }
+        }
+        json!({ "changes": changes })
+    }
+    fn code_actions(&self, params: &Value) -> Value {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let mut actions = Vec::new();
+        let start = &params["range"]["start"];
+        let position = json!({"textDocument": params["textDocument"], "position": start});
+        if let Some(word) = self.word(&position) {
+            let edits: Vec<Value> = occurrences(self.text(params), &word)
+                .into_iter()
+                .filter(|&(line, _, _)| Some(line as u64) == start["line"].as_u64())
+                .map(|(line, start, end)| {
+                    json!({"range": range(line, start, end), "newText": word.to_uppercase()})
+                })
+                .collect();
+            actions.push(json!({
+                "title": format!("Uppercase `{}`", word),
+                "kind": "refactor.rewrite",
+                "edit": {"changes": {uri: edits}},
+            }));
+        }
+        for diagnostic in params["context"]["diagnostics"]
+            .as_array()
+            .into_iter()
+            .flatten()
+        {
+            if diagnostic["message"] == "unfinished work" {
+                let line = &diagnostic["range"]["start"]["line"];
+                actions.push(json!({
+                    "title": "Remove TODO",
+                    "kind": "quickfix",
+                    "command": {"title": "Remove TODO", "command": "fake.removeTodo",
+                        "arguments": [uri, line]},
+                }));
+            }
+        }
+        Value::Array(actions)
+    }
+    fn completions(&self, params: &Value) -> Value {
+        let text = self.text(params);
+        let mut seen = Vec::new();
+        for (_, _, word) in text.lines().flat_map(words) {
+            if !seen.contains(&word) {
+                seen.push(word);
+            }
+        }
+        let under_cursor = self.word_range(params).map(|(range, _)| range);
+        let mut items: Vec<Value> = seen
+            .iter()
+            .map(|word| {
+                let mut item = json!({"label": word, "kind": 6, "detail": "fake word",
+                    "documentation": {"kind": "markdown", "value": format!(
+                        "`{}` appears {} times", word, occurrences(text, word).len())}});
+                if let Some(range) = &under_cursor {
+                    item["textEdit"] = json!({"range": range, "newText": word});
+                }
+                item
+            })
+            .collect();
+        items.push(json!({"label": "fake_call(…)", "kind": 3,
+            "insertText": "fake_call(${1:first}, ${2:second})$0", "insertTextFormat": 2,
+            "documentation": "Calls the fake function.\nIt takes two arguments."}));
+        json!({"isIncomplete": false, "items": items})
+    }
+    //Asks the editor to take the TODO off `line`, the way servers carry out commands.
+    fn remove_todo(&mut self, uri: &str, line: u64) {
+        let text = self.documents.get(uri).map_or("", String::as_str);
+        let content = text.lines().nth(line as usize).unwrap_or_default();
+        let edits: Vec<Value> = words(content)
+            .into_iter()
+            .filter(|(_, _, word)| word == "TODO")
+            .map(
+                |(start, end, _)| json!({"range": range(line as usize, start, end), "newText": ""}),
+            )
+            .collect();
+        self.next_id += 1;
+        send(
+            &json!({"jsonrpc": "2.0", "id": format!("server-{}", self.next_id),
+            "method": "workspace/applyEdit",
+            "params": {"label": "Remove TODO", "edit": {"changes": {uri: edits}}}}),
+        );
+    }
+}
+fn send(message: &Value) {
+    lsp::write_message(&mut stdout(), message).expect("the editor went away");
+}
+fn range(line: usize, start: usize, end: usize) -> Value {
+    lsp::range(
+        &LspPosition {
+            line,
+            character: start,
+        },
+        &LspPosition {
+            line,
+            character: end,
+        },
+    )
+}
+fn parse_range(range: &Value) -> Option<(LspPosition, LspPosition)> {
+    let position = |value: &Value| {
+        Some(LspPosition {
+            line: value["line"].as_u64()? as usize,
+            character: value["character"].as_u64()? as usize,
+        })
+    };
+    Some((position(&range["start"])?, position(&range["end"])?))
+}
+//The words of a line as (start, end, word), counted in UTF-16 units.
+fn words(line: &str) -> Vec<(usize, usize, String)> {
+    let mut words = Vec::new();
+    let mut current: Option<(usize, String)> = None;
+    let mut column = 0;
+    for c in line.chars() {
+        if c.is_alphanumeric() || c == '_' {
+            current.get_or_insert((column, String::new())).1.push(c);
+        } else if let Some((start, word)) = current.take() {
+            words.push((start, column, word));
+        }
+        column += c.len_utf16();
+    }
+    if let Some((start, word)) = current {
+        words.push((start, column, word));
+    }
+    words
+}
+fn occurrences(text: &str, word: &str) -> Vec<(usize, usize, usize)> {
+    text.lines()
+        .enumerate()
+        .flat_map(|(line, content)| {
+            words(content)
+                .into_iter()
+                .filter(|(_, _, found)| found == word)
+                .map(move |(start, end, _)| (line, start, end))
+        })
+        .collect()
+}
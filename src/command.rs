@@ -0,0 +1,278 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Command {
+    Quit,
+    Save,
+    Search,
+    InnerWords,
+    NextMessage,
+    CommandPalette,
+    CommandLine,
+    OpenFile,
+    Grep,
+    ReplaceInFiles,
+    ToggleExplorer,
+    WordLeft,
+    WordRight,
+    ParagraphUp,
+    ParagraphDown,
+    MatchingBracket,
+    GotoLine,
+    Undo,
+    Redo,
+    Copy,
+    Cut,
+    Paste,
+    AddCursorAbove,
+    AddCursorBelow,
+    AddNextOccurrence,
+    CursorsOnLines,
+    Outdent,
+    ToggleComment,
+    Fold,
+    Unfold,
+    ToggleFolds,
+    Hover,
+    GotoDefinition,
+    FindReferences,
+    Rename,
+    CodeAction,
+    Complete,
+    Format,
+    Filter,
+    InsertOutput,
+    ShowOutput,
+}
+pub struct CommandInfo {
+    pub command: Command,
+    pub name: &'static str,
+    pub description: &'static str,
+}
+//Every command the keymap can bind to. Names are what the config file uses.
+pub const COMMANDS: &[CommandInfo] = &[
+    CommandInfo {
+        command: Command::Quit,
+        name: "quit",
+        description: "Quit the editor",
+    },
+    CommandInfo {
+        command: Command::Save,
+        name: "save",
+        description: "Save the document",
+    },
+    CommandInfo {
+        command: Command::Search,
+        name: "search",
+        description: "Search the document",
+    },
+    CommandInfo {
+        command: Command::InnerWords,
+        name: "inner_words",
+        description: "Show the words of the current line",
+    },
+    CommandInfo {
+        command: Command::NextMessage,
+        name: "next_message",
+        description: "Show the next status message",
+    },
+    CommandInfo {
+        command: Command::CommandPalette,
+        name: "command_palette",
+        description: "Search and run any command",
+    },
+    CommandInfo {
+        command: Command::CommandLine,
+        name: "command_line",
+        description: "Enter a : command such as :w, :e, :42 or :%s/a/b/g",
+    },
+    CommandInfo {
+        command: Command::OpenFile,
+        name: "open_file",
+        description: "Find a file in the working directory and open it",
+    },
+    CommandInfo {
+        command: Command::Grep,
+        name: "grep",
+        description: "Search every file in the working directory with a regex",
+    },
+    CommandInfo {
+        command: Command::ReplaceInFiles,
+        name: "replace_in_files",
+        description: "Replace a regex in every file, with a preview first",
+    },
+    CommandInfo {
+        command: Command::ToggleExplorer,
+        name: "toggle_explorer",
+        description: "Show, focus or hide the file explorer sidebar",
+    },
+    CommandInfo {
+        command: Command::WordLeft,
+        name: "word_left",
+        description: "Move to the start of the previous word",
+    },
+    CommandInfo {
+        command: Command::WordRight,
+        name: "word_right",
+        description: "Move to the end of the next word",
+    },
+    CommandInfo {
+        command: Command::ParagraphUp,
+        name: "paragraph_up",
+        description: "Move to the blank line above the paragraph",
+    },
+    CommandInfo {
+        command: Command::ParagraphDown,
+        name: "paragraph_down",
+        description: "Move to the blank line below the paragraph",
+    },
+    CommandInfo {
+        command: Command::MatchingBracket,
+        name: "matching_bracket",
+        description: "Jump to the bracket matching the one at the cursor",
+    },
+    CommandInfo {
+        command: Command::GotoLine,
+        name: "goto_line",
+        description: "Go to a line number",
+    },
+    CommandInfo {
+        command: Command::Undo,
+        name: "undo",
+        description: "Undo the last edit",
+    },
+    CommandInfo {
+        command: Command::Redo,
+        name: "redo",
+        description: "Redo the last undone edit",
+    },
+    CommandInfo {
+        command: Command::Copy,
+        name: "copy",
+        description: "Copy the selections, or the lines under the cursors",
+    },
+    CommandInfo {
+        command: Command::Cut,
+        name: "cut",
+        description: "Cut the selections, or the lines under the cursors",
+    },
+    CommandInfo {
+        command: Command::Paste,
+        name: "paste",
+        description: "Paste at every cursor, one copied piece each if the counts match",
+    },
+    CommandInfo {
+        command: Command::AddCursorAbove,
+        name: "add_cursor_above",
+        description: "Add a cursor on the line above the topmost one",
+    },
+    CommandInfo {
+        command: Command::AddCursorBelow,
+        name: "add_cursor_below",
+        description: "Add a cursor on the line below the lowest one",
+    },
+    CommandInfo {
+        command: Command::AddNextOccurrence,
+        name: "add_next_occurrence",
+        description: "Select the word, then add a cursor at its next occurrence",
+    },
+    CommandInfo {
+        command: Command::CursorsOnLines,
+        name: "cursors_on_lines",
+        description: "Put a cursor at the end of every selected line",
+    },
+    CommandInfo {
+        command: Command::Outdent,
+        name: "outdent",
+        description: "Take the current or selected lines back one indent level",
+    },
+    CommandInfo {
+        command: Command::ToggleComment,
+        name: "toggle_comment",
+        description: "Comment out the current or selected lines, or uncomment them",
+    },
+    CommandInfo {
+        command: Command::Fold,
+        name: "fold",
+        description: "Fold the block around the cursor",
+    },
+    CommandInfo {
+        command: Command::Unfold,
+        name: "unfold",
+        description: "Open the fold under the cursor",
+    },
+    CommandInfo {
+        command: Command::ToggleFolds,
+        name: "toggle_folds",
+        description: "Fold every block, or open every fold",
+    },
+    CommandInfo {
+        command: Command::Hover,
+        name: "hover",
+        description: "Show what the language server knows about the symbol under the cursor",
+    },
+    CommandInfo {
+        command: Command::GotoDefinition,
+        name: "goto_definition",
+        description: "Go to where the symbol under the cursor is defined",
+    },
+    CommandInfo {
+        command: Command::FindReferences,
+        name: "find_references",
+        description: "List every use of the symbol under the cursor",
+    },
+    CommandInfo {
+        command: Command::Rename,
+        name: "rename",
+        description: "Rename the symbol under the cursor everywhere",
+    },
+    CommandInfo {
+        command: Command::CodeAction,
+        name: "code_action",
+        description: "Pick a fix or refactoring the language server offers here",
+    },
+    CommandInfo {
+        command: Command::Complete,
+        name: "complete",
+        description: "Show what the word before the cursor could be completed to",
+    },
+    CommandInfo {
+        command: Command::Format,
+        name: "format",
+        description: "Run the document through its file type's formatter",
+    },
+    CommandInfo {
+        command: Command::Filter,
+        name: "filter",
+        description:
+            "Replace the selection, or the whole document, with what a shell command makes of it",
+    },
+    CommandInfo {
+        command: Command::InsertOutput,
+        name: "insert_output",
+        description: "Insert what a shell command prints at the cursor",
+    },
+    CommandInfo {
+        command: Command::ShowOutput,
+        name: "show_output",
+        description: "Show what a shell command prints, given the selection if there is one",
+    },
+];
+impl Command {
+    #[must_use]
+    pub fn from_name(name: &str) -> Option<Command> {
+        COMMANDS
+            .iter()
+            .find(|info| info.name == name)
+            .map(|info| info.command)
+    }
+    ///# Panics
+    ///
+    /// panics if the command is missing from COMMANDS
+    #[must_use]
+    pub fn info(self) -> &'static CommandInfo {
+        COMMANDS.iter().find(|info| info.command == self).unwrap()
+    }
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        self.info().name
+    }
+}
@@ -0,0 +1,285 @@
+use std::{cmp::Reverse, collections::HashSet, env, fs, path::PathBuf};
+
+use crate::{
+    document::Document,
+    file_type::FileType,
+    fuzzy,
+    popup::Popup,
+    row::Row,
+    snippet::{self, Snippet},
+    Position,
+};
+
+//Shorter words from the text aren't worth offering.
+const MIN_WORD: usize = 3;
+//Labels longer than this push the details out of the popup.
+const MAX_LABEL: usize = 40;
+
+//Where a completion comes from. Earlier sources win ties in the ranking.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Source {
+    LanguageServer,
+    Snippet,
+    Keyword,
+    Word,
+    Path,
+}
+pub struct Item {
+    pub label: String,
+    //what accepting it puts in place of the prefix
+    pub insert: String,
+    //`insert` is a snippet body, with tab stops to fill in
+    pub snippet: bool,
+    //a short note beside the label, such as what kind of thing it is
+    pub detail: String,
+    pub documentation: Vec<String>,
+    pub source: Source,
+    //the text it takes the place of when that isn't just the prefix, as a
+    //language server can ask for
+    pub replace: Option<(Position, Position)>,
+}
+//The completion popup: the candidates gathered when it opened, narrowed down
+//as the prefix grows.
+pub struct Completion {
+    //where the text being completed starts
+    pub start: Position,
+    //for a path, the directory part in front of `start`
+    pub directory: Option<String>,
+    //opened on request or picked from, so Enter accepts instead of breaking the line
+    pub chosen: bool,
+    //a language server is still working on its candidates
+    pub waiting: bool,
+    items: Vec<Item>,
+    matches: Vec<usize>,
+    prefix: String,
+    pub list: Popup,
+}
+impl Completion {
+    #[must_use]
+    pub fn new(
+        start: Position,
+        directory: Option<String>,
+        items: Vec<Item>,
+        chosen: bool,
+    ) -> Completion {
+        Completion {
+            start,
+            directory,
+            chosen,
+            waiting: false,
+            items,
+            matches: Vec::new(),
+            prefix: String::new(),
+            list: Popup::new(Vec::new()),
+        }
+    }
+    //Adds candidates that came in late, in place of the same text from other sources.
+    pub fn add(&mut self, items: Vec<Item>) {
+        let inserts: HashSet<&str> = items.iter().map(|item| item.insert.as_str()).collect();
+        let mut kept: Vec<Item> = std::mem::take(&mut self.items)
+            .into_iter()
+            .filter(|item| !inserts.contains(item.insert.as_str()))
+            .collect();
+        kept.extend(items);
+        self.items = kept;
+        self.waiting = false;
+        let prefix = self.prefix.clone();
+        self.refilter(&prefix);
+    }
+    //Keeps the candidates that fuzzily match `prefix`, best first. The prefix
+    //itself is left out, there being nothing to complete.
+    pub fn refilter(&mut self, prefix: &str) {
+        self.prefix = prefix.to_string();
+        let mut scored: Vec<(usize, i64)> = self
+            .items
+            .iter()
+            .enumerate()
+            .filter(|(_, item)| item.insert != prefix)
+            .filter_map(|(i, item)| Some((i, fuzzy::score(prefix, &item.label)?)))
+            .collect();
+        let items = &self.items;
+        scored.sort_by_key(|&(i, score)| {
+            let item = &items[i];
+            (
+                Reverse(score),
+                item.source,
+                item.label.len(),
+                item.label.clone(),
+            )
+        });
+        self.matches = scored.into_iter().map(|(i, _)| i).collect();
+        self.list = Popup::new(self.lines());
+        self.list.selected = Some(0);
+    }
+    fn lines(&self) -> Vec<String> {
+        let width = self
+            .matches
+            .iter()
+            .map(|&i| self.items[i].label.chars().count())
+            .max()
+            .unwrap_or(0)
+            .min(MAX_LABEL);
+        self.matches
+            .iter()
+            .map(|&i| {
+                let item = &self.items[i];
+                format!("{:<width$}  {}", item.label, item.detail, width = width)
+                    .trim_end()
+                    .to_string()
+            })
+            .collect()
+    }
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.matches.is_empty()
+    }
+    pub fn up(&mut self) {
+        let selected = self.list.selected.unwrap_or(0);
+        self.list.selected = Some(selected.checked_sub(1).unwrap_or(self.matches.len() - 1));
+    }
+    pub fn down(&mut self) {
+        let selected = self.list.selected.unwrap_or(0);
+        self.list.selected = Some((selected + 1) % self.matches.len());
+    }
+    #[must_use]
+    pub fn selected(&self) -> Option<&Item> {
+        let i = *self.matches.get(self.list.selected?)?;
+        self.items.get(i)
+    }
+}
+//The text being completed before `x` in `row`: the column it starts at and, when
+//it is the last part of a path to an existing directory, that directory.
+#[must_use]
+pub fn prefix(row: &Row, x: usize) -> (usize, Option<String>) {
+    let content = &row.content[..x.min(row.content.len())];
+    let word_start = content
+        .iter()
+        .rposition(|gr| !is_word(gr))
+        .map_or(0, |i| i + 1);
+    let token_start = content
+        .iter()
+        .rposition(|gr| !is_path(gr))
+        .map_or(0, |i| i + 1);
+    if let Some(slash) = content[token_start..].iter().rposition(|gr| gr == "/") {
+        let directory = content[token_start..=token_start + slash].concat();
+        //a comment marker like // is not a path to the root
+        if directory.chars().any(|c| c != '/') && expand(&directory).is_dir() {
+            return (token_start + slash + 1, Some(directory));
+        }
+    }
+    (word_start, None)
+}
+fn is_word(gr: &str) -> bool {
+    gr.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+fn is_path(gr: &str) -> bool {
+    !gr.trim().is_empty() && !gr.chars().any(|c| "\"'`()[]{}<>,;=|&".contains(c))
+}
+fn expand(directory: &str) -> PathBuf {
+    match (directory.strip_prefix("~/"), env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(directory),
+    }
+}
+//SOURCES
+//Words of the document long enough to be worth it, once each, leaving out the
+//one starting at `typing` that is being completed.
+#[must_use]
+pub fn words(document: &Document, typing: &Position) -> Vec<Item> {
+    let mut seen = HashSet::new();
+    let mut items = Vec::new();
+    for (y, row) in document.rows.iter().enumerate() {
+        for range in row.words() {
+            if y + 1 == typing.y && range.start == typing.x {
+                continue;
+            }
+            let word = row.content[range].concat();
+            let starts_like_a_name = word
+                .chars()
+                .next()
+                .is_some_and(|c| c.is_alphabetic() || c == '_');
+            if word.chars().count() < MIN_WORD || !starts_like_a_name || !is_word(&word) {
+                continue;
+            }
+            if seen.insert(word.clone()) {
+                items.push(Item {
+                    label: word.clone(),
+                    insert: word,
+                    snippet: false,
+                    detail: String::new(),
+                    documentation: Vec::new(),
+                    source: Source::Word,
+                    replace: None,
+                });
+            }
+        }
+    }
+    items
+}
+#[must_use]
+pub fn keywords(file_type: &FileType) -> Vec<Item> {
+    let options = &file_type.highlight_ops;
+    let keywords = options.key_words.iter().map(|word| (word, "keyword"));
+    let types = options.types.iter().map(|word| (word, "type"));
+    keywords
+        .chain(types)
+        .map(|(word, detail)| Item {
+            label: word.clone(),
+            insert: word.clone(),
+            snippet: false,
+            detail: detail.to_string(),
+            documentation: Vec::new(),
+            source: Source::Keyword,
+            replace: None,
+        })
+        .collect()
+}
+//What's in `directory`, with a slash after the directories.
+#[must_use]
+pub fn paths(directory: &str) -> Vec<Item> {
+    let entries = match fs::read_dir(expand(directory)) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    entries
+        .flatten()
+        .map(|entry| {
+            let mut name = entry.file_name().to_string_lossy().to_string();
+            let is_dir = entry.file_type().is_ok_and(|kind| kind.is_dir());
+            if is_dir {
+                name.push('/');
+            }
+            Item {
+                label: name.clone(),
+                insert: name,
+                snippet: false,
+                detail: if is_dir { "directory" } else { "file" }.to_string(),
+                documentation: Vec::new(),
+                source: Source::Path,
+                replace: None,
+            }
+        })
+        .collect()
+}
+#[must_use]
+pub fn snippets(snippets: &[Snippet]) -> Vec<Item> {
+    snippets
+        .iter()
+        .map(|snippet| Item {
+            label: snippet.prefix.clone(),
+            insert: snippet.body.clone(),
+            snippet: true,
+            detail: if snippet.description.is_empty() {
+                "snippet".to_string()
+            } else {
+                snippet.description.clone()
+            },
+            documentation: snippet::preview(&snippet.body)
+                .lines()
+                .map(str::to_string)
+                .collect(),
+            source: Source::Snippet,
+            replace: None,
+        })
+        .collect()
+}
@@ -0,0 +1,114 @@
+use std::{env, fs::read_to_string, path::PathBuf};
+
+//The config file is a list of `[section]` headers followed by `key = value` lines.
+//Lines starting with # are comments. Entries keep the order they were written in.
+#[derive(Default)]
+pub struct Config {
+    pub sections: Vec<(String, Vec<(String, String)>)>,
+    pub errors: Vec<String>,
+}
+impl Config {
+    #[must_use]
+    pub fn load() -> Config {
+        if let Some(path) = config_dir().map(|dir| dir.join("config")) {
+            if let Ok(content) = read_to_string(&path) {
+                return Config::parse(&content);
+            }
+        }
+        Config::default()
+    }
+    #[must_use]
+    pub fn parse(content: &str) -> Config {
+        let mut config = Config::default();
+        let mut current = String::new();
+        for (i, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                current = name.trim().to_string();
+                continue;
+            }
+            if let Some((key, value)) = line.split_once('=') {
+                let entry = (key.trim().to_string(), value.trim().to_string());
+                if let Some((_, entries)) = config.sections.iter_mut().find(|s| s.0 == current) {
+                    entries.push(entry);
+                } else {
+                    config.sections.push((current.clone(), vec![entry]));
+                }
+            } else {
+                config
+                    .errors
+                    .push(format!("config line {}: expected key = value", i + 1));
+            }
+        }
+        config
+    }
+    #[must_use]
+    pub fn section(&self, name: &str) -> &[(String, String)] {
+        self.sections
+            .iter()
+            .find(|s| s.0 == name)
+            .map_or(&[], |s| s.1.as_slice())
+    }
+    #[must_use]
+    pub fn get(&self, section: &str, key: &str) -> Option<&str> {
+        self.section(section)
+            .iter()
+            .rev()
+            .find(|entry| entry.0 == key)
+            .map(|entry| entry.1.as_str())
+    }
+}
+#[must_use]
+pub fn config_dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os("XDG_CONFIG_HOME") {
+        return Some(PathBuf::from(dir).join("saphire"));
+    }
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(".config").join("saphire"))
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sections_in_order() {
+        let config = Config::parse(
+            "# a comment\n[keys]\nctrl+s = save\n  alt+x=quit  \n\n[settings]\ntabs = 4\n",
+        );
+        assert!(config.errors.is_empty());
+        assert_eq!(
+            config.section("keys"),
+            [
+                ("ctrl+s".to_string(), "save".to_string()),
+                ("alt+x".to_string(), "quit".to_string())
+            ]
+        );
+        assert_eq!(config.get("settings", "tabs"), Some("4"));
+        assert!(config.section("missing").is_empty());
+    }
+    #[test]
+    fn merges_repeated_sections_and_keeps_the_last_value() {
+        let config = Config::parse("[a]\nx = 1\n[b]\ny = 2\n[a]\nx = 3\n");
+        assert_eq!(config.sections.len(), 2);
+        assert_eq!(config.section("a").len(), 2);
+        assert_eq!(config.get("a", "x"), Some("3"));
+    }
+    #[test]
+    fn entries_before_a_header_have_no_section() {
+        let config = Config::parse("x = 1\n");
+        assert_eq!(config.get("", "x"), Some("1"));
+    }
+    #[test]
+    fn values_keep_later_equals_signs() {
+        let config = Config::parse("[format]\nrust = A=1 rustfmt\n");
+        assert_eq!(config.get("format", "rust"), Some("A=1 rustfmt"));
+    }
+    #[test]
+    fn reports_lines_that_are_not_entries() {
+        let config = Config::parse("[a]\nx = 1\njunk\n");
+        assert_eq!(config.errors, vec!["config line 3: expected key = value"]);
+        assert_eq!(config.get("a", "x"), Some("1"));
+    }
+}
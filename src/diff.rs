@@ -0,0 +1,140 @@
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Change {
+    Same,
+    Added,
+    Removed,
+}
+//Beyond this many lines in the changed middle part, lines are shown as all removed then all added.
+const MAX_TABLE: usize = 4_000_000;
+
+//A line diff from `old` to `new` using the longest common subsequence.
+#[must_use]
+pub fn diff<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<(Change, &'a str)> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+    let mut result: Vec<(Change, &str)> =
+        old[..prefix].iter().map(|l| (Change::Same, *l)).collect();
+    let (n, m) = (old_middle.len(), new_middle.len());
+    if n * m > MAX_TABLE {
+        result.extend(old_middle.iter().map(|l| (Change::Removed, *l)));
+        result.extend(new_middle.iter().map(|l| (Change::Added, *l)));
+    } else {
+        //lengths[i][j] is the LCS length of old_middle[i..] and new_middle[j..]
+        let mut lengths = vec![vec![0_usize; m + 1]; n + 1];
+        for i in (0..n).rev() {
+            for j in (0..m).rev() {
+                lengths[i][j] = if old_middle[i] == new_middle[j] {
+                    lengths[i + 1][j + 1] + 1
+                } else {
+                    lengths[i + 1][j].max(lengths[i][j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < n || j < m {
+            if i < n && j < m && old_middle[i] == new_middle[j] {
+                result.push((Change::Same, old_middle[i]));
+                i += 1;
+                j += 1;
+            } else if j < m && (i == n || lengths[i][j + 1] >= lengths[i + 1][j]) {
+                result.push((Change::Added, new_middle[j]));
+                j += 1;
+            } else {
+                result.push((Change::Removed, old_middle[i]));
+                i += 1;
+            }
+        }
+    }
+    result.extend(old[old.len() - suffix..].iter().map(|l| (Change::Same, *l)));
+    result
+}
+//The diff as text lines prefixed with ' ', '+' or '-'.
+#[must_use]
+pub fn render(old: &str, new: &str) -> Vec<String> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    diff(&old, &new)
+        .into_iter()
+        .map(|(change, line)| {
+            let sign = match change {
+                Change::Same => ' ',
+                Change::Added => '+',
+                Change::Removed => '-',
+            };
+            format!("{}{}", sign, line)
+        })
+        .collect()
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //The old and new sides the diff was made from, read back out of it.
+    fn sides<'a>(diff: &[(Change, &'a str)]) -> (Vec<&'a str>, Vec<&'a str>) {
+        let old = diff
+            .iter()
+            .filter(|(change, _)| *change != Change::Added)
+            .map(|(_, line)| *line)
+            .collect();
+        let new = diff
+            .iter()
+            .filter(|(change, _)| *change != Change::Removed)
+            .map(|(_, line)| *line)
+            .collect();
+        (old, new)
+    }
+    fn same(diff: &[(Change, &str)]) -> usize {
+        diff.iter()
+            .filter(|(change, _)| *change == Change::Same)
+            .count()
+    }
+    #[test]
+    fn equal_sides_are_all_the_same() {
+        let lines = ["a", "b"];
+        assert_eq!(
+            diff(&lines, &lines),
+            vec![(Change::Same, "a"), (Change::Same, "b")]
+        );
+        assert!(diff(&[], &[]).is_empty());
+    }
+    #[test]
+    fn finds_additions_and_removals() {
+        assert_eq!(
+            diff(&["a", "b", "c"], &["a", "c", "d"]),
+            vec![
+                (Change::Same, "a"),
+                (Change::Removed, "b"),
+                (Change::Same, "c"),
+                (Change::Added, "d")
+            ]
+        );
+        assert_eq!(diff(&[], &["x"]), vec![(Change::Added, "x")]);
+        assert_eq!(diff(&["x"], &[]), vec![(Change::Removed, "x")]);
+    }
+    #[test]
+    fn keeps_the_longest_common_subsequence() {
+        let old = ["a", "b", "c", "a", "b", "b", "a"];
+        let new = ["c", "b", "a", "b", "a", "c"];
+        let result = diff(&old, &new);
+        assert_eq!(sides(&result), (old.to_vec(), new.to_vec()));
+        assert_eq!(same(&result), 4);
+    }
+    #[test]
+    fn repeated_lines_at_the_edges() {
+        let result = diff(&["a"], &["a", "a"]);
+        assert_eq!(sides(&result), (vec!["a"], vec!["a", "a"]));
+        assert_eq!(same(&result), 1);
+    }
+    #[test]
+    fn renders_with_signs() {
+        assert_eq!(render("a\nb\n", "a\n"), vec![" a", "-b"]);
+        assert_eq!(render("b\n", "a\nb"), vec!["+a", " b"]);
+    }
+}
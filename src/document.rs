@@ -1,13 +1,80 @@
-use std::{fs::read_to_string, io::Write, path::PathBuf};
+use std::{
+    collections::hash_map::DefaultHasher,
+    fs::{self, read_to_string},
+    hash::{Hash, Hasher},
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering as AtomicOrdering},
+    time::SystemTime,
+};
 
+use regex::Regex;
 use unicode_segmentation::UnicodeSegmentation;
 
-use crate::{file_type::FileType, row::Row, Position};
+use crate::{file_type::FileType, highlight::Type, row::Row, Position};
 
+const BRACKETS: [(&str, &str); 3] = [("(", ")"), ("[", "]"), ("{", "}")];
+//Whether the grapheme at `x` is part of a string or comment. Brackets there
+//don't count towards the balance.
+#[must_use]
+pub fn quoted(row: &Row, x: usize) -> bool {
+    matches!(row.highlighting.get(x), Some(Type::String | Type::Comment))
+}
 pub struct Document {
     pub rows: Vec<Row>,
     pub path: Option<PathBuf>,
     pub file_type: FileType,
+    pub disk: Option<DiskState>,
+    //changes with every edit, for what's worked out from the rows to be kept until then
+    revision: usize,
+    //unbalanced_brackets as of the last edit, worked out when first asked for
+    brackets: Option<Vec<Position>>,
+}
+//Revisions are counted across documents, so one document's can't be taken for another's.
+static REVISIONS: AtomicUsize = AtomicUsize::new(0);
+fn next_revision() -> usize {
+    REVISIONS.fetch_add(1, AtomicOrdering::Relaxed)
+}
+//What the file looked like the last time we read or wrote it.
+#[derive(PartialEq, Clone)]
+pub struct DiskState {
+    pub modified: Option<SystemTime>,
+    pub len: u64,
+    pub hash: u64,
+}
+impl DiskState {
+    #[must_use]
+    pub fn read(path: &Path) -> Option<DiskState> {
+        let metadata = fs::metadata(path).ok()?;
+        let mut hasher = DefaultHasher::new();
+        fs::read(path).ok()?.hash(&mut hasher);
+        Some(DiskState {
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+            hash: hasher.finish(),
+        })
+    }
+    //Only hashes the file again if its size or modification time moved,
+    //so tools that rewrite identical content don't count as a change.
+    fn read_if_touched(path: &Path, known: &Option<DiskState>) -> Option<DiskState> {
+        let metadata = fs::metadata(path).ok();
+        match (known, metadata) {
+            (Some(known), Some(metadata))
+                if metadata.len() == known.len && metadata.modified().ok() == known.modified =>
+            {
+                Some(known.clone())
+            }
+            (_, None) => None,
+            _ => DiskState::read(path).map(|mut state| {
+                if let Some(known) = known {
+                    if state.hash == known.hash {
+                        state = known.clone();
+                    }
+                }
+                state
+            }),
+        }
+    }
 }
 impl Document {
     pub fn highlight(&mut self, word: &Option<String>) {
@@ -15,75 +82,419 @@ impl Document {
         for row in &mut self.rows {
             row.highlight(word, &self.file_type.highlight_ops.clone(), &mut in_comment);
         }
+        self.edited();
+    }
+    //Highlights the rows an edit on row `y` can have changed: it and the rows
+    //either side of it.
+    pub fn highlight_near(&mut self, y: usize) {
+        let mut in_comment = self
+            .rows
+            .get(y.saturating_sub(2))
+            .is_some_and(|row| row.highlighting.first() == Some(&Type::Comment));
+        let options = self.file_type.highlight_ops.clone();
+        let mut changed = false;
+        for row in self.rows.iter_mut().take(y + 1).skip(y.saturating_sub(2)) {
+            let before = std::mem::take(&mut row.highlighting);
+            row.highlight(&None, &options, &mut in_comment);
+            changed |= row.highlighting != before;
+        }
+        //moving the cursor around highlights the same again
+        if changed {
+            self.edited();
+        }
+    }
+    //Drops what was worked out from the rows. Called by everything here that
+    //changes them, and to be called after changing them directly.
+    pub fn edited(&mut self) {
+        self.revision = next_revision();
+        self.brackets = None;
+    }
+    #[must_use]
+    pub fn revision(&self) -> usize {
+        self.revision
     }
     ///# Panics
     ///
-    /// panics if file isn't there
+    /// panics if the file exists but can't be read
     #[must_use]
-    pub fn new(path: Option<PathBuf>) -> Document {
-        let mut rows = Vec::new();
-        let file_path;
-        if let Some(path) = path.clone() {
-            file_path = FileType::from(path.clone());
-            let content = read_to_string(&path).unwrap();
-            for line in content.lines() {
-                rows.push(Row {
-                    content: line
-                        .graphemes(true)
-                        .collect::<Vec<&str>>()
-                        .iter()
-                        .map(|str| (*str).to_string())
-                        .collect(),
-                    highlighting: Vec::new(),
-                });
-            }
-            log::info!("{}", rows.len());
-            for row in &mut rows {
-                row.parse_specials();
-            }
-        } else {
-            file_path = FileType::default();
-            rows = vec![Row {
-                content: Vec::new(),
-                highlighting: Vec::new(),
-            }];
+    pub fn new(path: Option<PathBuf>, tab_width: usize) -> Document {
+        match path {
+            Some(path) => Document::open(path, tab_width).unwrap(),
+            None => Document {
+                rows: vec![Row::new("", tab_width)],
+                path: None,
+                file_type: FileType::default(),
+                disk: None,
+                revision: next_revision(),
+                brackets: None,
+            },
         }
-        Document {
-            rows,
-            path,
-            file_type: file_path,
+    }
+    //A path that doesn't exist yet gives an empty document that will be created on save.
+    ///# Errors
+    ///
+    /// returns the error if the file exists but can't be read
+    pub fn open(path: PathBuf, tab_width: usize) -> Result<Document, std::io::Error> {
+        let content = match read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+        Ok(Document {
+            rows: Document::rows_from(&content, tab_width),
+            file_type: FileType::from(path.clone()),
+            disk: DiskState::read(&path),
+            path: Some(path),
+            revision: next_revision(),
+            brackets: None,
+        })
+    }
+    fn rows_from(content: &str, tab_width: usize) -> Vec<Row> {
+        let mut rows: Vec<Row> = content
+            .lines()
+            .map(|line| Row::new(line, tab_width))
+            .collect();
+        if rows.is_empty() {
+            rows.push(Row::new("", tab_width));
         }
+        rows
+    }
+    //Swaps the whole text for `content`, leaving the path and disk state alone.
+    pub fn set_contents(&mut self, content: &str, tab_width: usize) {
+        self.rows = Document::rows_from(content, tab_width);
+        self.highlight(&None);
     }
     ///# Panics
     ///
     /// panics if file creation fails
-    pub fn save(&self) {
+    pub fn save(&mut self) {
         let path = self.path.clone().unwrap();
-        let mut file = std::fs::File::create(path).unwrap();
-        for row in &self.rows {
-            let mut bit_buffer = String::new();
-            for gr in &row.content {
-                bit_buffer.push_str(gr);
-            }
-            bit_buffer.push('\n');
-            file.write_all(bit_buffer.as_bytes()).unwrap();
-        }
+        let mut file = std::fs::File::create(&path).unwrap();
+        file.write_all(self.contents().as_bytes()).unwrap();
+        self.disk = DiskState::read(&path);
     }
     ///# Panics
     ///
     /// panics if the file creation fails
     pub fn save_as(&mut self, path: String) {
-        let mut file = std::fs::File::create(path.clone()).unwrap();
+        self.path = Some(PathBuf::from(path));
+        self.file_type = FileType::from(self.path.clone().unwrap());
+        self.save();
+    }
+    #[must_use]
+    pub fn contents(&self) -> String {
+        let mut contents = String::new();
         for row in &self.rows {
-            let mut bit_buf = String::new();
             for gr in &row.content {
-                bit_buf.push_str(gr);
+                contents.push_str(gr);
             }
-            bit_buf.push('\n');
-            file.write_all(bit_buf.as_bytes()).unwrap();
+            contents.push('\n');
+        }
+        contents
+    }
+    //True if the file was changed by something else since it was loaded or saved.
+    #[must_use]
+    pub fn changed_on_disk(&self) -> bool {
+        match &self.path {
+            Some(path) => self.disk != DiskState::read_if_touched(path, &self.disk),
+            None => false,
+        }
+    }
+    //The file was there when last read or written and isn't any more.
+    #[must_use]
+    pub fn deleted_on_disk(&self) -> bool {
+        match (&self.path, &self.disk) {
+            (Some(path), Some(_)) => matches!(
+                fs::metadata(path),
+                Err(e) if e.kind() == ErrorKind::NotFound
+            ),
+            _ => false,
+        }
+    }
+    pub fn forget_disk_changes(&mut self) {
+        self.disk = self.path.as_deref().and_then(DiskState::read);
+    }
+    //Runs a regex replacement over the rows start..=end (1-based). A row whose
+    //replacement holds line breaks becomes several. Returns how many rows changed.
+    pub fn substitute(
+        &mut self,
+        (start, end): (usize, usize),
+        regex: &Regex,
+        replacement: &str,
+        global: bool,
+        tab_width: usize,
+    ) -> usize {
+        let mut changed = 0;
+        let mut rows = Vec::new();
+        for row in self.rows.drain(start - 1..end).collect::<Vec<_>>() {
+            let line = row.inner_string();
+            let new = if global {
+                regex.replace_all(&line, replacement)
+            } else {
+                regex.replace(&line, replacement)
+            };
+            if new == line {
+                rows.push(row);
+            } else {
+                rows.extend(new.split('\n').map(|line| Row::new(line, tab_width)));
+                changed += 1;
+            }
+        }
+        self.rows.splice(start - 1..start - 1, rows);
+        self.edited();
+        changed
+    }
+    pub fn sort(&mut self, (start, end): (usize, usize), reverse: bool) {
+        let rows = &mut self.rows[start - 1..end];
+        rows.sort_by_cached_key(Row::inner_string);
+        if reverse {
+            rows.reverse();
+        }
+        self.edited();
+    }
+    //How many characters other than whitespace come before `position`. Formatting
+    //moves whitespace around but leaves this count alone.
+    #[must_use]
+    pub fn non_blank_before(&self, position: &Position) -> usize {
+        let non_blank =
+            |content: &[String]| content.iter().filter(|gr| !gr.trim().is_empty()).count();
+        let above: usize = self.rows[..position.y - 1]
+            .iter()
+            .map(|row| non_blank(&row.content))
+            .sum();
+        let content = &self.rows[position.y - 1].content;
+        above + non_blank(&content[..position.x.min(content.len())])
+    }
+    //The position with `count` characters other than whitespace before it: right
+    //after the last of them if `after_previous`, otherwise right before the next one.
+    #[must_use]
+    pub fn non_blank_position(&self, count: usize, after_previous: bool) -> Position {
+        let mut seen = 0;
+        for (y, row) in self.rows.iter().enumerate() {
+            for (x, gr) in row.content.iter().enumerate() {
+                if gr.trim().is_empty() {
+                    continue;
+                }
+                if seen == count && !after_previous {
+                    return Position { x, y: y + 1 };
+                }
+                seen += 1;
+                if seen == count && after_previous {
+                    return Position { x: x + 1, y: y + 1 };
+                }
+            }
+        }
+        let y = self.rows.len();
+        Position {
+            x: self.rows[y - 1].content.len(),
+            y,
         }
-        self.path = Some(PathBuf::from(path));
-        self.file_type = FileType::from(self.path.clone().unwrap());
+    }
+    //The text from `start` up to `end`, with rows joined by newlines.
+    #[must_use]
+    pub fn text(&self, start: &Position, end: &Position) -> String {
+        let mut text = String::new();
+        for y in start.y..=end.y {
+            let content = &self.rows[y - 1].content;
+            let from = if y == start.y { start.x } else { 0 };
+            let to = if y == end.y { end.x } else { content.len() };
+            text.extend(content[from.min(to)..to.min(content.len())].iter().cloned());
+            if y != end.y {
+                text.push('\n');
+            }
+        }
+        text
+    }
+    //The position one character back, wrapping to the end of the row above.
+    #[must_use]
+    pub fn before(&self, position: &Position) -> Option<Position> {
+        if position.x > 0 {
+            Some(Position {
+                x: position.x - 1,
+                y: position.y,
+            })
+        } else if position.y > 1 {
+            Some(Position {
+                x: self.rows[position.y - 2].content.len(),
+                y: position.y - 1,
+            })
+        } else {
+            None
+        }
+    }
+    //The position one character on, wrapping to the start of the row below.
+    #[must_use]
+    pub fn after(&self, position: &Position) -> Option<Position> {
+        if position.x < self.rows[position.y - 1].content.len() {
+            Some(Position {
+                x: position.x + 1,
+                y: position.y,
+            })
+        } else if position.y < self.rows.len() {
+            Some(Position {
+                x: 0,
+                y: position.y + 1,
+            })
+        } else {
+            None
+        }
+    }
+    //The next place `needle` occurs at or after `from`, wrapping around the end.
+    #[must_use]
+    pub fn find(&self, needle: &str, from: &Position) -> Option<(Position, Position)> {
+        let contents = self.contents();
+        let offset: usize = self.rows[..from.y - 1]
+            .iter()
+            .map(|row| row.content.iter().map(String::len).sum::<usize>() + 1)
+            .sum::<usize>()
+            + self.rows[from.y - 1].content[..from.x]
+                .iter()
+                .map(String::len)
+                .sum::<usize>();
+        let byte = contents[offset..]
+            .find(needle)
+            .map(|i| i + offset)
+            .or_else(|| contents.find(needle))?;
+        let position_at = |byte: usize| {
+            let before = &contents[..byte];
+            let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+            Position {
+                x: before[line_start..].graphemes(true).count(),
+                y: before.matches('\n').count() + 1,
+            }
+        };
+        Some((position_at(byte), position_at(byte + needle.len())))
+    }
+    //Replaces the text from `start` up to `end` with `text`, which may hold
+    //newlines. Returns where the new text ends.
+    pub fn replace(&mut self, start: &Position, end: &Position, text: &str) -> Position {
+        self.delete(start, end);
+        let row = &mut self.rows[start.y - 1].content;
+        let tail = row.split_off(start.x.min(row.len()));
+        let mut lines = text.split('\n');
+        row.extend(
+            lines
+                .next()
+                .unwrap_or("")
+                .graphemes(true)
+                .map(str::to_string),
+        );
+        let mut position = Position {
+            x: row.len(),
+            y: start.y,
+        };
+        for line in lines {
+            let content: Vec<String> = line.graphemes(true).map(str::to_string).collect();
+            position = Position {
+                x: content.len(),
+                y: position.y + 1,
+            };
+            self.rows.insert(
+                position.y - 1,
+                Row {
+                    content,
+                    highlighting: Vec::new(),
+                },
+            );
+        }
+        self.rows[position.y - 1].content.extend(tail);
+        self.edited();
+        position
+    }
+    //Removes the text from `start` up to `end`, joining the rows at either side.
+    pub fn delete(&mut self, start: &Position, end: &Position) {
+        let tail: Vec<String> = {
+            let content = &self.rows[end.y - 1].content;
+            content[end.x.min(content.len())..].to_vec()
+        };
+        self.rows.drain(start.y..end.y);
+        let content = &mut self.rows[start.y - 1].content;
+        content.truncate(start.x);
+        content.extend(tail);
+        self.edited();
+    }
+    //The bracket matching the one at `at`, looking across rows. Brackets in strings
+    //and comments are skipped unless the one at `at` is in one too.
+    #[must_use]
+    pub fn matching_bracket(&self, at: &Position) -> Option<Position> {
+        let row = self.rows.get(at.y.checked_sub(1)?)?;
+        let bracket = row.content.get(at.x)?.as_str();
+        let (open, close, forward) = BRACKETS.iter().find_map(|&(open, close)| {
+            if bracket == open {
+                Some((open, close, true))
+            } else if bracket == close {
+                Some((open, close, false))
+            } else {
+                None
+            }
+        })?;
+        let skip_quoted = !quoted(row, at.x);
+        let mut depth = 0;
+        let mut position = at.clone();
+        loop {
+            let row = &self.rows[position.y - 1];
+            let gr = row.content.get(position.x).map(String::as_str);
+            if gr.is_some() && !(skip_quoted && quoted(row, position.x)) {
+                if gr == Some(open) {
+                    depth += if forward { 1 } else { -1 };
+                } else if gr == Some(close) {
+                    depth += if forward { -1 } else { 1 };
+                }
+                if depth == 0 {
+                    return Some(position);
+                }
+            }
+            if forward {
+                if position.x + 1 < row.content.len() {
+                    position.x += 1;
+                } else if position.y < self.rows.len() {
+                    position = Position {
+                        x: 0,
+                        y: position.y + 1,
+                    };
+                } else {
+                    return None;
+                }
+            } else if position.x > 0 {
+                position.x -= 1;
+            } else if position.y > 1 {
+                position.y -= 1;
+                position.x = self.rows[position.y - 1].content.len().saturating_sub(1);
+            } else {
+                return None;
+            }
+        }
+    }
+    //Every bracket outside strings and comments that has no partner, or the wrong one.
+    pub fn unbalanced_brackets(&mut self) -> &[Position] {
+        if self.brackets.is_none() {
+            self.brackets = Some(self.find_unbalanced_brackets());
+        }
+        self.brackets.as_deref().unwrap_or_default()
+    }
+    fn find_unbalanced_brackets(&self) -> Vec<Position> {
+        let mut unbalanced = Vec::new();
+        let mut open: Vec<(Position, &str)> = Vec::new();
+        for (y, row) in self.rows.iter().enumerate() {
+            for (x, gr) in row.content.iter().enumerate() {
+                if quoted(row, x) {
+                    continue;
+                }
+                let position = Position { x, y: y + 1 };
+                if let Some(&(_, close)) = BRACKETS.iter().find(|(open, _)| gr == open) {
+                    open.push((position, close));
+                } else if BRACKETS.iter().any(|(_, close)| gr == close) {
+                    match open.last() {
+                        Some((_, close)) if gr == close => {
+                            open.pop();
+                        }
+                        _ => unbalanced.push(position),
+                    }
+                }
+            }
+        }
+        unbalanced.extend(open.into_iter().map(|(position, _)| position));
+        unbalanced
     }
     #[allow(clippy::must_use_candidate)]
     pub fn search(&self, string: &str) -> Vec<Position> {
@@ -96,3 +507,238 @@ impl Document {
         result
     }
 }
+//Where `position` ends up after the text from `start` to `end` was replaced by
+//text ending at `new_end`. Positions inside the replaced text move to its start.
+#[must_use]
+pub fn shift(
+    position: &Position,
+    start: &Position,
+    end: &Position,
+    new_end: &Position,
+) -> Position {
+    if position < start {
+        position.clone()
+    } else if position < end {
+        start.clone()
+    } else if position.y == end.y {
+        Position {
+            x: new_end.x + position.x - end.x,
+            y: new_end.y,
+        }
+    } else {
+        Position {
+            x: position.x,
+            y: position.y - end.y + new_end.y,
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+
+    fn at(x: usize, y: usize) -> Position {
+        Position { x, y }
+    }
+    fn document(text: &str) -> Document {
+        let mut document = Document::new(None, 4);
+        document.set_contents(text, 4);
+        document
+    }
+    #[test]
+    fn replaces_within_a_row() {
+        let mut document = document("hello world");
+        let end = document.replace(&at(0, 1), &at(5, 1), "bye");
+        assert_eq!(end, at(3, 1));
+        assert_eq!(document.contents(), "bye world\n");
+    }
+    #[test]
+    fn replaces_across_rows() {
+        let mut document = document("one\ntwo\nthree");
+        let end = document.replace(&at(1, 1), &at(2, 3), "X\nY\nZ");
+        assert_eq!(end, at(1, 3));
+        assert_eq!(document.contents(), "oX\nY\nZree\n");
+        let end = document.replace(&at(1, 1), &at(1, 3), "");
+        assert_eq!(end, at(1, 1));
+        assert_eq!(document.contents(), "oree\n");
+    }
+    #[test]
+    fn inserts_counting_graphemes() {
+        let mut document = document("ée");
+        let end = document.replace(&at(1, 1), &at(1, 1), "👍🏽\n");
+        assert_eq!(end, at(0, 2));
+        assert_eq!(document.rows[0].content, vec!["é", "👍🏽"]);
+        assert_eq!(document.text(&at(0, 1), &at(1, 2)), "é👍🏽\ne");
+    }
+    #[test]
+    fn shifts_positions_around_an_edit() {
+        let (start, end, new_end) = (at(2, 1), at(4, 2), at(1, 1));
+        //before the edit stays put
+        assert_eq!(shift(&at(1, 1), &start, &end, &new_end), at(1, 1));
+        //inside it moves to its start
+        assert_eq!(shift(&at(5, 1), &start, &end, &new_end), at(2, 1));
+        assert_eq!(shift(&at(3, 2), &start, &end, &new_end), at(2, 1));
+        //at or after its end on the last row, along the new end
+        assert_eq!(shift(&at(4, 2), &start, &end, &new_end), at(1, 1));
+        assert_eq!(shift(&at(6, 2), &start, &end, &new_end), at(3, 1));
+        //on rows below, up by the rows removed
+        assert_eq!(shift(&at(7, 5), &start, &end, &new_end), at(7, 4));
+    }
+    #[test]
+    fn shifts_positions_after_an_insert() {
+        let (start, new_end) = (at(3, 2), at(2, 4));
+        assert_eq!(shift(&at(3, 2), &start, &start, &new_end), at(2, 4));
+        assert_eq!(shift(&at(5, 2), &start, &start, &new_end), at(4, 4));
+        assert_eq!(shift(&at(0, 3), &start, &start, &new_end), at(0, 5));
+        assert_eq!(shift(&at(2, 2), &start, &start, &new_end), at(2, 2));
+    }
+    #[test]
+    fn finds_from_a_position_and_wraps() {
+        let document = document("ab ab\nxab");
+        assert_eq!(document.find("ab", &at(0, 1)), Some((at(0, 1), at(2, 1))));
+        assert_eq!(document.find("ab", &at(1, 1)), Some((at(3, 1), at(5, 1))));
+        assert_eq!(document.find("ab", &at(4, 1)), Some((at(1, 2), at(3, 2))));
+        assert_eq!(document.find("ab", &at(2, 2)), Some((at(0, 1), at(2, 1))));
+        assert_eq!(document.find("b\nx", &at(0, 1)), Some((at(4, 1), at(1, 2))));
+        assert_eq!(document.find("zz", &at(0, 1)), None);
+    }
+    #[test]
+    fn finds_after_wide_characters() {
+        let document = document("日本 本");
+        assert_eq!(document.find("本", &at(2, 1)), Some((at(3, 1), at(4, 1))));
+    }
+    #[test]
+    fn steps_across_row_ends() {
+        let document = document("ab\nc");
+        assert_eq!(document.before(&at(0, 2)), Some(at(2, 1)));
+        assert_eq!(document.before(&at(0, 1)), None);
+        assert_eq!(document.after(&at(2, 1)), Some(at(0, 2)));
+        assert_eq!(document.after(&at(1, 2)), None);
+    }
+    fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("saphire-{}-{}", name, process::id()))
+    }
+    #[test]
+    fn notices_changes_on_disk_but_not_its_own() {
+        let path = temp_path("changed");
+        fs::write(&path, "text\n").unwrap();
+        let mut document = Document::open(path.clone(), 4).unwrap();
+        assert!(!document.changed_on_disk());
+        document.replace(&at(0, 1), &at(0, 1), "more ");
+        document.save();
+        assert!(!document.changed_on_disk());
+        fs::write(&path, "someone else's text\n").unwrap();
+        assert!(document.changed_on_disk());
+        document.forget_disk_changes();
+        assert!(!document.changed_on_disk());
+        fs::remove_file(&path).unwrap();
+    }
+    #[test]
+    fn notices_a_deleted_file() {
+        let path = temp_path("deleted");
+        fs::write(&path, "text\n").unwrap();
+        let mut document = Document::open(path.clone(), 4).unwrap();
+        assert!(!document.deleted_on_disk());
+        fs::remove_file(&path).unwrap();
+        assert!(document.deleted_on_disk());
+        //the text stays, and once it's written again the file is back
+        assert_eq!(document.contents(), "text\n");
+        document.save();
+        assert!(!document.deleted_on_disk());
+        fs::remove_file(&path).unwrap();
+        document.forget_disk_changes();
+        assert!(!document.deleted_on_disk());
+        assert!(!document.changed_on_disk());
+    }
+    #[test]
+    fn a_new_file_is_not_deleted() {
+        let document = Document::open(temp_path("new"), 4).unwrap();
+        assert!(!document.deleted_on_disk());
+        assert!(!document.changed_on_disk());
+        assert_eq!(document.rows.len(), 1);
+    }
+    fn rust(text: &str) -> Document {
+        let mut document = Document::new(None, 4);
+        document.file_type = FileType::from(PathBuf::from("a.rs"));
+        document.set_contents(text, 4);
+        document
+    }
+    #[test]
+    fn unbalanced_brackets_follow_edits() {
+        let mut document = document("(a]\n{");
+        assert_eq!(
+            document.unbalanced_brackets(),
+            [at(2, 1), at(0, 1), at(0, 2)]
+        );
+        document.replace(&at(2, 1), &at(3, 1), ")");
+        assert_eq!(document.unbalanced_brackets(), [at(0, 2)]);
+        document.replace(&at(1, 2), &at(1, 2), "}");
+        assert!(document.unbalanced_brackets().is_empty());
+        document.delete(&at(0, 1), &at(1, 1));
+        assert_eq!(document.unbalanced_brackets(), [at(1, 1)]);
+    }
+    #[test]
+    fn brackets_in_strings_and_comments_do_not_count() {
+        let mut document = rust("f(\"(\", ']'); // {\n/* [ */ g(]");
+        assert_eq!(document.unbalanced_brackets(), [at(10, 2), at(9, 2)]);
+    }
+    #[test]
+    fn revisions_change_with_every_edit() {
+        let mut document = document("b\na");
+        let other = self::document("b\na");
+        let revision = document.revision();
+        assert_ne!(revision, other.revision());
+        let _ = document.text(&at(0, 1), &at(1, 2));
+        let _ = document.find("a", &at(0, 1));
+        assert_eq!(document.revision(), revision);
+        document.replace(&at(1, 1), &at(1, 1), "c");
+        let replaced = document.revision();
+        assert_ne!(replaced, revision);
+        document.sort((1, 2), false);
+        assert_ne!(document.revision(), replaced);
+    }
+    #[test]
+    fn counts_non_blank_characters() {
+        let document = document("  a b\n\n c");
+        assert_eq!(document.non_blank_before(&at(5, 1)), 2);
+        assert_eq!(document.non_blank_before(&at(2, 3)), 3);
+        assert_eq!(document.non_blank_before(&at(99, 3)), 3);
+        assert_eq!(document.non_blank_position(2, true), at(5, 1));
+        assert_eq!(document.non_blank_position(2, false), at(1, 3));
+        assert_eq!(document.non_blank_position(0, false), at(2, 1));
+        assert_eq!(document.non_blank_position(9, false), at(2, 3));
+    }
+    #[test]
+    fn keeps_a_place_through_reformatting() {
+        let mut document = document("fn f(){let x=1;}");
+        //just after `x`
+        let count = document.non_blank_before(&at(12, 1));
+        document.set_contents("fn f() {\n    let x = 1;\n}\n", 4);
+        assert_eq!(document.rows.len(), 3);
+        assert_eq!(document.non_blank_position(count, true), at(9, 2));
+    }
+    #[test]
+    fn sorts_and_substitutes_row_ranges() {
+        let mut document = document("c\nb\na\nd");
+        document.sort((1, 3), false);
+        assert_eq!(document.contents(), "a\nb\nc\nd\n");
+        document.sort((2, 4), true);
+        assert_eq!(document.contents(), "a\nd\nc\nb\n");
+        let regex = Regex::new("[a-c]").unwrap();
+        assert_eq!(document.substitute((1, 3), &regex, "x", true, 4), 2);
+        assert_eq!(document.contents(), "x\nd\nx\nb\n");
+        assert_eq!(document.substitute((2, 2), &regex, "x", true, 4), 0);
+        let regex = Regex::new("x").unwrap();
+        assert_eq!(document.substitute((3, 4), &regex, "1\n2", false, 4), 1);
+        assert_eq!(document.contents(), "x\nd\n1\n2\nb\n");
+    }
+    #[test]
+    fn matches_brackets_across_rows() {
+        let document = document("f(a[0],\n  b)");
+        assert_eq!(document.matching_bracket(&at(1, 1)), Some(at(3, 2)));
+        assert_eq!(document.matching_bracket(&at(3, 2)), Some(at(1, 1)));
+        assert_eq!(document.matching_bracket(&at(3, 1)), Some(at(5, 1)));
+        assert_eq!(document.matching_bracket(&at(0, 1)), None);
+    }
+}
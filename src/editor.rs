@@ -1,18 +1,128 @@
 use std::{
-    io::{stdout, Write},
+    cmp::Ordering,
+    collections::HashMap,
+    fs,
+    io::{self, stdout, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{self, AtomicBool},
+        mpsc::Sender,
+        Arc,
+    },
+    thread,
     time::{Duration, Instant},
 };
 
+use regex::Regex;
+use serde_json::{json, Value};
 use termion::{
-    color::{self, Bg, Black, Blue, Fg, Reset, White},
-    event::Key,
-    input::TermRead,
+    color::{self, Bg, Black, Blue, Fg, Reset, Rgb, White},
+    event::{Key, MouseButton, MouseEvent},
 };
+use unicode_segmentation::UnicodeSegmentation;
 
 use crate::{
-    document::Document, highlight::Type, row::Row, terminal::Terminal, Position, StatusMessage,
+    command::{Command, COMMANDS},
+    completion::{self, Completion},
+    config::Config,
+    diff,
+    document::{self, Document},
+    events::{Event, Events, Job, KeyPress},
+    ex::{self, Ex},
+    explorer::Explorer,
+    file_type::FileType,
+    fold::Folds,
+    highlight::Type,
+    history::History,
+    input::{Completer, InputEvent, LineInput},
+    keymap::{keys_to_string, Keymap, Lookup},
+    lsp::{self, Client, Location, Message, Request, Severity, TextEdit},
+    picker::{self, Picker, PickerItem},
+    popup::Popup,
+    row::Row,
+    settings::Settings,
+    shell,
+    snippet::{self, Session, Snippet},
+    swap::{self, Swap},
+    terminal::Terminal,
+    undo::{EditKind, Snapshot, Undo},
+    viewer::Viewer,
+    workspace::{self, Hit, LineChange},
+    Position, StatusMessage,
 };
 
+//How long a status message stays up.
+const MESSAGE_TIME: Duration = Duration::from_secs(5);
+//How often the open file and the explorer are checked for outside changes.
+const POLL_TIME: Duration = Duration::from_secs(1);
+//Clicks closer together than this on the same spot count as a double or triple click.
+const MULTI_CLICK_TIME: Duration = Duration::from_millis(400);
+const WHEEL_LINES: usize = 3;
+const SELECTION: Rgb = Rgb(38, 79, 120);
+const EXTRA_CURSOR: Rgb = Rgb(150, 150, 150);
+const MATCHING_BRACKET: Rgb = Rgb(80, 80, 80);
+const UNBALANCED: Rgb = Rgb(150, 30, 30);
+//Columns between the sidebar and the text for markers such as the fold indicator.
+const GUTTER_WIDTH: u16 = 2;
+const GUTTER: Rgb = Rgb(135, 129, 128);
+//How much of a word has to be typed before completions come up by themselves.
+const AUTO_COMPLETE_AFTER: usize = 2;
+//Narrower than this, documentation beside the completion list isn't worth showing.
+const MIN_DOCUMENTATION_WIDTH: u16 = 20;
+
+//A cursor and its selection; `anchor` is the other end of the selection.
+#[derive(Clone)]
+struct Cursor {
+    position: Position,
+    anchor: Option<Position>,
+}
+impl Cursor {
+    //The text this cursor selects as (start, end), or None if it selects nothing.
+    fn selection(&self) -> Option<(Position, Position)> {
+        let anchor = self.anchor.clone()?;
+        match anchor.cmp(&self.position) {
+            Ordering::Less => Some((anchor, self.position.clone())),
+            Ordering::Greater => Some((self.position.clone(), anchor)),
+            Ordering::Equal => None,
+        }
+    }
+    fn start(&self) -> Position {
+        self.selection()
+            .map_or_else(|| self.position.clone(), |(start, _)| start)
+    }
+}
+//What one cursor's edit does: the text from `start` to `end` becomes `text`,
+//followed by `after`. The cursor ends up between the two.
+struct Change {
+    start: Position,
+    end: Position,
+    text: String,
+    after: String,
+}
+impl Change {
+    fn new(start: Position, end: Position, text: String) -> Change {
+        Change {
+            start,
+            end,
+            text,
+            after: String::new(),
+        }
+    }
+}
+//What becomes of a shell command's output once it comes back.
+enum ShellOutput {
+    //it takes the place of `input`, from `start` to `end`
+    Replace {
+        start: Position,
+        end: Position,
+        input: String,
+    },
+    //it goes in at every cursor
+    Insert,
+    //it is shown in a viewer
+    Show,
+}
 pub struct Editor {
     should_exit: bool,
     terminal: Terminal,
@@ -22,104 +132,378 @@ pub struct Editor {
     message_buffer: Vec<String>,
     message: StatusMessage,
     unsaved_changes: bool,
+    keymap: Keymap,
+    pending_keys: Vec<KeyPress>,
+    recent_commands: Vec<Command>,
+    settings: Settings,
+    histories: HashMap<String, History>,
+    explorer: Option<Explorer>,
+    explorer_focused: bool,
+    last_disk_check: Instant,
+    swap: Swap,
+    events: Events,
+    deferred: Vec<Job>,
+    //the other end of the selection, the cursor being the end that moves
+    selection: Option<Position>,
+    last_click: Option<(Instant, Position)>,
+    clicks: usize,
+    //the column vertical moves try to keep
+    desired_x: Option<usize>,
+    //extra cursors, edited together with the main one
+    cursors: Vec<Cursor>,
+    undo_stack: Undo,
+    //one piece per cursor that copied it
+    clipboard: Vec<String>,
+    folds: Folds,
+    //by file type name; None once a server couldn't start or went away, so it isn't retried
+    language_servers: HashMap<String, Option<Client>>,
+    //the [lsp] config section: file type name = server command
+    server_commands: Vec<(String, String)>,
+    //the [format] config section: file type name = formatter command
+    formatters: Vec<(String, String)>,
+    popup: Option<Popup>,
+    completion: Option<Completion>,
+    //the snippet whose stops are being filled in
+    snippet: Option<Session>,
+    //what each file type's snippet file held, read the first time it's needed
+    snippets: HashMap<String, Vec<Snippet>>,
+    //set to stop the shell command running on a worker
+    shell: Option<Arc<AtomicBool>>,
 }
 impl Editor {
     //RUN
     ///# Panics
     ///
-    /// will panic if something is wrong with the inputted key
+    /// will panic if the terminal can't be written to
     pub fn run(&mut self) {
         Terminal::clear_screen();
         self.document.highlight(&None);
+        self.check_swap();
+        self.lsp_open();
         loop {
+            self.check_disk();
+            self.update_swap();
+            //catches the language server up on edits it only gets whole
+            self.language_server();
+            for job in std::mem::take(&mut self.deferred) {
+                job(self);
+            }
             self.render();
-            let c = Self::get_next_key();
-            if let Ok(key) = c {
-                self.decode_key(key);
-            } else {
-                panic!()
+            match self.events.next(self.next_deadline()) {
+                Some(Event::Key(key)) => self.decode_key(key),
+                Some(Event::Mouse(mouse)) => self.mouse(mouse),
+                Some(Event::Resize) => self.resize(),
+                Some(Event::Signal(signal)) => self.stopped_by(signal),
+                Some(Event::Job(job)) => job(self),
+                Some(Event::Failed(report)) => self.message = StatusMessage::new(report),
+                //a timer ran out, the checks at the top of the loop deal with it
+                None => (),
             }
             if self.should_exit {
-                Terminal::clear_screen();
-                println!("goodbye! \r");
-                Terminal::flush();
+                self.swap.remove();
+                for client in self.language_servers.values_mut().flatten() {
+                    client.shutdown();
+                }
                 break;
             }
         }
     }
-    //STATIC
-    fn get_next_key() -> Result<Key, std::io::Error> {
+    //The next time something has to happen without a key press: a status
+    //message running out, the disk and explorer poll or a swap write.
+    fn next_deadline(&self) -> Option<Instant> {
+        let mut deadlines = Vec::new();
+        if !self.message.message.is_empty() || !self.message_buffer.is_empty() {
+            deadlines.push(self.message.time + MESSAGE_TIME);
+        }
+        if self.document.path.is_some() || self.explorer.is_some() {
+            deadlines.push(self.last_disk_check + POLL_TIME);
+        }
+        if self.unsaved_changes {
+            deadlines.push(self.swap.next_due());
+        }
+        deadlines.into_iter().min()
+    }
+    //Waits for the next key inside prompts and overlays, redrawing on resizes.
+    //Worker results are held back until the main loop is running again.
+    fn next_key(&mut self) -> KeyPress {
         loop {
-            if let Some(key) = std::io::stdin().lock().keys().next() {
-                return key;
+            match self.events.next(None) {
+                Some(Event::Key(key)) => return key,
+                Some(Event::Resize) => self.resize(),
+                Some(Event::Signal(signal)) => self.stopped_by(signal),
+                Some(Event::Job(job)) => self.deferred.push(job),
+                Some(Event::Failed(report)) => self.message = StatusMessage::new(report),
+                Some(Event::Mouse(_)) | None => (),
             }
         }
     }
+    fn stopped_by(&mut self, signal: i32) -> ! {
+        self.emergency_exit(
+            &format!("saphire was stopped by signal {}", signal),
+            128 + signal,
+        )
+    }
+    //Picks up the new window size and redraws straight away.
+    fn resize(&mut self) {
+        if let Ok(size) = termion::terminal_size() {
+            self.terminal.resize(size);
+        }
+        self.scroll();
+        Terminal::clear_screen();
+        self.render();
+    }
+    //For other threads to reach the main loop with.
+    #[must_use]
+    pub fn sender(&self) -> Sender<Event> {
+        self.events.sender()
+    }
+    //Saves a dirty buffer to an emergency file, hands the terminal back, prints
+    //`reason` and exits. Used after a panic or a fatal signal.
+    pub fn emergency_exit(&mut self, reason: &str, code: i32) -> ! {
+        let saved = if self.unsaved_changes {
+            let contents = self.document.contents();
+            self.swap.update(self.document.path.as_deref(), &contents);
+            swap::write_emergency(self.document.path.as_deref(), &contents)
+        } else {
+            self.swap.remove();
+            None
+        };
+        self.terminal.restore();
+        let mut err = std::io::stderr();
+        let _ = writeln!(err, "{}", reason);
+        if self.unsaved_changes {
+            let _ = match saved {
+                Some(file) => writeln!(err, "unsaved changes were written to {}", file.display()),
+                None => writeln!(err, "unsaved changes could not be written anywhere"),
+            };
+        }
+        std::process::exit(code)
+    }
+    //STATIC
     #[allow(clippy::cast_possible_truncation)]
     //RENDERING
     fn render(&mut self) {
         self.draw_rows();
         self.message_bar();
         self.stats_bar();
+        self.draw_popup();
+        self.draw_completion();
         Terminal::move_cursor(1, 1);
         self.render_cursor();
         Terminal::flush();
     }
     #[allow(clippy::cast_possible_truncation)]
     fn render_cursor(&self) {
+        if let (Some(explorer), true) = (&self.explorer, self.explorer_focused) {
+            Terminal::move_cursor(0, explorer.selected_row() as u16);
+            return;
+        }
+        let y = self.cursor_position.y;
+        let screen_y = self.folds.count(self.offset.y + 1, y);
         Terminal::move_cursor(
-            self.cursor_position.x.saturating_sub(self.offset.x) as u16,
-            self.cursor_position.y.saturating_sub(self.offset.y) as u16,
+            self.text_left() + self.cursor_position.x.saturating_sub(self.offset.x) as u16,
+            screen_y as u16,
         );
+        //the wheel can scroll the cursor out of view
+        if y <= self.offset.y || screen_y >= self.terminal.height as usize {
+            print!("{}", termion::cursor::Hide);
+        }
+    }
+    //Draws the popup below the cursor, or above it if there's more room there.
+    fn draw_popup(&mut self) {
+        let size = match &self.popup {
+            Some(popup) => popup.size(),
+            None => return,
+        };
+        let (x, y, room) = self.place_popup(&self.cursor_position, size);
+        if let Some(popup) = &mut self.popup {
+            popup.draw(x, y, self.terminal.width - x, room);
+        }
+    }
+    //Where a box of `width` by `height` goes for the text at `at`: its top left
+    //corner and the rows it may take.
+    #[allow(clippy::cast_possible_truncation)]
+    fn place_popup(&self, at: &Position, (width, height): (u16, u16)) -> (u16, u16, u16) {
+        let screen_y = self.folds.count(self.offset.y + 1, at.y) as u16;
+        let below = self.terminal.height.saturating_sub(screen_y + 1);
+        let (y, room) = if below >= height || below >= screen_y {
+            (screen_y + 1, below)
+        } else {
+            let room = screen_y.saturating_sub(1);
+            (screen_y - height.min(room), room)
+        };
+        let x = (self.text_left() + at.x.saturating_sub(self.offset.x) as u16)
+            .min(self.terminal.width.saturating_sub(width));
+        (x, y, room)
+    }
+    //The completion list under the text being completed, with the selected
+    //item's documentation beside it when there's room.
+    fn draw_completion(&mut self) {
+        let (start, size, documentation) = match &self.completion {
+            Some(completion) if !completion.is_empty() => (
+                completion.start.clone(),
+                completion.list.size(),
+                completion
+                    .selected()
+                    .map(|item| item.documentation.clone())
+                    .unwrap_or_default(),
+            ),
+            _ => return,
+        };
+        let (x, y, room) = self.place_popup(&start, size);
+        let width = self.terminal.width;
+        if let Some(completion) = &mut self.completion {
+            completion.list.draw(x, y, width - x, room);
+        }
+        let beside = x + size.0;
+        if !documentation.is_empty() && width.saturating_sub(beside) >= MIN_DOCUMENTATION_WIDTH {
+            Popup::new(documentation).draw(beside, y, width - beside, room);
+        }
+    }
+    //The columns left of the document that belong to the sidebar.
+    fn sidebar_width(&self) -> u16 {
+        self.explorer.as_ref().map_or(0, |e| e.width)
+    }
+    fn text_left(&self) -> u16 {
+        self.sidebar_width() + GUTTER_WIDTH
+    }
+    fn text_width(&self) -> u16 {
+        self.terminal.width.saturating_sub(self.text_left())
     }
     pub fn draw_rows(&mut self) {
         print!("{}", termion::cursor::Hide);
+        Terminal::move_cursor(0, 0);
+        if let Some(explorer) = &mut self.explorer {
+            explorer.refresh_if_changed();
+        }
+        let text_width = self.text_width() as usize;
+        let selection = self.selection();
+        let extras = &self.cursors;
+        let brackets = self.bracket_pair();
+        let unbalanced = self.document.unbalanced_brackets().to_vec();
+        let mut next = self.folds.visible_from(self.offset.y + 1);
         for row in 0..self.terminal.height as usize {
             Terminal::clear_row();
+            if let Some(explorer) = &mut self.explorer {
+                let height = self.terminal.height as usize;
+                print!("\r{}", explorer.row(row, height, self.explorer_focused));
+            }
             if row == 0 {
                 self.welcome();
-            } else if (1..=self.document.rows.len()).contains(&(row + self.offset.y)) {
-                if self.document.rows[row + self.offset.y - 1]
-                    .content
-                    .is_empty()
-                {
-                    println!();
+            } else if next <= self.document.rows.len() {
+                let y = next;
+                next = self.folds.visible_from(y + 1);
+                let foldable = self.folds.region(&self.document, y).is_some();
+                print!("{}", self.gutter(y, foldable));
+                let marked = |x: usize| {
+                    let here = Position { x, y };
+                    let selected = |selection: Option<&(Position, Position)>| {
+                        selection.is_some_and(|(start, end)| (start..end).contains(&&here))
+                    };
+                    if extras.iter().any(|cursor| cursor.position == here) {
+                        Some(EXTRA_CURSOR)
+                    } else if selected(selection.as_ref())
+                        || extras
+                            .iter()
+                            .any(|cursor| selected(cursor.selection().as_ref()))
+                    {
+                        Some(SELECTION)
+                    } else if brackets.contains(&here) {
+                        Some(MATCHING_BRACKET)
+                    } else if unbalanced.contains(&here) {
+                        Some(UNBALANCED)
+                    } else {
+                        None
+                    }
+                };
+                let len = self.document.rows[y - 1].content.len();
+                //a selected line break shows as one marked space
+                let line_break = match marked(len) {
+                    Some(color) if len + 1 > self.offset.x => {
+                        format!("{} {}", Bg(color), Bg(Reset))
+                    }
+                    _ => String::new(),
+                };
+                if len == 0 {
+                    println!("{}\r", line_break);
                     continue;
                 }
-                let end = if self.document.rows[row + self.offset.y - 1].content.len()
-                    > self.terminal.width as usize + self.offset.x - 2
-                {
-                    self.terminal.width as usize + self.offset.x - 2
+                let end = if len > (text_width + self.offset.x).saturating_sub(2) {
+                    (text_width + self.offset.x).saturating_sub(2)
                 } else {
-                    self.document.rows[row + self.offset.y - 1]
-                        .content
-                        .len()
-                        .saturating_sub(1)
+                    len.saturating_sub(1)
                 };
                 println!(
-                    "{}{}{}\r",
-                    &self.document.rows[row + self.offset.y - 1].to_string(self.offset.x..=end),
+                    "{}{}{}{}\r",
+                    &self.document.rows[y - 1].to_string_marked(self.offset.x..=end, &marked),
                     Fg(Reset),
                     Bg(Reset),
+                    if end + 1 == len {
+                        line_break
+                    } else {
+                        String::new()
+                    },
                 );
             } else {
-                println!("~\r");
+                println!("{}~\r", " ".repeat(GUTTER_WIDTH as usize));
             }
         }
         print!("{}", termion::cursor::Show);
     }
+    //The gutter cells for row `y`: the worst diagnostic on it, then whether a
+    //fold starts there.
+    fn gutter(&self, y: usize, foldable: bool) -> String {
+        let diagnostic = match self
+            .diagnostics()
+            .iter()
+            .filter(|diagnostic| diagnostic.start.line + 1 == y)
+            .map(|diagnostic| diagnostic.severity)
+            .max()
+        {
+            Some(severity) => format!("{}{}", Fg(severity.color()), severity.marker()),
+            None => " ".to_string(),
+        };
+        let fold = if self.folds.is_closed(y) {
+            "▸"
+        } else if foldable {
+            "▾"
+        } else {
+            " "
+        };
+        format!("{}{}{}{}", diagnostic, Fg(GUTTER), fold, Fg(Reset))
+    }
+    //The bracket at or just before the cursor together with its partner,
+    //or nothing if there is no bracket there or it has no partner.
+    fn bracket_pair(&self) -> Vec<Position> {
+        let Position { x, y } = self.cursor_position;
+        let candidates = [Some(x), x.checked_sub(1)];
+        for x in candidates.into_iter().flatten() {
+            let at = Position { x, y };
+            if let Some(partner) = self.document.matching_bracket(&at) {
+                return vec![at, partner];
+            }
+        }
+        Vec::new()
+    }
 
     //DECODE KEYS
     fn scroll(&mut self) {
         let Position { x, y } = self.cursor_position;
-        let mut off = &mut self.offset;
-        let width = self.terminal.width as usize;
+        let width = self.text_width() as usize;
+        //whatever the cursor lands on can't stay folded away
+        self.folds.reveal(y);
         let height = self.terminal.height as usize;
-        if y <= off.y {
-            off.y = y - 1;
-        } else if y >= off.y.saturating_add(height) {
-            off.y = y.saturating_sub(height).saturating_add(1);
+        if y <= self.offset.y {
+            self.offset.y = y - 1;
+        } else if self.folds.count(self.offset.y + 1, y) >= height {
+            //the screen starts far enough up that `y` lands on its last row
+            let mut top = y;
+            for _ in 2..height {
+                top = self.folds.previous(top).unwrap_or(top);
+            }
+            self.offset.y = top - 1;
         }
+        let mut off = &mut self.offset;
         if x < off.x {
             off.x = x;
         } else if x >= off.x.saturating_add(width) {
@@ -127,9 +511,13 @@ impl Editor {
         }
     }
     #[allow(clippy::if_same_then_else)]
-    fn ctrl_decode(&mut self, key: char) {
-        match key {
-            'q' => {
+    fn execute(&mut self, command: Command) {
+        if command != Command::CommandPalette {
+            self.recent_commands.retain(|c| *c != command);
+            self.recent_commands.insert(0, command);
+        }
+        match command {
+            Command::Quit => {
                 if !self.unsaved_changes {
                     println!("\r");
                     self.should_exit = true;
@@ -140,23 +528,9 @@ impl Editor {
                     self.should_exit = true;
                 }
             }
-            's' => {
-                self.document.highlight(&None);
-                match self.document.path {
-                    Some(_) => {
-                        self.unsaved_changes = false;
-                        self.document.save();
-                    }
-                    None => {
-                        if let Some(path) = self.prompt("Save As") {
-                            self.document.save_as(path);
-                            self.unsaved_changes = false;
-                        }
-                    }
-                }
-            }
-            'f' => {
-                let query = self.prompt("Search");
+            Command::Save => self.save(),
+            Command::Search => {
+                let query = self.prompt("Search: ", "search", None);
                 if let Some(string) = query {
                     self.document.highlight(&Some(string.clone()));
                     let finds = self.document.search(&string);
@@ -167,7 +541,7 @@ impl Editor {
                         let mut current = 0;
                         loop {
                             self.cursor_position = finds[current].clone();
-                            let key = Self::get_next_key().unwrap();
+                            let key = self.next_key().key();
                             match key {
                                 Key::Esc => {
                                     self.document.highlight(&None);
@@ -202,279 +576,2577 @@ impl Editor {
                     }
                 }
             }
-            'd' => {
+            Command::InnerWords => {
                 let inner_words = self.document.rows[self.cursor_position.y - 1].get_inner_words();
                 self.message_buffer.push(format!("{:?}", inner_words))
             }
-            'n' => {
-                self.message.time = Instant::now() - Duration::new(5, 0);
+            Command::NextMessage => {
+                self.message.time = Instant::now() - MESSAGE_TIME;
             }
-            _ => (),
-        }
-    }
-    fn delete(&mut self) {
-        if self.cursor_position.x
-            < self.document.rows[self.cursor_position.y - 1]
-                .content
-                .len()
-                .saturating_sub(1)
-        {
-            self.unsaved_changes = true;
-            self.document.rows[self.cursor_position.y - 1]
-                .content
-                .remove(self.cursor_position.x + 1);
-        } else if self.cursor_position.y < self.document.rows.len() {
-            self.unsaved_changes = true;
-            let mut current_row = self.document.rows.remove(self.cursor_position.y).content;
-            self.document.rows[self.cursor_position.y - 1]
-                .content
-                .append(&mut current_row);
-        }
-    }
-    #[allow(
-        clippy::match_same_arms,
-        clippy::cast_possible_truncation,
-        clippy::cast_possible_wrap,
-        clippy::cast_sign_loss
-    )]
-    fn decode_key(&mut self, key: Key) {
-        match key {
-            Key::Ctrl(x) => self.ctrl_decode(x),
-            Key::Backspace => {
-                if self.cursor_position.x > 0 {
-                    self.unsaved_changes = true;
-                    self.document.rows[self.cursor_position.y - 1]
-                        .content
-                        .remove(self.cursor_position.x - 1);
-                    self.cursor_position.x -= 1;
-                } else if self.cursor_position.y > 1 {
-                    self.unsaved_changes = true;
-                    let mut current_row = self
-                        .document
-                        .rows
-                        .remove(self.cursor_position.y - 1)
-                        .content;
-                    self.document.rows[self.cursor_position.y - 2]
-                        .content
-                        .append(&mut current_row);
-                    self.cursor_position.x =
-                        self.document.rows[self.cursor_position.y - 2].content.len();
-                    self.cursor_position.y -= 1;
-                }
-            }
-            Key::Delete => self.delete(),
-            Key::Char('\n') => {
-                self.unsaved_changes = true;
-                if self.cursor_position.x
-                    < self.document.rows[self.cursor_position.y - 1].content.len()
-                {
-                    self.document.rows.insert(
-                        self.cursor_position.y,
-                        Row {
-                            content: (&self.document.rows[self.cursor_position.y - 1].content
-                                [self.cursor_position.x..])
-                                .to_vec(),
-                            highlighting: Vec::new(),
-                        },
-                    );
-                    self.document.rows[self.cursor_position.y - 1].content = self.document.rows
-                        [self.cursor_position.y - 1]
-                        .content[..self.cursor_position.x]
-                        .to_vec();
+            Command::CommandPalette => self.command_palette(),
+            Command::CommandLine => self.command_line(),
+            Command::OpenFile => self.open_file(),
+            Command::Grep => self.grep(),
+            Command::ReplaceInFiles => self.replace_in_files(),
+            Command::ToggleExplorer => {
+                if self.explorer.is_none() {
+                    let width = (self.terminal.width / 4).clamp(16, 40);
+                    let mut explorer =
+                        Explorer::new(PathBuf::from("."), width, &self.settings.ignore);
+                    if let Some(path) = &self.document.path {
+                        explorer.select(&Path::new(".").join(path));
+                    }
+                    self.explorer = Some(explorer);
+                    self.explorer_focused = true;
+                } else if self.explorer_focused {
+                    self.explorer = None;
+                    self.explorer_focused = false;
                 } else {
-                    self.document.rows.insert(
-                        self.cursor_position.y,
-                        Row {
-                            content: Vec::new(),
-                            highlighting: Vec::new(),
-                        },
-                    );
+                    self.explorer_focused = true;
                 }
-                self.cursor_position.y += 1;
-                self.cursor_position.x = 0;
+                self.offset.x = 0;
             }
-            Key::Char('\t') => {
-                self.unsaved_changes = true;
-                for _ in 0..4 {
-                    self.document.rows[self.cursor_position.y - 1]
-                        .content
-                        .insert(self.cursor_position.x, " ".to_string());
+            Command::WordLeft => self.word_left(),
+            Command::WordRight => self.word_right(),
+            Command::ParagraphUp => self.paragraph(false),
+            Command::ParagraphDown => self.paragraph(true),
+            Command::MatchingBracket => self.matching_bracket(),
+            Command::GotoLine => self.goto_line(),
+            Command::Undo => self.undo(false),
+            Command::Redo => self.undo(true),
+            Command::Copy => self.copy(),
+            Command::Cut => self.cut(),
+            Command::Paste => self.paste(),
+            Command::AddCursorAbove => self.add_cursor_vertically(false),
+            Command::AddCursorBelow => self.add_cursor_vertically(true),
+            Command::AddNextOccurrence => self.add_next_occurrence(),
+            Command::CursorsOnLines => self.cursors_on_lines(),
+            Command::Outdent => self.indent_lines(false),
+            Command::ToggleComment => self.toggle_comment(),
+            Command::Fold => self.fold(),
+            Command::Unfold => self.unfold(),
+            Command::ToggleFolds => self.toggle_folds(),
+            Command::Complete => self.open_completion(true),
+            Command::Format => self.format(),
+            Command::Filter => {
+                if let Some(command) = self.prompt("Filter through: ", "shell", None) {
+                    let (start, end) = self.selection().unwrap_or_else(|| self.whole_document());
+                    self.filter(start, end, &command);
                 }
-                self.cursor_position.x += 4;
             }
-            Key::Char(x) => {
-                self.unsaved_changes = true;
-                self.document.rows[self.cursor_position.y - 1]
-                    .content
-                    .insert(self.cursor_position.x, x.to_string());
-                self.cursor_position.x += 1;
+            Command::InsertOutput => {
+                if let Some(command) = self.prompt("Insert output of: ", "shell", None) {
+                    self.insert_output(&command);
+                }
             }
-            Key::Up
-            | Key::Left
-            | Key::Right
-            | Key::Down
-            | Key::PageUp
-            | Key::PageDown
-            | Key::End
-            | Key::Home => self.move_cursor(key),
-            _ => (),
+            Command::ShowOutput => {
+                if let Some(command) = self.prompt("Show output of: ", "shell", None) {
+                    self.show_output(&command);
+                }
+            }
+            Command::Hover => {
+                let params = self.lsp_position();
+                self.lsp_request(Request::Hover, "textDocument/hover", params);
+            }
+            Command::GotoDefinition => {
+                let params = self.lsp_position();
+                self.lsp_request(Request::Definition, "textDocument/definition", params);
+            }
+            Command::FindReferences => {
+                let mut params = self.lsp_position();
+                params["context"] = json!({"includeDeclaration": true});
+                self.lsp_request(Request::References, "textDocument/references", params);
+            }
+            Command::Rename => self.rename(),
+            Command::CodeAction => self.code_action(),
         }
-        let mut in_comment = if let Some(row) = self
-            .document
-            .rows
-            .get(self.cursor_position.y.saturating_sub(2))
-        {
-            if row.highlighting.get(0) == Some(&Type::Comment) {
-                true
-            } else {
-                false
+    }
+    //Handles a key while the sidebar has focus. Returns false for keys the keymap should see.
+    fn explorer_key(&mut self, key: Key) -> bool {
+        let explorer = match &mut self.explorer {
+            Some(explorer) => explorer,
+            None => return false,
+        };
+        match key {
+            Key::Up => explorer.up(),
+            Key::Down => explorer.down(),
+            Key::Right => explorer.expand(),
+            Key::Left => explorer.collapse(),
+            Key::Char('R') => explorer.refresh(),
+            Key::Esc | Key::Char('\t') => self.explorer_focused = false,
+            Key::Char('\n') => {
+                if explorer.selected_is_dir() {
+                    explorer.toggle();
+                } else if let Some(path) = explorer.selected_path().map(Path::to_path_buf) {
+                    if self.is_open(&path) || self.confirm_discard() {
+                        if !self.is_open(&path) {
+                            self.open(path);
+                        }
+                        self.explorer_focused = false;
+                    }
+                }
             }
+            Key::Char('a') => self.explorer_create(),
+            Key::Char('r') => self.explorer_rename(),
+            Key::Char('d') | Key::Delete => self.explorer_delete(),
+            Key::Ctrl(_) | Key::Alt(_) | Key::F(_) => return false,
+            _ => (),
+        }
+        true
+    }
+    fn explorer_create(&mut self) {
+        let dir = match &self.explorer {
+            Some(explorer) => explorer.target_dir(),
+            None => return,
+        };
+        let name = match self.prompt("New file (end with / for a folder): ", "path", None) {
+            Some(name) if !name.is_empty() => name,
+            _ => return,
+        };
+        let path = dir.join(&name);
+        let result = if name.ends_with('/') {
+            fs::create_dir_all(&path)
         } else {
-            false
+            path.parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|()| {
+                    fs::OpenOptions::new()
+                        .write(true)
+                        .create_new(true)
+                        .open(&path)
+                })
+                .map(|_| ())
         };
-        for i in -2..1 {
-            if let Some(row) = self
-                .document
-                .rows
-                .get_mut((self.cursor_position.y as isize).saturating_add(i) as usize)
-            {
-                row.highlight(
-                    &None,
-                    &self.document.file_type.highlight_ops.clone(),
-                    &mut in_comment,
-                );
+        match result {
+            Ok(()) => {
+                if let Some(explorer) = &mut self.explorer {
+                    explorer.refresh();
+                    explorer.select(&path);
+                }
             }
+            Err(e) => self.message = StatusMessage::new(format!("can't create {}: {}", name, e)),
         }
-        Terminal::flush();
-        self.scroll();
     }
-    #[allow(clippy::cast_possible_truncation)]
-    fn prompt(&mut self, query: &str) -> Option<String> {
-        Terminal::move_cursor(1, self.terminal.height + 1);
-        Terminal::clear_row();
-        let mut message = String::new();
-        print!("{}: ", query);
-        stdout().flush().unwrap();
-        loop {
-            let key = Self::get_next_key().unwrap();
-            match key {
-                Key::Esc => return None,
-                Key::Char('\n') => {
-                    break;
-                }
-                Key::Char(x) => {
-                    print!("{}", x);
-                    message.push(x);
-                    stdout().flush().unwrap();
+    fn explorer_rename(&mut self) {
+        let path = match self.explorer.as_ref().and_then(Explorer::selected_path) {
+            Some(path) => path.to_path_buf(),
+            None => return,
+        };
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let new_name = match self.prompt_with("Rename to: ", "path", None, &name) {
+            Some(new_name) if !new_name.is_empty() && new_name != name => new_name,
+            _ => return,
+        };
+        let new_path = path.with_file_name(&new_name);
+        let was_open = self.is_open(&path);
+        match fs::rename(&path, &new_path) {
+            Ok(()) => {
+                if was_open {
+                    self.document.file_type = FileType::from(new_path.clone());
+                    self.document.path = Some(new_path.clone());
                 }
-                Key::Backspace => {
-                    message.pop();
-                    Terminal::move_cursor(message.len() as u16 + 2, self.terminal.height + 1);
-                    print!(" ");
-                    Terminal::move_cursor(message.len() as u16 + 2, self.terminal.height + 1);
-                    stdout().flush().unwrap();
+                if let Some(explorer) = &mut self.explorer {
+                    explorer.refresh();
+                    explorer.select(&new_path);
                 }
-                _ => (),
             }
+            Err(e) => self.message = StatusMessage::new(format!("can't rename {}: {}", name, e)),
         }
-        Terminal::clear_row();
-        Some(message)
     }
-    fn prompt_char(&mut self, query: &str) -> Option<char> {
-        Terminal::move_cursor(1, self.terminal.height + 1);
-        Terminal::clear_row();
-        print!("{}: ", query);
-        stdout().flush().unwrap();
-        loop {
-            let key = Self::get_next_key().unwrap();
-            match key {
-                Key::Esc => {
-                    Terminal::clear_row();
-                    return None;
-                }
-                Key::Char(x) => {
-                    Terminal::clear_row();
-                    return Some(x);
+    fn explorer_delete(&mut self) {
+        let (path, is_dir) = match &self.explorer {
+            Some(explorer) => match explorer.selected_path() {
+                Some(path) => (path.to_path_buf(), explorer.selected_is_dir()),
+                None => return,
+            },
+            None => return,
+        };
+        let question = if is_dir {
+            format!(
+                "Delete the folder {} and everything in it?(y,n)",
+                path.display()
+            )
+        } else {
+            format!("Delete {}?(y,n)", path.display())
+        };
+        if self.prompt_char(&question) != Some('y') {
+            return;
+        }
+        let result = if is_dir {
+            fs::remove_dir_all(&path)
+        } else {
+            fs::remove_file(&path)
+        };
+        match result {
+            Ok(()) => {
+                if let Some(explorer) = &mut self.explorer {
+                    explorer.refresh();
                 }
-                _ => (),
+            }
+            Err(e) => {
+                self.message =
+                    StatusMessage::new(format!("can't delete {}: {}", path.display(), e));
             }
         }
     }
-    //MOVE CURSOR
-    #[allow(clippy::cast_possible_wrap)]
-    fn move_cursor(&mut self, key: Key) {
-        let Position { mut x, mut y } = self.cursor_position;
-        let width = self.document.rows[y - 1].content.len();
-        let height = self.document.rows.len();
-        match key {
-            Key::Up => {
-                if y > 1 {
-                    y = y.saturating_sub(1);
-                }
+    fn grep(&mut self) {
+        let regex = match self.prompt_regex("Grep: ") {
+            Some(regex) => regex,
+            None => return,
+        };
+        //the walk can take a while in a big tree, so it runs on a worker and the
+        //hits come back to the main loop
+        self.message = StatusMessage::new(format!("searching for {}...", regex));
+        let ignore = self.settings.ignore.clone();
+        let sender = self.events.sender();
+        thread::spawn(move || {
+            let hits = workspace::grep(Path::new("."), &ignore, &regex);
+            let job: Job = Box::new(move |editor| editor.show_hits(&regex, &hits));
+            let _ = sender.send(Event::Job(job));
+        });
+    }
+    //Lists the hits of a grep for `regex` by file and goes to the one picked.
+    fn show_hits(&mut self, regex: &Regex, hits: &[Hit]) {
+        self.message = StatusMessage::new(String::new());
+        if hits.is_empty() {
+            self.message = StatusMessage::new(format!("no matches for {}", regex));
+            return;
+        }
+        let mut items = Vec::new();
+        let mut targets = Vec::new();
+        for (i, hit) in hits.iter().enumerate() {
+            if i == 0 || hits[i - 1].path != hit.path {
+                let count = hits[i..].iter().take_while(|h| h.path == hit.path).count();
+                items.push(PickerItem {
+                    label: hit.path.to_string_lossy().to_string(),
+                    detail: format!("{} hits", count),
+                });
+                targets.push(i);
             }
-            Key::Down => {
-                if y < height {
-                    y = y.saturating_add(1);
-                }
+            items.push(PickerItem {
+                label: format!("  {}: {}", hit.line, hit.text.trim()),
+                detail: String::new(),
+            });
+            targets.push(i);
+        }
+        let mut picker = Picker::new(&format!("Grep {}", regex), items);
+        picker.keep_order = true;
+        if let Some(i) = self.pick(picker, None) {
+            let hit = &hits[targets[i]];
+            self.jump_to(&hit.path, hit.line, &hit.text[..hit.byte]);
+        }
+    }
+    fn prompt_regex(&mut self, label: &str) -> Option<Regex> {
+        let pattern = self.prompt(label, "grep", None)?;
+        match Regex::new(&pattern) {
+            Ok(regex) => Some(regex),
+            Err(e) => {
+                self.message = StatusMessage::new(e.to_string());
+                None
             }
-            Key::Left => x = x.saturating_sub(1),
-            Key::Right => {
-                if x < width {
-                    x = x.saturating_add(1);
+        }
+    }
+    fn is_open(&self, path: &Path) -> bool {
+        match &self.document.path {
+            Some(open) => match (open.canonicalize(), path.canonicalize()) {
+                (Ok(a), Ok(b)) => a == b,
+                _ => open == path,
+            },
+            None => false,
+        }
+    }
+    //Moves to `line` of `path`, opening it first if needed. `prefix` is the text before the
+    //target column, so tabs expand the same way they do in the row.
+    fn jump_to(&mut self, path: &Path, line: usize, prefix: &str) {
+        if !self.is_open(path) {
+            if !self.confirm_discard() {
+                return;
+            }
+            self.open(path.to_path_buf());
+        }
+        let y = line.clamp(1, self.document.rows.len());
+        let x = Row::new(prefix, self.settings.tab_width).content.len();
+        self.cursor_position = Position {
+            x: x.min(self.document.rows[y - 1].content.len()),
+            y,
+        };
+    }
+    fn replace_in_files(&mut self) {
+        let regex = match self.prompt_regex("Replace in files: ") {
+            Some(regex) => regex,
+            None => return,
+        };
+        let replacement = match self.prompt("Replace with: ", "replace", None) {
+            Some(replacement) => ex::vim_replacement(&replacement),
+            None => return,
+        };
+        //found and worked out on a worker like grep, and previewed once it's back
+        self.message = StatusMessage::new(format!("searching for {}...", regex));
+        let ignore = self.settings.ignore.clone();
+        let sender = self.events.sender();
+        thread::spawn(move || {
+            let mut paths: Vec<PathBuf> = workspace::grep(Path::new("."), &ignore, &regex)
+                .into_iter()
+                .map(|hit| hit.path)
+                .collect();
+            paths.dedup();
+            let changes: Vec<(PathBuf, Vec<LineChange>)> = paths
+                .into_iter()
+                .filter_map(|path| {
+                    let content = std::fs::read_to_string(&path).ok()?;
+                    let lines = workspace::replacements(&content, &regex, &replacement);
+                    (!lines.is_empty()).then_some((path, lines))
+                })
+                .collect();
+            let job: Job = Box::new(move |editor| {
+                editor.confirm_replacements(&regex, &replacement, &changes);
+            });
+            let _ = sender.send(Event::Job(job));
+        });
+    }
+    //Previews the `changes` a replace in files found and makes them once confirmed.
+    fn confirm_replacements(
+        &mut self,
+        regex: &Regex,
+        replacement: &str,
+        changes: &[(PathBuf, Vec<LineChange>)],
+    ) {
+        self.message = StatusMessage::new(String::new());
+        if changes.is_empty() {
+            self.message = StatusMessage::new("nothing to replace".to_string());
+            return;
+        }
+        let items = changes
+            .iter()
+            .map(|(path, lines)| PickerItem {
+                label: path.to_string_lossy().to_string(),
+                detail: format!("{} lines", lines.len()),
+            })
+            .collect();
+        let preview = |i: usize| {
+            changes[i]
+                .1
+                .iter()
+                .flat_map(|(line, old, new)| {
+                    [
+                        format!("{:>5}- {}", line, old),
+                        format!("{:>5}+ {}", line, new),
+                    ]
+                })
+                .collect()
+        };
+        let title = "Replace preview (Enter to apply)";
+        if self
+            .pick(Picker::new(title, items), Some(&preview))
+            .is_none()
+            || self.prompt_char(&format!("Replace in {} files?(y,n)", changes.len())) != Some('y')
+        {
+            return;
+        }
+        let mut done = 0;
+        let mut problems = Vec::new();
+        for (path, _) in changes {
+            let open = self.is_open(path);
+            if open && self.unsaved_changes {
+                problems.push(format!("{} has unsaved changes", path.display()));
+                continue;
+            }
+            match workspace::replace_in_file(path, regex, replacement) {
+                Ok(()) => {
+                    done += 1;
+                    if open {
+                        self.reload();
+                    }
                 }
+                Err(e) => problems.push(format!("{}: {}", path.display(), e)),
             }
-            Key::Home => x = 0,
-            Key::End => x = width,
-            Key::PageDown => {
-                y = if y.saturating_add(self.terminal.height as usize) < height {
-                    y + self.terminal.height as usize
-                } else {
-                    self.document.rows.len()
+        }
+        self.message = StatusMessage::new(format!("replaced in {} files", done));
+        self.message_buffer.extend(problems);
+    }
+    fn open_file(&mut self) {
+        let root = PathBuf::from(".");
+        let files = workspace::files(&root, &self.settings.ignore);
+        let items = files
+            .iter()
+            .map(|path| PickerItem {
+                label: path.to_string_lossy().to_string(),
+                detail: String::new(),
+            })
+            .collect();
+        let lines = self.terminal.height as usize;
+        let preview = |i: usize| workspace::preview(&files[i], lines);
+        if let Some(i) = self.pick(Picker::new("Open", items), Some(&preview)) {
+            if self.confirm_discard() {
+                self.open(files[i].clone());
+            }
+        }
+    }
+    //True if there are no unsaved changes or the user agrees to lose them.
+    fn confirm_discard(&mut self) -> bool {
+        !self.unsaved_changes
+            || self.prompt_char("This file has unsaved changes, discard them?(y,n)") == Some('y')
+    }
+    fn save(&mut self) {
+        let path = match self.document.path {
+            Some(_) => None,
+            None => match self.prompt("Save As: ", "path", Some(ex::complete_path)) {
+                Some(path) => Some(path),
+                None => return,
+            },
+        };
+        self.write(path);
+    }
+    //Saves the buffer to its file, or as `path`. Overwriting a file that changed
+    //on disk, or some other file that's already there, needs a yes first.
+    fn write(&mut self, path: Option<String>) {
+        self.document.highlight(&None);
+        let other = path.as_ref().map(PathBuf::from).filter(|path| {
+            let own = self.document.path.as_ref().map(fs::canonicalize);
+            !matches!((own, fs::canonicalize(path)), (Some(Ok(own)), Ok(path)) if own == path)
+        });
+        match &other {
+            Some(other) if other.exists() => {
+                let question = format!("{} already exists, overwrite it?(y,n)", other.display());
+                if self.prompt_char(&question) != Some('y') {
+                    return;
                 }
             }
-            Key::PageUp => {
-                y = if y > self.terminal.height as usize {
-                    y - self.terminal.height as usize
-                } else {
-                    1
+            Some(_) => (),
+            None => {
+                if self.document.changed_on_disk() && !self.confirm_overwrite() {
+                    return;
                 }
             }
-            _ => (),
         }
-        if x > self.document.rows[y - 1].content.len() {
-            x = self.document.rows[y - 1].content.len();
+        if self.settings.format_on_save {
+            self.format();
+        }
+        self.unsaved_changes = false;
+        self.undo_stack.mark_saved();
+        match path {
+            Some(path) if other.is_some() => {
+                self.document.save_as(path);
+                self.lsp_open();
+            }
+            _ => {
+                self.document.save();
+                if let Some(client) = self.language_server() {
+                    client.did_save();
+                }
+            }
         }
-        self.cursor_position = Position { x, y }
-    }
-
-    //BARS
-    fn welcome(&self) {
-        Terminal::move_cursor(self.terminal.width / 2 - 1, 0);
-        println!(
-            "{}Welcome to Saphire!{}\r",
-            color::Fg(Blue),
-            color::Fg(Reset)
-        );
     }
-    fn stats_bar(&mut self) {
-        Terminal::clear_row();
-        print!("{}", self.message.message);
-        if Instant::now() - self.message.time > Duration::new(5, 0) {
-            if self.message_buffer.is_empty() {
-                self.message = StatusMessage::new("".to_string());
-            } else {
-                self.message = StatusMessage::new(self.message_buffer.remove(0));
+    fn confirm_overwrite(&mut self) -> bool {
+        loop {
+            match self.prompt_char(
+                "The file changed on disk since it was opened, overwrite it?(y,n,d=diff)",
+            ) {
+                Some('y') => return true,
+                Some('d') => self.show_disk_diff(),
+                _ => return false,
             }
         }
     }
-    fn message_bar(&self) {
-        print!(
+    //Polls the open file for changes made by other programs, at most once a second.
+    fn check_disk(&mut self) {
+        if self.last_disk_check.elapsed() < POLL_TIME {
+            return;
+        }
+        self.last_disk_check = Instant::now();
+        //reloading would empty the buffer, so it's kept as the only copy left
+        if self.document.deleted_on_disk() {
+            self.document.forget_disk_changes();
+            self.unsaved_changes = true;
+            self.undo_stack.mark_unsaved();
+            self.message = StatusMessage::new(
+                "the file was deleted on disk, save to write it again".to_string(),
+            );
+            return;
+        }
+        if !self.document.changed_on_disk() {
+            return;
+        }
+        if !self.unsaved_changes {
+            self.reload();
+            self.message = StatusMessage::new("reloaded, the file changed on disk".to_string());
+            return;
+        }
+        loop {
+            match self
+                .prompt_char("The file changed on disk: (r)eload, (k)eep your changes or (d)iff?")
+            {
+                Some('r') => {
+                    self.reload();
+                    return;
+                }
+                Some('d') => self.show_disk_diff(),
+                Some('k') | None => {
+                    self.document.forget_disk_changes();
+                    self.undo_stack.mark_unsaved();
+                    return;
+                }
+                _ => (),
+            }
+        }
+    }
+    //Keeps the swap file in step with the buffer: written while dirty, gone once clean.
+    fn update_swap(&mut self) {
+        if !self.unsaved_changes {
+            self.swap.remove();
+        } else if self.swap.due() {
+            let contents = self.document.contents();
+            self.swap.update(self.document.path.as_deref(), &contents);
+        }
+    }
+    //Offers to recover unsaved changes an earlier session left behind for the open file.
+    fn check_swap(&mut self) {
+        let found = match self.document.path.as_deref().and_then(Swap::find) {
+            Some(found) => found,
+            None => return,
+        };
+        if found.contents == self.document.contents() {
+            let _ = fs::remove_file(&found.file);
+            return;
+        }
+        let query = if found.running() {
+            format!(
+                "Process {} is editing this file too: (r)estore its changes, (d)iff or (x) discard?",
+                found.pid
+            )
+        } else {
+            "Unsaved changes from a crashed session: (r)estore, (d)iff or (x) discard?".to_string()
+        };
+        loop {
+            match self.prompt_char(&query) {
+                Some('r') => {
+                    self.record_undo(EditKind::Other);
+                    self.folds.clear();
+                    self.document
+                        .set_contents(&found.contents, self.settings.tab_width);
+                    self.lsp_invalidate();
+                    self.unsaved_changes = true;
+                    let _ = fs::remove_file(&found.file);
+                    self.cursor_position.y = self.cursor_position.y.min(self.document.rows.len());
+                    self.message = StatusMessage::new("restored from the swap file".to_string());
+                    return;
+                }
+                Some('d') => {
+                    let lines = diff::render(&self.document.contents(), &found.contents);
+                    self.view(Viewer::new("File (-) against the swap file (+)", lines));
+                }
+                Some('x') => {
+                    let _ = fs::remove_file(&found.file);
+                    return;
+                }
+                None => return,
+                _ => (),
+            }
+        }
+    }
+    fn show_disk_diff(&mut self) {
+        let disk = self
+            .document
+            .path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .unwrap_or_default();
+        let lines = diff::render(&disk, &self.document.contents());
+        self.view(Viewer::new("Disk (-) against your changes (+)", lines));
+    }
+    fn view(&mut self, mut viewer: Viewer) {
+        let height = self.terminal.height;
+        loop {
+            self.render();
+            viewer.draw(0, 0, self.terminal.width, height);
+            Terminal::flush();
+            if !viewer.handle(self.next_key().key(), height as usize) {
+                return;
+            }
+        }
+    }
+    //Loads the file again, keeping the cursor where it was as far as possible.
+    fn reload(&mut self) {
+        if let Some(path) = self.document.path.clone() {
+            let cursor = self.cursor_position.clone();
+            self.open(path);
+            let y = cursor.y.min(self.document.rows.len());
+            let x = cursor.x.min(self.document.rows[y - 1].content.len());
+            self.cursor_position = Position { x, y };
+        }
+    }
+    fn open(&mut self, path: PathBuf) {
+        match Document::open(path, self.settings.tab_width) {
+            Ok(document) => {
+                self.lsp_close();
+                self.completion = None;
+                self.snippet = None;
+                self.swap.remove();
+                self.undo_stack.clear();
+                self.folds.clear();
+                self.cursors.clear();
+                self.selection = None;
+                self.document = document;
+                self.document.highlight(&None);
+                self.cursor_position = Position { x: 0, y: 1 };
+                self.offset = Position { x: 0, y: 0 };
+                self.unsaved_changes = false;
+                self.check_swap();
+                self.lsp_open();
+            }
+            Err(e) => self.message = StatusMessage::new(format!("can't open file: {}", e)),
+        }
+    }
+    fn run_ex(&mut self, command: Ex) {
+        match command {
+            Ex::Goto(line) => {
+                self.cursor_position = Position { x: 0, y: line };
+            }
+            Ex::Write { path, quit } => {
+                match path {
+                    Some(path) => self.write(Some(path)),
+                    None => self.save(),
+                }
+                if quit && !self.unsaved_changes {
+                    self.should_exit = true;
+                }
+            }
+            Ex::Quit { force } => {
+                if force || !self.unsaved_changes {
+                    self.should_exit = true;
+                } else {
+                    self.message =
+                        StatusMessage::new("unsaved changes (add ! to override)".to_string());
+                }
+            }
+            Ex::Edit { path, force } => {
+                if force || !self.unsaved_changes {
+                    self.open(PathBuf::from(path));
+                } else {
+                    self.message =
+                        StatusMessage::new("unsaved changes (add ! to override)".to_string());
+                }
+            }
+            Ex::Substitute {
+                start,
+                end,
+                pattern,
+                replacement,
+                global,
+            } => match Regex::new(&pattern) {
+                Ok(regex) => {
+                    self.record_undo(EditKind::Other);
+                    self.cursors.clear();
+                    let changed = substitute(
+                        &mut self.document,
+                        &mut self.folds,
+                        (start, end),
+                        &regex,
+                        &replacement,
+                        global,
+                        self.settings.tab_width,
+                    );
+                    if changed > 0 {
+                        self.unsaved_changes = true;
+                        self.lsp_invalidate();
+                    }
+                    self.document.highlight(&None);
+                    self.message = StatusMessage::new(format!("{} lines changed", changed));
+                }
+                Err(e) => self.message = StatusMessage::new(e.to_string()),
+            },
+            Ex::Set { option, value } => {
+                if let Err(e) = self.settings.set(&option, value.as_deref()) {
+                    self.message = StatusMessage::new(e);
+                }
+            }
+            Ex::Sort {
+                start,
+                end,
+                reverse,
+            } => {
+                self.record_undo(EditKind::Other);
+                self.cursors.clear();
+                self.folds.clear();
+                self.document.sort((start, end), reverse);
+                self.lsp_invalidate();
+                self.document.highlight(&None);
+                self.unsaved_changes = true;
+            }
+            Ex::Filter {
+                start,
+                end,
+                command,
+            } => {
+                let end = Position {
+                    x: self.document.rows[end - 1].content.len(),
+                    y: end,
+                };
+                self.filter(Position { x: 0, y: start }, end, &command);
+            }
+            Ex::Shell { command } => self.show_output(&command),
+            Ex::Read { command } => self.insert_output(&command),
+        }
+        let row_len = self.document.rows[self.cursor_position.y - 1].content.len();
+        self.cursor_position.x = self.cursor_position.x.min(row_len);
+    }
+    fn command_palette(&mut self) {
+        let mut commands = self.recent_commands.clone();
+        for info in COMMANDS {
+            if info.command != Command::CommandPalette && !commands.contains(&info.command) {
+                commands.push(info.command);
+            }
+        }
+        let items = commands
+            .iter()
+            .map(|command| PickerItem {
+                label: format!("{}: {}", command.name(), command.info().description),
+                detail: self
+                    .keymap
+                    .keys_for(*command)
+                    .map(keys_to_string)
+                    .unwrap_or_default(),
+            })
+            .collect();
+        if let Some(i) = self.pick(Picker::new("Commands", items), None) {
+            self.execute(commands[i]);
+        }
+    }
+    //Feeds a key to the keymap. Returns true if the key was used as (part of) a binding.
+    fn decode_binding(&mut self, key: KeyPress) -> bool {
+        self.pending_keys.push(key);
+        match self.keymap.lookup(&self.pending_keys) {
+            Lookup::Command(command) => {
+                self.pending_keys.clear();
+                self.execute(command);
+                true
+            }
+            Lookup::Prefix => {
+                self.message =
+                    StatusMessage::new(format!("{}-", keys_to_string(&self.pending_keys)));
+                true
+            }
+            Lookup::None => {
+                let chord = self.pending_keys.len() > 1;
+                if chord {
+                    self.message = StatusMessage::new(format!(
+                        "{} is not bound",
+                        keys_to_string(&self.pending_keys)
+                    ));
+                }
+                self.pending_keys.clear();
+                chord
+            }
+        }
+    }
+    #[allow(
+        clippy::match_same_arms,
+        clippy::cast_possible_truncation,
+        clippy::cast_possible_wrap,
+        clippy::cast_sign_loss
+    )]
+    fn decode_key(&mut self, press: KeyPress) {
+        //an unbound modified key does what the key does alone
+        let key = press.key();
+        //any key closes the popup, and Esc does nothing else
+        if self.popup.take().is_some() && key == Key::Esc {
+            return;
+        }
+        if key == Key::Esc {
+            if let Some(cancel) = &self.shell {
+                cancel.store(true, atomic::Ordering::Relaxed);
+                return;
+            }
+        }
+        if press == KeyPress::Key(key) && (self.completion_key(key) || self.snippet_key(key)) {
+            self.highlight_around_cursor();
+            self.scroll();
+            return;
+        }
+        if !matches!(key, Key::Up | Key::Down | Key::PageUp | Key::PageDown) {
+            self.desired_x = None;
+        }
+        if self.explorer_focused && self.pending_keys.is_empty() && self.explorer_key(key) {
+            return;
+        }
+        if self.decode_binding(press) {
+            self.update_completion(key);
+            self.update_snippet();
+            self.scroll();
+            return;
+        }
+        match key {
+            Key::Backspace => {
+                let auto_pairs = self.settings.auto_pairs;
+                self.edit(EditKind::Delete, |document, cursor| {
+                    let (start, mut end) = cursor.selection().or_else(|| {
+                        Some((document.before(&cursor.position)?, cursor.position.clone()))
+                    })?;
+                    //between an empty pair both halves go
+                    let content = &document.rows[end.y - 1].content;
+                    if auto_pairs && cursor.selection().is_none() && start.y == end.y {
+                        let (before, after) = (content.get(start.x), content.get(end.x));
+                        let empty_pair = document
+                            .file_type
+                            .pairs
+                            .iter()
+                            .any(|(open, close)| before == Some(open) && after == Some(close));
+                        if empty_pair {
+                            end.x += 1;
+                        }
+                    }
+                    Some(Change::new(start, end, String::new()))
+                });
+            }
+            Key::Delete => self.edit(EditKind::Delete, |document, cursor| {
+                let (start, end) = cursor.selection().or_else(|| {
+                    Some((cursor.position.clone(), document.after(&cursor.position)?))
+                })?;
+                Some(Change::new(start, end, String::new()))
+            }),
+            Key::Esc => {
+                self.cursors.clear();
+                self.selection = None;
+            }
+            Key::Char('\t')
+                if self
+                    .selection()
+                    .is_some_and(|(start, end)| start.y != end.y) =>
+            {
+                self.indent_lines(true);
+            }
+            Key::Char(c) => self.type_char(c),
+            Key::Up
+            | Key::Left
+            | Key::Right
+            | Key::Down
+            | Key::PageUp
+            | Key::PageDown
+            | Key::End
+            | Key::Home => {
+                self.selection = None;
+                self.undo_stack.break_step();
+                let desired_x = self.desired_x;
+                for i in 0..self.cursors.len() {
+                    std::mem::swap(&mut self.cursor_position, &mut self.cursors[i].position);
+                    self.desired_x = None;
+                    self.move_cursor(key);
+                    std::mem::swap(&mut self.cursor_position, &mut self.cursors[i].position);
+                    self.cursors[i].anchor = None;
+                }
+                self.desired_x = desired_x;
+                self.move_cursor(key);
+                self.merge_cursors();
+            }
+            _ => (),
+        }
+        self.update_completion(key);
+        self.update_snippet();
+        self.highlight_around_cursor();
+        Terminal::flush();
+        self.scroll();
+    }
+    //Highlights the rows an edit at the cursor can have changed.
+    fn highlight_around_cursor(&mut self) {
+        self.document.highlight_near(self.cursor_position.y);
+    }
+    //The main selection as (start, end), or None if nothing is selected.
+    fn selection(&self) -> Option<(Position, Position)> {
+        self.main_cursor().selection()
+    }
+    fn main_cursor(&self) -> Cursor {
+        Cursor {
+            position: self.cursor_position.clone(),
+            anchor: self.selection.clone(),
+        }
+    }
+    //The main cursor first, then the extra ones.
+    fn all_cursors(&self) -> Vec<Cursor> {
+        let mut cursors = vec![self.main_cursor()];
+        cursors.extend(self.cursors.iter().cloned());
+        cursors
+    }
+    fn set_cursors(&mut self, mut cursors: Vec<Cursor>) {
+        let main = cursors.remove(0);
+        self.cursor_position = main.position;
+        self.selection = main.anchor;
+        self.cursors = cursors;
+        self.merge_cursors();
+    }
+    //Drops cursors that ended up on the same spot as another one.
+    fn merge_cursors(&mut self) {
+        let mut seen = vec![self.cursor_position.clone()];
+        self.cursors.retain(|cursor| {
+            if seen.contains(&cursor.position) {
+                false
+            } else {
+                seen.push(cursor.position.clone());
+                true
+            }
+        });
+    }
+    //EDITING
+    //Applies an edit at every cursor as one undo step. For each cursor `edit` gives
+    //the range to replace and the new text, or None to leave it be. Cursors are
+    //edited from the bottom up, and the ones already done are moved along.
+    fn edit<F>(&mut self, kind: EditKind, edit: F)
+    where
+        F: Fn(&Document, &Cursor) -> Option<Change>,
+    {
+        self.record_undo(kind);
+        let mut cursors = self.all_cursors();
+        let mut order: Vec<usize> = (0..cursors.len()).collect();
+        order.sort_by_key(|&i| std::cmp::Reverse(cursors[i].start()));
+        let mut rows_changed = false;
+        for i in order {
+            let change = match edit(&self.document, &cursors[i]) {
+                Some(change) => change,
+                None => continue,
+            };
+            let position = self.apply_change(&mut cursors, &change);
+            rows_changed |= change.start.y != change.end.y
+                || change.text.contains('\n')
+                || change.after.contains('\n');
+            cursors[i] = Cursor {
+                position,
+                anchor: None,
+            };
+        }
+        self.set_cursors(cursors);
+        //decode_key only rehighlights the rows around the main cursor
+        if rows_changed || !self.cursors.is_empty() {
+            self.document.highlight(&None);
+        }
+    }
+    //Makes one change to the document, moving `cursors` along with the text and
+    //keeping the folds and the language server in step. Returns where the
+    //cursor that made it goes.
+    fn apply_change(&mut self, cursors: &mut [Cursor], change: &Change) -> Position {
+        let Change {
+            start,
+            end,
+            text,
+            after,
+        } = change;
+        self.lsp_edit(start, end, &format!("{}{}", text, after));
+        let position = self.document.replace(start, end, text);
+        let new_end = self.document.replace(&position, &position, after);
+        self.folds.edited(start.y, end.y, new_end.y);
+        if let Some(session) = &mut self.snippet {
+            session.edited(start, end, &new_end);
+        }
+        for cursor in cursors {
+            cursor.position = document::shift(&cursor.position, start, end, &new_end);
+            cursor.anchor = cursor
+                .anchor
+                .as_ref()
+                .map(|anchor| document::shift(anchor, start, end, &new_end));
+        }
+        self.unsaved_changes = true;
+        position
+    }
+    //Types `c` at every cursor, over any selection. New lines keep the indentation
+    //of the line they split, one level deeper after an opener, and a closer typed
+    //at the start of a line takes it a level back out.
+    fn type_char(&mut self, c: char) {
+        let unit = " ".repeat(self.settings.tab_width);
+        let auto_pairs = self.settings.auto_pairs;
+        let kind = if c == '\n' {
+            EditKind::Other
+        } else {
+            EditKind::Insert
+        };
+        self.edit(kind, |document, cursor| {
+            let (start, end) = cursor
+                .selection()
+                .unwrap_or_else(|| (cursor.position.clone(), cursor.position.clone()));
+            Some(typed_change(document, start, end, c, &unit, auto_pairs))
+        });
+    }
+    //The rows (1-based, in order) that have a cursor or part of a selection on them.
+    fn cursor_lines(&self) -> Vec<usize> {
+        let mut lines: Vec<usize> = Vec::new();
+        for cursor in self.all_cursors() {
+            let (start, end) = cursor
+                .selection()
+                .unwrap_or_else(|| (cursor.position.clone(), cursor.position.clone()));
+            lines.extend(start.y..=end.y);
+        }
+        lines.sort_unstable();
+        lines.dedup();
+        lines
+    }
+    //Swaps `remove` graphemes at `x` in row `y` for `insert`, moving the cursors on
+    //that row along with the text.
+    fn splice_row(
+        &mut self,
+        cursors: &mut [Cursor],
+        y: usize,
+        x: usize,
+        remove: usize,
+        insert: &str,
+    ) {
+        self.lsp_edit(&Position { x, y }, &Position { x: x + remove, y }, insert);
+        let inserted: Vec<String> = insert.graphemes(true).map(str::to_string).collect();
+        let added = inserted.len();
+        self.document.rows[y - 1]
+            .content
+            .splice(x..x + remove, inserted);
+        if let Some(session) = &mut self.snippet {
+            let (start, end) = (Position { x, y }, Position { x: x + remove, y });
+            session.edited(&start, &end, &Position { x: x + added, y });
+        }
+        let shift = |position: &mut Position| {
+            if position.y == y && position.x > x {
+                position.x = x + added + position.x.saturating_sub(x + remove);
+            }
+        };
+        for cursor in cursors {
+            shift(&mut cursor.position);
+            if let Some(anchor) = &mut cursor.anchor {
+                shift(anchor);
+            }
+        }
+        self.unsaved_changes = true;
+    }
+    //Indents or outdents every line that has a cursor or selection on it by one level.
+    fn indent_lines(&mut self, indent: bool) {
+        self.record_undo(EditKind::Other);
+        self.undo_stack.break_step();
+        let unit = self.settings.tab_width;
+        let mut cursors = self.all_cursors();
+        for y in self.cursor_lines() {
+            let content = &self.document.rows[y - 1].content;
+            if indent && !content.is_empty() {
+                self.splice_row(&mut cursors, y, 0, 0, &" ".repeat(unit));
+            } else if !indent {
+                let spaces = content
+                    .iter()
+                    .take(unit)
+                    .take_while(|gr| *gr == " ")
+                    .count();
+                if spaces > 0 {
+                    self.splice_row(&mut cursors, y, 0, spaces, "");
+                }
+            }
+        }
+        self.document.highlight(&None);
+        self.set_cursors(cursors);
+    }
+    //Comments out the lines with cursors on them with the file type's line comment,
+    //or a block comment around each run of them, and takes the comments off again
+    //if they are all commented already. Markers go at the smallest indentation.
+    fn toggle_comment(&mut self) {
+        let lines = self.cursor_lines();
+        let splices = match comment_splices(&self.document, &lines) {
+            Some(splices) => splices,
+            None => {
+                self.message = StatusMessage::new(format!(
+                    "no comment syntax for {}",
+                    self.document.file_type.name
+                ));
+                return;
+            }
+        };
+        if splices.is_empty() {
+            return;
+        }
+        let mut cursors = self.all_cursors();
+        self.record_undo(EditKind::Other);
+        for (y, x, remove, insert) in splices {
+            self.splice_row(&mut cursors, y, x, remove, &insert);
+        }
+        self.document.highlight(&None);
+        self.set_cursors(cursors);
+    }
+    fn record_undo(&mut self, kind: EditKind) {
+        if self.undo_stack.starts_step(kind) {
+            let snapshot = self.snapshot();
+            self.undo_stack.push(snapshot);
+        }
+    }
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            rows: self
+                .document
+                .rows
+                .iter()
+                .map(|row| row.content.clone())
+                .collect(),
+            cursors: self
+                .all_cursors()
+                .into_iter()
+                .map(|cursor| cursor.position)
+                .collect(),
+        }
+    }
+    fn undo(&mut self, redo: bool) {
+        let current = self.snapshot();
+        let snapshot = if redo {
+            self.undo_stack.redo(current)
+        } else {
+            self.undo_stack.undo(current)
+        };
+        let snapshot = match snapshot {
+            Some(snapshot) => snapshot,
+            None => {
+                let what = if redo { "redo" } else { "undo" };
+                self.message = StatusMessage::new(format!("nothing to {}", what));
+                return;
+            }
+        };
+        self.folds.clear();
+        self.snippet = None;
+        self.document.rows = snapshot
+            .rows
+            .into_iter()
+            .map(|content| Row {
+                content,
+                highlighting: Vec::new(),
+            })
+            .collect();
+        self.document.highlight(&None);
+        let rows = &self.document.rows;
+        let cursors = snapshot
+            .cursors
+            .into_iter()
+            .map(|position| {
+                let y = position.y.clamp(1, rows.len());
+                Cursor {
+                    position: Position {
+                        x: position.x.min(rows[y - 1].content.len()),
+                        y,
+                    },
+                    anchor: None,
+                }
+            })
+            .collect();
+        self.set_cursors(cursors);
+        self.lsp_invalidate();
+        self.unsaved_changes = !self.undo_stack.is_saved();
+    }
+    //Copies each cursor's selection, or its whole line when nothing is selected.
+    fn copy(&mut self) {
+        let mut cursors = self.all_cursors();
+        cursors.sort_by_key(Cursor::start);
+        self.clipboard = cursors
+            .iter()
+            .map(|cursor| match cursor.selection() {
+                Some((start, end)) => self.document.text(&start, &end),
+                None => format!(
+                    "{}\n",
+                    self.document.rows[cursor.position.y - 1].inner_string()
+                ),
+            })
+            .collect();
+    }
+    fn cut(&mut self) {
+        self.copy();
+        self.edit(EditKind::Other, |document, cursor| {
+            if let Some((start, end)) = cursor.selection() {
+                return Some(Change::new(start, end, String::new()));
+            }
+            let y = cursor.position.y;
+            let line = Position { x: 0, y };
+            if y < document.rows.len() {
+                return Some(Change::new(
+                    line,
+                    Position { x: 0, y: y + 1 },
+                    String::new(),
+                ));
+            }
+            let end = Position {
+                x: document.rows[y - 1].content.len(),
+                y,
+            };
+            let start = document.before(&line).unwrap_or(line);
+            Some(Change::new(start, end, String::new()))
+        });
+    }
+    //Pastes at every cursor. With as many pieces as cursors each gets its own,
+    //otherwise they all get everything. Copied lines go in above the cursor's line.
+    fn paste(&mut self) {
+        if self.clipboard.is_empty() {
+            return;
+        }
+        let spaces = " ".repeat(self.settings.tab_width);
+        let pieces: Vec<String> = self
+            .clipboard
+            .iter()
+            .map(|piece| piece.replace('\t', &spaces))
+            .collect();
+        let whole = if pieces.iter().all(|piece| piece.ends_with('\n')) {
+            pieces.concat()
+        } else {
+            pieces.join("\n")
+        };
+        let mut starts: Vec<Position> = self.all_cursors().iter().map(Cursor::start).collect();
+        starts.sort();
+        let spread = pieces.len() > 1 && pieces.len() == starts.len();
+        self.edit(EditKind::Other, |_, cursor| {
+            let text = if spread {
+                let i = starts.iter().position(|start| *start == cursor.start())?;
+                pieces[i].clone()
+            } else {
+                whole.clone()
+            };
+            match cursor.selection() {
+                Some((start, end)) => Some(Change::new(start, end, text)),
+                None if text.ends_with('\n') => {
+                    let start = Position {
+                        x: 0,
+                        y: cursor.position.y,
+                    };
+                    Some(Change::new(start.clone(), start, text))
+                }
+                None => Some(Change::new(
+                    cursor.position.clone(),
+                    cursor.position.clone(),
+                    text,
+                )),
+            }
+        });
+    }
+    fn add_cursor_vertically(&mut self, down: bool) {
+        let positions = self.all_cursors().into_iter().map(|cursor| cursor.position);
+        let edge = if down {
+            positions.max()
+        } else {
+            positions.min()
+        };
+        let edge = match edge {
+            Some(edge) => edge,
+            None => return,
+        };
+        let y = if down { edge.y + 1 } else { edge.y - 1 };
+        if y < 1 || y > self.document.rows.len() {
+            return;
+        }
+        let x = edge.x.min(self.document.rows[y - 1].content.len());
+        self.cursors.push(Cursor {
+            position: Position { x, y },
+            anchor: None,
+        });
+        self.merge_cursors();
+    }
+    //Selects the word under the cursor, or adds a cursor selecting the next
+    //occurrence of the selected text.
+    fn add_next_occurrence(&mut self) {
+        let (start, end) = match self.selection() {
+            Some(selection) => selection,
+            None => {
+                let Position { x, y } = self.cursor_position;
+                let row = &self.document.rows[y - 1];
+                let word = row.word_at(x);
+                if word.is_empty() || row.content[word.start].trim().is_empty() {
+                    return;
+                }
+                self.selection = Some(Position { x: word.start, y });
+                self.cursor_position = Position { x: word.end, y };
+                return;
+            }
+        };
+        let needle = self.document.text(&start, &end);
+        let from = self
+            .cursors
+            .last()
+            .map_or(end, |cursor| cursor.position.clone());
+        match self.document.find(&needle, &from) {
+            Some((start, end))
+                if self
+                    .all_cursors()
+                    .iter()
+                    .all(|cursor| cursor.start() != start) =>
+            {
+                self.cursors.push(Cursor {
+                    position: end,
+                    anchor: Some(start),
+                });
+            }
+            _ => {
+                self.message = StatusMessage::new("every occurrence has a cursor".to_string());
+            }
+        }
+    }
+    //Splits the selection into one cursor at the end of each of its lines.
+    fn cursors_on_lines(&mut self) {
+        let (start, end) = match self.selection() {
+            Some(selection) => selection,
+            None => return,
+        };
+        let mut cursors = Vec::new();
+        for y in start.y..=end.y {
+            //a selection ending at the start of a line doesn't take that line in
+            if y == end.y && end.x == 0 && y > start.y {
+                break;
+            }
+            let x = if y == end.y {
+                end.x
+            } else {
+                self.document.rows[y - 1].content.len()
+            };
+            cursors.push(Cursor {
+                position: Position { x, y },
+                anchor: None,
+            });
+        }
+        self.set_cursors(cursors);
+    }
+    //COMPLETION
+    //Opens the completion popup for the text before the cursor, asking the
+    //language server as well if there is one. `explicit` is for when it was
+    //asked for rather than brought up by typing.
+    fn open_completion(&mut self, explicit: bool) {
+        let Position { x, y } = self.cursor_position;
+        let row = &self.document.rows[y - 1];
+        let (start, directory) = completion::prefix(row, x);
+        let prefix = row.content[start..x].concat();
+        let start = Position { x: start, y };
+        let items = match &directory {
+            Some(directory) => completion::paths(directory),
+            None => {
+                let mut items = completion::words(&self.document, &start);
+                items.extend(completion::keywords(&self.document.file_type));
+                items.extend(completion::snippets(&self.snippets()));
+                items
+            }
+        };
+        let asking = directory.is_none() && self.lsp_complete();
+        let mut completion = Completion::new(start, directory, items, explicit);
+        completion.waiting = asking;
+        completion.refilter(&prefix);
+        if completion.is_empty() && !asking {
+            if explicit {
+                self.message = StatusMessage::new("no completions".to_string());
+            }
+            return;
+        }
+        self.completion = Some(completion);
+    }
+    //Asks the language server for completions at the cursor, without a word
+    //about it if there is none. False if there was no one to ask.
+    fn lsp_complete(&mut self) -> bool {
+        let params = self.lsp_position();
+        match self.language_server() {
+            Some(client) => {
+                client.request(Request::Completion, "textDocument/completion", params);
+                true
+            }
+            None => false,
+        }
+    }
+    //Keys the completion popup takes for itself. Everything else goes on to the
+    //document, and the popup follows along afterwards.
+    fn completion_key(&mut self, key: Key) -> bool {
+        let Position { x, y } = self.cursor_position;
+        let in_snippet = self.snippet.is_some();
+        let completion = match &mut self.completion {
+            Some(completion) => completion,
+            None => return false,
+        };
+        if completion.is_empty() || completion.start.y != y || completion.start.x > x {
+            return false;
+        }
+        match key {
+            Key::Up | Key::Ctrl('p') => {
+                completion.up();
+                completion.chosen = true;
+            }
+            Key::Down | Key::Ctrl('n') => {
+                completion.down();
+                completion.chosen = true;
+            }
+            Key::Esc => self.completion = None,
+            //in a snippet Tab goes on to the next stop unless an item was picked
+            Key::Char('\t') if completion.chosen || !in_snippet => self.accept_completion(),
+            Key::Char('\n') if completion.chosen => self.accept_completion(),
+            _ => return false,
+        }
+        true
+    }
+    //Keeps the completion popup in step after a key: narrowed to the new prefix,
+    //closed once the cursor has left it, or opened once enough of a word is typed.
+    fn update_completion(&mut self, key: Key) {
+        let Position { x, y } = self.cursor_position;
+        let row = &self.document.rows[y - 1];
+        let (start, directory) = completion::prefix(row, x);
+        let prefix = row.content[start..x].concat();
+        if let Some(completion) = &mut self.completion {
+            let same =
+                completion.start == (Position { x: start, y }) && completion.directory == directory;
+            //backing out the whole word closes a popup that came up by itself
+            let wanted = !prefix.is_empty() || completion.chosen || directory.is_some();
+            if same && wanted {
+                completion.refilter(&prefix);
+                if !completion.is_empty() || completion.waiting {
+                    return;
+                }
+            }
+            self.completion = None;
+        }
+        let typed = matches!(key, Key::Char(c) if !c.is_whitespace());
+        let enough = if directory.is_some() {
+            true
+        } else {
+            x - start >= AUTO_COMPLETE_AFTER
+        };
+        if typed && enough && self.settings.auto_complete && self.selection.is_none() {
+            self.open_completion(false);
+        }
+    }
+    //Puts the selected completion in place of the prefix, at every cursor with
+    //the same prefix in front of it.
+    fn accept_completion(&mut self) {
+        let completion = match self.completion.take() {
+            Some(completion) => completion,
+            None => return,
+        };
+        let (insert, is_snippet, replace) = match completion.selected() {
+            Some(item) => (item.insert.clone(), item.snippet, item.replace.clone()),
+            None => return,
+        };
+        //a range from the server holding the cursor, stretched over what was typed since
+        let Position { x, y } = self.cursor_position;
+        let (start, end) = match replace {
+            Some((start, end)) if start.y == y && end.y == y && start.x <= x => {
+                let length = self.document.rows[y - 1].content.len();
+                (start.x, end.x.clamp(x, length))
+            }
+            _ => (completion.start.x, x),
+        };
+        if is_snippet {
+            self.expand_snippet(Position { x: start, y }, Position { x: end, y }, &insert);
+            return;
+        }
+        let content = &self.document.rows[y - 1].content;
+        let prefix = content[start..x].to_vec();
+        let suffix = content[x..end].to_vec();
+        self.edit(EditKind::Other, |document, cursor| {
+            if cursor.selection().is_some() {
+                return None;
+            }
+            let Position { x, y } = cursor.position;
+            let start = x.checked_sub(prefix.len())?;
+            let content = &document.rows[y - 1].content;
+            let before = content.get(start..x)?;
+            let after = content.get(x..x + suffix.len())?;
+            (before == prefix.as_slice() && after == suffix.as_slice()).then(|| {
+                Change::new(
+                    Position { x: start, y },
+                    Position {
+                        x: x + suffix.len(),
+                        y,
+                    },
+                    insert.clone(),
+                )
+            })
+        });
+        self.undo_stack.break_step();
+        //a directory leads on to what's in it
+        if completion.directory.is_some() && insert.ends_with('/') {
+            self.open_completion(false);
+        }
+    }
+    //FORMATTING
+    fn formatter(&self) -> Option<String> {
+        let file_type = &self.document.file_type;
+        self.formatters
+            .iter()
+            .rev()
+            .find(|(name, _)| name.eq_ignore_ascii_case(&file_type.name))
+            .map(|(_, command)| command.clone())
+            .or_else(|| file_type.formatter.clone())
+            .filter(|command| !command.trim().is_empty())
+    }
+    //Swaps the document for what its formatter makes of it, as one undo step,
+    //with the cursor kept on the same text.
+    fn format(&mut self) {
+        let command = match self.formatter() {
+            Some(command) => command,
+            None => {
+                self.message = StatusMessage::new(format!(
+                    "no formatter for {}",
+                    self.document.file_type.name
+                ));
+                return;
+            }
+        };
+        let contents = self.document.contents();
+        let formatted = match shell::pipe(&command, &contents) {
+            Ok(formatted) => formatted,
+            Err(e) => {
+                self.message = StatusMessage::new(format!("formatting failed: {}", e));
+                return;
+            }
+        };
+        if formatted == contents {
+            return;
+        }
+        let Position { x, y } = self.cursor_position;
+        let count = self.document.non_blank_before(&self.cursor_position);
+        let after_previous = x
+            .checked_sub(1)
+            .and_then(|x| self.document.rows[y - 1].content.get(x))
+            .is_some_and(|gr| !gr.trim().is_empty());
+        self.record_undo(EditKind::Other);
+        self.undo_stack.break_step();
+        self.completion = None;
+        self.snippet = None;
+        self.cursors.clear();
+        self.selection = None;
+        self.folds.clear();
+        self.document
+            .set_contents(&formatted, self.settings.tab_width);
+        self.lsp_invalidate();
+        self.unsaved_changes = true;
+        self.cursor_position = self.document.non_blank_position(count, after_previous);
+    }
+    //SHELL
+    fn whole_document(&self) -> (Position, Position) {
+        let y = self.document.rows.len();
+        let end = Position {
+            x: self.document.rows[y - 1].content.len(),
+            y,
+        };
+        (Position { x: 0, y: 1 }, end)
+    }
+    //Starts `command` on `input` on a worker, so a slow one doesn't hold up the
+    //editor, with `then` saying what becomes of its output. Esc stops it. A
+    //line's worth of input goes in with its new line and comes out without it.
+    fn run_shell(&mut self, command: &str, input: &str, then: ShellOutput) {
+        let command = command.trim().to_string();
+        if command.is_empty() {
+            return;
+        }
+        if self.shell.is_some() {
+            self.message = StatusMessage::new("a shell command is still running".to_string());
+            return;
+        }
+        let whole_lines = input.is_empty() || input.ends_with('\n');
+        let fed = if whole_lines {
+            input.to_string()
+        } else {
+            format!("{}\n", input)
+        };
+        let keep_last_line_break = whole_lines && !input.is_empty();
+        let tab = " ".repeat(self.settings.tab_width);
+        let cancel = Arc::new(AtomicBool::new(false));
+        self.shell = Some(Arc::clone(&cancel));
+        self.message = StatusMessage::new(format!("running {}, Esc stops it", command));
+        let sender = self.events.sender();
+        thread::spawn(move || {
+            let result = shell::pipe_until(&command, &fed, &cancel).map(|output| {
+                let output = output.replace('\t', &tab);
+                match output.strip_suffix('\n') {
+                    Some(output) if !keep_last_line_break => output.to_string(),
+                    _ => output,
+                }
+            });
+            let job: Job = Box::new(move |editor| editor.shell_done(&command, result, then));
+            let _ = sender.send(Event::Job(job));
+        });
+    }
+    //Deals with the output of a shell command once it comes back, saying on the
+    //status bar why if the command failed.
+    fn shell_done(&mut self, command: &str, result: Result<String, String>, then: ShellOutput) {
+        self.shell = None;
+        let output = match result {
+            Ok(output) => output,
+            Err(e) => {
+                self.message = StatusMessage::new(format!("{} failed: {}", command, e));
+                return;
+            }
+        };
+        self.message = StatusMessage::new(String::new());
+        match then {
+            ShellOutput::Replace { start, end, input } => {
+                self.replace_with_output(start, end, &input, output);
+            }
+            ShellOutput::Insert => self.insert_text(&output),
+            ShellOutput::Show if output.is_empty() => {
+                self.message = StatusMessage::new(format!("{} printed nothing", command));
+            }
+            ShellOutput::Show => {
+                let lines = output.lines().map(str::to_string).collect();
+                self.view(Viewer::new(&format!("$ {}", command), lines));
+            }
+        }
+    }
+    //Replaces start..end with what `command` prints when given that text, as
+    //one undo step. Nothing changes if the command fails.
+    fn filter(&mut self, start: Position, end: Position, command: &str) {
+        let input = self.document.text(&start, &end);
+        let then = ShellOutput::Replace {
+            start,
+            end,
+            input: input.clone(),
+        };
+        self.run_shell(command, &input, then);
+    }
+    fn replace_with_output(&mut self, start: Position, end: Position, input: &str, output: String) {
+        //the text may have been edited while the command ran
+        let rows = &self.document.rows;
+        let within = |p: &Position| p.y <= rows.len() && p.x <= rows[p.y - 1].content.len();
+        if !within(&start) || !within(&end) || self.document.text(&start, &end) != input {
+            self.message = StatusMessage::new(
+                "the text changed while the command ran, so it was left alone".to_string(),
+            );
+            return;
+        }
+        self.record_undo(EditKind::Other);
+        self.undo_stack.break_step();
+        self.completion = None;
+        self.snippet = None;
+        self.cursors.clear();
+        self.selection = None;
+        let mut cursors = self.all_cursors();
+        self.apply_change(&mut cursors, &Change::new(start.clone(), end, output));
+        self.document.highlight(&None);
+        self.cursor_position = start;
+    }
+    //Puts what `command` prints at every cursor, over any selection.
+    fn insert_output(&mut self, command: &str) {
+        self.run_shell(command, "", ShellOutput::Insert);
+    }
+    fn insert_text(&mut self, text: &str) {
+        self.edit(EditKind::Other, |_, cursor| {
+            let (start, end) = cursor
+                .selection()
+                .unwrap_or_else(|| (cursor.position.clone(), cursor.position.clone()));
+            Some(Change::new(start, end, text.to_string()))
+        });
+        self.undo_stack.break_step();
+        self.document.highlight(&None);
+    }
+    //Shows what `command` prints, given the selection if there is one, in a
+    //viewer that leaves the document alone.
+    fn show_output(&mut self, command: &str) {
+        let input = self
+            .selection()
+            .map(|(start, end)| self.document.text(&start, &end))
+            .unwrap_or_default();
+        self.run_shell(command, &input, ShellOutput::Show);
+    }
+    //SNIPPETS
+    //The snippets for the open document: the user's, then the built-in ones
+    //they don't replace.
+    fn snippets(&mut self) -> Vec<Snippet> {
+        let name = self.document.file_type.name.clone();
+        if !self.snippets.contains_key(&name) {
+            let loaded = snippet::load(&name).unwrap_or_else(|errors| {
+                self.message_buffer.extend(errors);
+                Vec::new()
+            });
+            self.snippets.insert(name.clone(), loaded);
+        }
+        let mut snippets = self.snippets[&name].clone();
+        for built_in in &self.document.file_type.snippets {
+            if !snippets.iter().any(|s| s.prefix == built_in.prefix) {
+                snippets.push(built_in.clone());
+            }
+        }
+        snippets
+    }
+    //Tab and Shift+Tab move between the stops of the snippet being filled in, and
+    //Tab right after a snippet's prefix expands it.
+    fn snippet_key(&mut self, key: Key) -> bool {
+        match key {
+            Key::Char('\t') if self.snippet.is_some() => self.next_stop(true),
+            Key::BackTab if self.snippet.is_some() => self.next_stop(false),
+            Key::Char('\t') if self.selection.is_none() && self.cursors.is_empty() => {
+                let Position { x, y } = self.cursor_position;
+                let row = &self.document.rows[y - 1];
+                let (start, directory) = completion::prefix(row, x);
+                let prefix = row.content[start..x].concat();
+                if directory.is_some() || prefix.is_empty() {
+                    return false;
+                }
+                match self.snippets().into_iter().find(|s| s.prefix == prefix) {
+                    Some(snippet) => {
+                        let end = self.cursor_position.clone();
+                        self.expand_snippet(Position { x: start, y }, end, &snippet.body);
+                    }
+                    None => return false,
+                }
+            }
+            Key::Esc => {
+                self.snippet = None;
+                return false;
+            }
+            _ => return false,
+        }
+        true
+    }
+    //Replaces the text from `start` to `end` with the snippet `body` and starts
+    //on its first stop.
+    fn expand_snippet(&mut self, start: Position, end: Position, body: &str) {
+        let row = &self.document.rows[start.y - 1];
+        let indent = row.content[..row.first_non_blank().min(start.x)].concat();
+        let unit = " ".repeat(self.settings.tab_width);
+        let expansion = snippet::expand(body, &indent, &unit);
+        self.snippet = None;
+        self.record_undo(EditKind::Other);
+        self.cursors.clear();
+        self.selection = None;
+        let mut cursors = self.all_cursors();
+        let change = Change::new(start.clone(), end, expansion.text.clone());
+        self.apply_change(&mut cursors, &change);
+        self.document.highlight(&None);
+        self.snippet = Some(Session::new(&expansion, &start));
+        self.select_stop();
+    }
+    fn next_stop(&mut self, forward: bool) {
+        if let Some(session) = &mut self.snippet {
+            session.step(forward);
+        }
+        self.select_stop();
+    }
+    //Selects the current stop everywhere it appears, so typing fills them all in.
+    //Getting to $0 is the end of the snippet.
+    fn select_stop(&mut self) {
+        let stop = match &self.snippet {
+            Some(session) => session.stop(),
+            None => return,
+        };
+        let last = stop.number == 0;
+        self.completion = None;
+        let cursors = stop
+            .ranges
+            .iter()
+            .map(|(start, end)| Cursor {
+                position: end.clone(),
+                anchor: (start != end).then(|| start.clone()),
+            })
+            .collect();
+        self.undo_stack.break_step();
+        self.set_cursors(cursors);
+        if last {
+            self.snippet = None;
+        }
+    }
+    //Ends the snippet once the cursor leaves the stop being filled in.
+    fn update_snippet(&mut self) {
+        let left = self
+            .snippet
+            .as_ref()
+            .is_some_and(|session| !session.contains(&self.cursor_position));
+        if left {
+            self.snippet = None;
+        }
+    }
+    //LANGUAGE SERVERS
+    //The command that runs the open document's language server, if it has one.
+    fn server_command(&self) -> Option<String> {
+        let file_type = &self.document.file_type;
+        self.server_commands
+            .iter()
+            .rev()
+            .find(|(name, _)| name.eq_ignore_ascii_case(&file_type.name))
+            .map(|(_, command)| command.clone())
+            .or_else(|| file_type.language_server.clone())
+            .filter(|command| !command.trim().is_empty())
+    }
+    //Starts the open document's language server if it isn't running yet, and
+    //hands it the document once it's ready.
+    fn lsp_open(&mut self) {
+        let path = match &self.document.path {
+            Some(path) => path.clone(),
+            None => return,
+        };
+        let name = self.document.file_type.name.clone();
+        if !self.language_servers.contains_key(&name) {
+            let command = match self.server_command() {
+                Some(command) => command,
+                None => return,
+            };
+            let client = match Client::start(&name, &command, self.events.sender()) {
+                Ok(client) => Some(client),
+                //not everyone has every default server installed
+                Err(e)
+                    if e.kind() == io::ErrorKind::NotFound
+                        && self.document.file_type.language_server.as_ref() == Some(&command) =>
+                {
+                    log::info!("no language server for {}: {}", name, e);
+                    None
+                }
+                Err(e) => {
+                    self.message = StatusMessage::new(format!("can't start {}: {}", command, e));
+                    None
+                }
+            };
+            self.language_servers.insert(name.clone(), client);
+        }
+        let uri = lsp::uri(&path);
+        let document = &self.document;
+        if let Some(Some(client)) = self.language_servers.get_mut(&name) {
+            if client.initialized && client.open.as_deref() != Some(uri.as_str()) {
+                client.did_close();
+                client.did_open(&uri, &name.to_lowercase(), &document.contents());
+            }
+        }
+    }
+    fn lsp_close(&mut self) {
+        if let Some(Some(client)) = self.language_servers.get_mut(&self.document.file_type.name) {
+            client.did_close();
+        }
+    }
+    //The language server that has the open document, caught up on its edits.
+    fn language_server(&mut self) -> Option<&mut Client> {
+        let uri = lsp::uri(self.document.path.as_ref()?);
+        let client = self
+            .language_servers
+            .get_mut(&self.document.file_type.name)?
+            .as_mut()?;
+        if client.open.as_deref() != Some(uri.as_str()) {
+            return None;
+        }
+        let document = &self.document;
+        client.sync(|| document.contents());
+        Some(client)
+    }
+    //Tells the language server that start..end is about to become `text`.
+    fn lsp_edit(&mut self, start: &Position, end: &Position, text: &str) {
+        let start = lsp::to_lsp(&self.document, start);
+        let end = lsp::to_lsp(&self.document, end);
+        if let Some(client) = self.language_server() {
+            client.edited(&start, &end, text);
+        }
+    }
+    //For changes to the whole document at once, such as an undo.
+    fn lsp_invalidate(&mut self) {
+        if let Some(client) = self.language_server() {
+            client.invalidate();
+        }
+    }
+    //What the language server reported about the open document.
+    fn diagnostics(&self) -> &[lsp::Diagnostic] {
+        let client = match self.language_servers.get(&self.document.file_type.name) {
+            Some(Some(client)) => client,
+            _ => return &[],
+        };
+        client
+            .open
+            .as_ref()
+            .and_then(|uri| client.diagnostics.get(uri))
+            .map_or(&[], Vec::as_slice)
+    }
+    //The open document and the cursor's place in it, as most requests take them.
+    fn lsp_position(&self) -> Value {
+        let uri = self.document.path.as_deref().map(lsp::uri);
+        json!({
+            "textDocument": {"uri": uri},
+            "position": lsp::to_lsp(&self.document, &self.cursor_position).to_json(),
+        })
+    }
+    //Sends a request about the open document, or says why there's no one to ask.
+    fn lsp_request(&mut self, request: Request, method: &str, params: Value) {
+        let name = self.document.file_type.name.clone();
+        match self.language_server() {
+            Some(client) => client.request(request, method, params),
+            None => {
+                let why = match self.language_servers.get(&name) {
+                    Some(Some(_)) if self.document.path.is_some() => {
+                        "the language server is still starting".to_string()
+                    }
+                    _ => format!("no language server for {}", name),
+                };
+                self.message = StatusMessage::new(why);
+            }
+        }
+    }
+    fn rename(&mut self) {
+        let Position { x, y } = self.cursor_position;
+        let row = &self.document.rows[y - 1];
+        let word = row.content[row.word_at(x)].concat();
+        let name = match self.prompt_with("Rename to: ", "rename", None, word.trim()) {
+            Some(name) if !name.is_empty() => name,
+            _ => return,
+        };
+        let mut params = self.lsp_position();
+        params["newName"] = json!(name);
+        self.lsp_request(Request::Rename, "textDocument/rename", params);
+    }
+    //Asks for the actions that apply to the selection, or the cursor, together
+    //with the diagnostics on its lines.
+    fn code_action(&mut self) {
+        let (start, end) = self
+            .selection()
+            .unwrap_or_else(|| (self.cursor_position.clone(), self.cursor_position.clone()));
+        let diagnostics: Vec<Value> = self
+            .diagnostics()
+            .iter()
+            .filter(|diagnostic| {
+                diagnostic.start.line < end.y && diagnostic.end.line + 1 >= start.y
+            })
+            .map(|diagnostic| diagnostic.raw.clone())
+            .collect();
+        let mut params = self.lsp_position();
+        params["range"] = lsp::range(
+            &lsp::to_lsp(&self.document, &start),
+            &lsp::to_lsp(&self.document, &end),
+        );
+        params["context"] = json!({ "diagnostics": diagnostics });
+        self.lsp_request(Request::CodeAction, "textDocument/codeAction", params);
+    }
+    //A message from the language server for the file type called `server`.
+    pub fn lsp_message(&mut self, server: &str, message: Value) {
+        let client = match self.language_servers.get_mut(server) {
+            Some(Some(client)) => client,
+            _ => return,
+        };
+        match client.receive(message) {
+            Some(Message::Response { request, result }) => match result {
+                Ok(result) => self.lsp_response(server, request, &result),
+                //completions are asked for behind the user's back, so failing is quiet
+                Err(e) if matches!(request, Request::Completion) => {
+                    log::info!("{}: completion failed: {}", server, e);
+                    if let Some(completion) = &mut self.completion {
+                        completion.waiting = false;
+                    }
+                }
+                Err(e) => {
+                    self.message = StatusMessage::new(format!("language server: {}", e));
+                }
+            },
+            Some(Message::Request { id, method, params }) => {
+                let result = self.lsp_server_request(&method, &params);
+                if let Some(Some(client)) = self.language_servers.get_mut(server) {
+                    match result {
+                        Some(result) => client.respond(id, result),
+                        None => client.respond_error(id, -32601, "method not found"),
+                    }
+                }
+            }
+            Some(Message::Notification { method, params }) => match method.as_str() {
+                "textDocument/publishDiagnostics" => {
+                    let (uri, diagnostics) = lsp::diagnostics(&params);
+                    if let Some(Some(client)) = self.language_servers.get_mut(server) {
+                        client.diagnostics.insert(uri, diagnostics);
+                    }
+                }
+                "window/showMessage" => {
+                    let message = params["message"].as_str().unwrap_or_default();
+                    self.message = StatusMessage::new(format!("{}: {}", server, message));
+                }
+                "window/logMessage" => log::info!("{}: {}", server, params["message"]),
+                _ => (),
+            },
+            None => (),
+        }
+    }
+    //The server for `server` stopped, or never got going.
+    pub fn lsp_exited(&mut self, server: &str) {
+        if let Some(entry) = self.language_servers.get_mut(server) {
+            if entry.take().is_some() {
+                self.message =
+                    StatusMessage::new(format!("the {} language server stopped", server));
+            }
+        }
+    }
+    fn lsp_response(&mut self, server: &str, request: Request, result: &Value) {
+        match request {
+            Request::Initialize => {
+                if let Some(Some(client)) = self.language_servers.get_mut(server) {
+                    client.initialized(result);
+                }
+                self.lsp_open();
+            }
+            Request::Hover => {
+                let mut lines = lsp::hover_text(result);
+                while lines.last().is_some_and(|line| line.trim().is_empty()) {
+                    lines.pop();
+                }
+                if lines.is_empty() {
+                    self.message = StatusMessage::new("nothing to show here".to_string());
+                } else {
+                    self.popup = Some(Popup::new(lines));
+                }
+            }
+            Request::Definition => match lsp::locations(result).first() {
+                Some(location) => self.goto_location(location),
+                None => self.message = StatusMessage::new("no definition found".to_string()),
+            },
+            Request::References => self.pick_reference(&lsp::locations(result)),
+            Request::Rename => match self.apply_workspace_edit(result) {
+                Ok(files) => {
+                    self.message = StatusMessage::new(format!("renamed in {} files", files));
+                }
+                Err(e) => self.message = StatusMessage::new(e),
+            },
+            Request::CodeAction => self.pick_code_action(server, result),
+            Request::Completion => {
+                if let Some(completion) = &mut self.completion {
+                    completion.add(lsp::completion_items(&self.document, result));
+                }
+            }
+            Request::Shutdown | Request::ExecuteCommand => (),
+        }
+    }
+    //Answers a request from the server, or None for ones we don't know.
+    fn lsp_server_request(&mut self, method: &str, params: &Value) -> Option<Value> {
+        Some(match method {
+            "workspace/applyEdit" => {
+                let applied = match self.apply_workspace_edit(&params["edit"]) {
+                    Ok(_) => true,
+                    Err(e) => {
+                        self.message = StatusMessage::new(e);
+                        false
+                    }
+                };
+                json!({ "applied": applied })
+            }
+            //no settings to give, so the server's defaults stand
+            "workspace/configuration" => {
+                let items = params["items"].as_array().map_or(0, Vec::len);
+                Value::Array(vec![Value::Null; items])
+            }
+            "window/workDoneProgress/create"
+            | "client/registerCapability"
+            | "client/unregisterCapability"
+            | "window/showMessageRequest" => Value::Null,
+            _ => return None,
+        })
+    }
+    //Moves to `location`, opening its file first if it isn't the open one.
+    fn goto_location(&mut self, location: &Location) {
+        if !self.is_open(&location.path) {
+            if !self.confirm_discard() {
+                return;
+            }
+            self.open(location.path.clone());
+            if !self.is_open(&location.path) {
+                return;
+            }
+        }
+        self.cursors.clear();
+        self.selection = None;
+        self.cursor_position = lsp::from_lsp(&self.document, &location.start);
+        self.scroll();
+    }
+    fn pick_reference(&mut self, locations: &[Location]) {
+        if locations.is_empty() {
+            self.message = StatusMessage::new("no references found".to_string());
+            return;
+        }
+        let items = locations
+            .iter()
+            .map(|location| {
+                let line = location.start.line;
+                let text = if self.is_open(&location.path) {
+                    self.document.rows.get(line).map(Row::inner_string)
+                } else {
+                    fs::read_to_string(&location.path)
+                        .ok()
+                        .and_then(|text| text.lines().nth(line).map(str::to_string))
+                };
+                PickerItem {
+                    label: format!(
+                        "{}:{}: {}",
+                        location.path.display(),
+                        line + 1,
+                        text.unwrap_or_default().trim()
+                    ),
+                    detail: String::new(),
+                }
+            })
+            .collect();
+        let mut picker = Picker::new("References", items);
+        picker.keep_order = true;
+        if let Some(i) = self.pick(picker, None) {
+            self.goto_location(&locations[i]);
+        }
+    }
+    //Offers the actions the server came up with and carries out the picked one:
+    //its edit first, then its command, which the server runs.
+    fn pick_code_action(&mut self, server: &str, result: &Value) {
+        let actions = result.as_array().cloned().unwrap_or_default();
+        if actions.is_empty() {
+            self.message = StatusMessage::new("no code actions here".to_string());
+            return;
+        }
+        let items = actions
+            .iter()
+            .map(|action| PickerItem {
+                label: action["title"].as_str().unwrap_or_default().to_string(),
+                detail: action["kind"].as_str().unwrap_or_default().to_string(),
+            })
+            .collect();
+        let mut picker = Picker::new("Code actions", items);
+        picker.keep_order = true;
+        let action = match self.pick(picker, None) {
+            Some(i) => &actions[i],
+            None => return,
+        };
+        if let Some(edit) = action.get("edit") {
+            if let Err(e) = self.apply_workspace_edit(edit) {
+                self.message = StatusMessage::new(e);
+                return;
+            }
+        }
+        //a bare command has its name in `command`, a code action has a whole command there
+        let command = if action["command"].is_string() {
+            Some(action)
+        } else {
+            action.get("command")
+        };
+        if let (Some(command), Some(Some(client))) =
+            (command, self.language_servers.get_mut(server))
+        {
+            let mut params = json!({ "command": command["command"] });
+            if let Some(arguments) = command.get("arguments") {
+                params["arguments"] = arguments.clone();
+            }
+            client.request(Request::ExecuteCommand, "workspace/executeCommand", params);
+        }
+    }
+    //Applies a workspace edit from a language server: the open document's part as
+    //one undo step, other files straight on disk. Gives how many files changed.
+    fn apply_workspace_edit(&mut self, edit: &Value) -> Result<usize, String> {
+        let files = lsp::workspace_edit(edit);
+        for (path, edits) in &files {
+            if self.is_open(path) {
+                self.apply_text_edits(edits);
+                continue;
+            }
+            let text = fs::read_to_string(path)
+                .map_err(|e| format!("can't read {}: {}", path.display(), e))?;
+            fs::write(path, lsp::apply_edits(&text, edits))
+                .map_err(|e| format!("can't write {}: {}", path.display(), e))?;
+        }
+        Ok(files.len())
+    }
+    fn apply_text_edits(&mut self, edits: &[TextEdit]) {
+        self.record_undo(EditKind::Other);
+        self.undo_stack.break_step();
+        let spaces = " ".repeat(self.settings.tab_width);
+        //from the back, so earlier positions stay right; edits at the same spot go in in order
+        let mut changes: Vec<Change> = edits
+            .iter()
+            .rev()
+            .map(|edit| {
+                Change::new(
+                    lsp::from_lsp(&self.document, &edit.start),
+                    lsp::from_lsp(&self.document, &edit.end),
+                    edit.text.replace('\t', &spaces),
+                )
+            })
+            .collect();
+        changes.sort_by_key(|change| std::cmp::Reverse(change.start.clone()));
+        let mut cursors = self.all_cursors();
+        for change in &changes {
+            self.apply_change(&mut cursors, change);
+        }
+        self.set_cursors(cursors);
+        self.document.highlight(&None);
+    }
+    //MOUSE
+    fn mouse(&mut self, event: MouseEvent) {
+        match event {
+            MouseEvent::Press(MouseButton::WheelUp, _, _) => {
+                let top = self.folds.visible_from(self.offset.y + 1);
+                let top = (0..WHEEL_LINES).fold(top, |y, _| self.folds.previous(y).unwrap_or(y));
+                self.offset.y = top - 1;
+            }
+            MouseEvent::Press(MouseButton::WheelDown, _, _) => {
+                let rows = self.document.rows.len();
+                let top = self.folds.visible_from(self.offset.y + 1).min(rows);
+                let top = (0..WHEEL_LINES).fold(top, |y, _| self.folds.next(y, rows).unwrap_or(y));
+                self.offset.y = top - 1;
+            }
+            //termion reports cells 1-based
+            MouseEvent::Press(MouseButton::Left, x, y) if y <= self.terminal.height => {
+                if x <= self.sidebar_width() {
+                    self.explorer_click(y - 1);
+                } else if x <= self.text_left() {
+                    //the gutter: a click on a fold indicator opens or closes it
+                    if y > 1 {
+                        let position = self.document_position(x - 1, y - 1);
+                        self.toggle_fold(position.y);
+                    }
+                } else {
+                    self.explorer_focused = false;
+                    let position = self.document_position(x - 1, y - 1);
+                    self.click(position);
+                }
+            }
+            MouseEvent::Hold(x, y) if self.selection.is_some() => {
+                self.cursor_position =
+                    self.document_position(x.saturating_sub(1), y.saturating_sub(1));
+                self.scroll();
+            }
+            MouseEvent::Release(_, _) if self.selection.as_ref() == Some(&self.cursor_position) => {
+                self.selection = None;
+            }
+            _ => (),
+        }
+    }
+    //The document position drawn at screen cell (x, y), clamped to the text.
+    fn document_position(&self, x: u16, y: u16) -> Position {
+        let rows = self.document.rows.len();
+        let top = self.folds.visible_from(self.offset.y + 1).min(rows);
+        let y = (1..y).fold(top, |y, _| self.folds.next(y, rows).unwrap_or(y));
+        let x = (x.saturating_sub(self.text_left()) as usize + self.offset.x)
+            .min(self.document.rows[y - 1].content.len());
+        Position { x, y }
+    }
+    //Places the cursor, or selects the word or the line on a double or triple click.
+    fn click(&mut self, position: Position) {
+        self.completion = None;
+        let repeated = matches!(&self.last_click, Some((time, last))
+            if *last == position && time.elapsed() < MULTI_CLICK_TIME);
+        self.clicks = if repeated { self.clicks % 3 + 1 } else { 1 };
+        self.last_click = Some((Instant::now(), position.clone()));
+        self.cursors.clear();
+        self.undo_stack.break_step();
+        self.desired_x = None;
+        let y = position.y;
+        let (anchor, cursor) = match self.clicks {
+            2 => {
+                let word = self.document.rows[y - 1].word_at(position.x);
+                (Position { x: word.start, y }, Position { x: word.end, y })
+            }
+            3 if y < self.document.rows.len() => {
+                (Position { x: 0, y }, Position { x: 0, y: y + 1 })
+            }
+            3 => {
+                let x = self.document.rows[y - 1].content.len();
+                (Position { x: 0, y }, Position { x, y })
+            }
+            _ => (position.clone(), position),
+        };
+        self.selection = Some(anchor);
+        self.cursor_position = cursor;
+        self.scroll();
+    }
+    fn explorer_click(&mut self, row: u16) {
+        if let Some(explorer) = &mut self.explorer {
+            if explorer.select_row(row as usize) {
+                self.explorer_focused = true;
+                self.explorer_key(Key::Char('\n'));
+            }
+        }
+    }
+    //Reads a line on the status row. Each `kind` of prompt keeps its own history.
+    fn prompt(&mut self, label: &str, kind: &str, completer: Option<Completer>) -> Option<String> {
+        self.prompt_with(label, kind, completer, "")
+    }
+    fn prompt_with(
+        &mut self,
+        label: &str,
+        kind: &str,
+        completer: Option<Completer>,
+        initial: &str,
+    ) -> Option<String> {
+        let history = self
+            .histories
+            .entry(kind.to_string())
+            .or_insert_with(|| History::load(kind));
+        let mut input = LineInput::new(history.entries.clone(), completer);
+        input.set_text(initial);
+        loop {
+            let x = input.draw(label, self.terminal.height + 1, self.terminal.width);
+            Terminal::move_cursor(x, self.terminal.height + 1);
+            Terminal::flush();
+            match input.handle(self.next_key()) {
+                InputEvent::Submit(text) => {
+                    Terminal::clear_row();
+                    if let Some(history) = self.histories.get_mut(kind) {
+                        history.push(&text);
+                    }
+                    return Some(text);
+                }
+                InputEvent::Cancel => {
+                    Terminal::clear_row();
+                    return None;
+                }
+                InputEvent::Continue => (),
+            }
+        }
+    }
+    fn command_line(&mut self) {
+        if let Some(input) = self.prompt(":", "command", Some(ex::complete)) {
+            match ex::parse(&input, self.cursor_position.y, self.document.rows.len()) {
+                Ok(command) => self.run_ex(command),
+                Err(e) => self.message = StatusMessage::new(e),
+            }
+        }
+    }
+    #[allow(clippy::cast_possible_truncation)]
+    fn pick(
+        &mut self,
+        mut picker: Picker,
+        preview: Option<&dyn Fn(usize) -> Vec<String>>,
+    ) -> Option<usize> {
+        let (width, height) = if preview.is_some() {
+            (
+                self.terminal.width.saturating_sub(4) / 2,
+                self.terminal.height.saturating_sub(2),
+            )
+        } else {
+            (
+                self.terminal.width.saturating_sub(4).min(80),
+                self.terminal.height.min(16),
+            )
+        };
+        let x = if preview.is_some() {
+            2
+        } else {
+            (self.terminal.width - width) / 2
+        };
+        loop {
+            self.render();
+            picker.draw(x, 1, width, height);
+            if let (Some(preview), Some(i)) = (preview, picker.selection()) {
+                picker::draw_preview(&preview(i), x + width, 1, width, height);
+            }
+            Terminal::move_cursor(x + 2 + picker.query.chars().count() as u16, height);
+            Terminal::flush();
+            match self.next_key().key() {
+                Key::Esc => return None,
+                Key::Char('\n') => return picker.selection(),
+                Key::Up | Key::Ctrl('p') => picker.up(),
+                Key::Down | Key::Ctrl('n') => picker.down(),
+                Key::Backspace => picker.pop(),
+                Key::Char(c) => picker.push(c),
+                _ => (),
+            }
+        }
+    }
+    fn prompt_char(&mut self, query: &str) -> Option<char> {
+        Terminal::move_cursor(1, self.terminal.height + 1);
+        Terminal::clear_row();
+        print!("{}: ", query);
+        stdout().flush().unwrap();
+        loop {
+            let key = self.next_key().key();
+            match key {
+                Key::Esc => {
+                    Terminal::clear_row();
+                    return None;
+                }
+                Key::Char(x) => {
+                    Terminal::clear_row();
+                    return Some(x);
+                }
+                _ => (),
+            }
+        }
+    }
+    //MOVE CURSOR
+    #[allow(clippy::cast_possible_wrap)]
+    fn move_cursor(&mut self, key: Key) {
+        let Position { mut x, mut y } = self.cursor_position;
+        let width = self.document.rows[y - 1].content.len();
+        let height = self.document.rows.len();
+        //up and down aim for the column the cursor was on before it hit a short line
+        if matches!(key, Key::Up | Key::Down | Key::PageUp | Key::PageDown) {
+            x = *self.desired_x.get_or_insert(x);
+        }
+        //a closed fold is stepped over as one line
+        let folds = &self.folds;
+        let page = |y: usize, step: &dyn Fn(usize) -> Option<usize>| {
+            (0..self.terminal.height).fold(y, |y, _| step(y).unwrap_or(y))
+        };
+        match key {
+            Key::Up => y = folds.previous(y).unwrap_or(y),
+            Key::Down => y = folds.next(y, height).unwrap_or(y),
+            Key::Left if x == 0 && y > 1 => {
+                y = folds.previous(y).unwrap_or(y);
+                x = self.document.rows[y - 1].content.len();
+            }
+            Key::Left => x = x.saturating_sub(1),
+            Key::Right if x == width && y < height => match folds.next(y, height) {
+                Some(next) => {
+                    y = next;
+                    x = 0;
+                }
+                None => x = width,
+            },
+            Key::Right => {
+                if x < width {
+                    x = x.saturating_add(1);
+                }
+            }
+            //first non-blank, or the start of the line if already there
+            Key::Home => {
+                let first = self.document.rows[y - 1].first_non_blank();
+                x = if x == first { 0 } else { first };
+            }
+            Key::End => x = width,
+            Key::PageDown => y = page(y, &|y| folds.next(y, height)),
+            Key::PageUp => y = page(y, &|y| folds.previous(y)),
+            _ => (),
+        }
+        if x > self.document.rows[y - 1].content.len() {
+            x = self.document.rows[y - 1].content.len();
+        }
+        self.cursor_position = Position { x, y }
+    }
+    fn word_left(&mut self) {
+        self.cursor_position = word_left(&self.document, &self.cursor_position);
+    }
+    fn word_right(&mut self) {
+        self.cursor_position = word_right(&self.document, &self.cursor_position);
+    }
+    fn paragraph(&mut self, down: bool) {
+        let y = paragraph(&self.document, self.cursor_position.y, down);
+        self.cursor_position = Position { x: 0, y };
+    }
+    //Closes the innermost fold around the cursor that isn't closed yet.
+    fn fold(&mut self) {
+        let y = self.cursor_position.y;
+        let found = (1..=y).rev().find_map(|header| {
+            let end = self.folds.region(&self.document, header)?;
+            (header == y || end >= y)
+                .then_some((header, end))
+                .filter(|&(header, _)| !self.folds.is_closed(header))
+        });
+        match found {
+            Some((header, end)) => {
+                self.folds.close(header, end);
+                self.cursor_position = Position {
+                    x: self
+                        .cursor_position
+                        .x
+                        .min(self.document.rows[header - 1].content.len()),
+                    y: header,
+                };
+            }
+            None => self.message = StatusMessage::new("nothing to fold here".to_string()),
+        }
+    }
+    fn unfold(&mut self) {
+        if !self.folds.open(self.cursor_position.y) {
+            self.message = StatusMessage::new("no fold here".to_string());
+        }
+    }
+    fn toggle_fold(&mut self, y: usize) {
+        if !self.folds.open(y) {
+            if let Some(end) = self.folds.region(&self.document, y) {
+                self.folds.close(y, end);
+            }
+        }
+    }
+    //Opens every fold if any are closed, otherwise closes every one there is.
+    fn toggle_folds(&mut self) {
+        if self.folds.is_empty() {
+            for y in 1..=self.document.rows.len() {
+                if let Some(end) = self.folds.region(&self.document, y) {
+                    self.folds.close(y, end);
+                }
+            }
+            //to the header of the fold the cursor went into, or scroll opens it again
+            let y = self.folds.previous(self.cursor_position.y + 1).unwrap_or(1);
+            let x = self
+                .cursor_position
+                .x
+                .min(self.document.rows[y - 1].content.len());
+            self.cursor_position = Position { x, y };
+        } else {
+            self.folds.clear();
+        }
+    }
+    //Jumps to the partner of the bracket under the cursor, or just before it.
+    fn matching_bracket(&mut self) {
+        let Position { x, y } = self.cursor_position.clone();
+        let found = self
+            .document
+            .matching_bracket(&Position { x, y })
+            .or_else(|| {
+                self.document.matching_bracket(&Position {
+                    x: x.checked_sub(1)?,
+                    y,
+                })
+            });
+        match found {
+            Some(position) => self.cursor_position = position,
+            None => self.message = StatusMessage::new("no matching bracket".to_string()),
+        }
+    }
+    fn goto_line(&mut self) {
+        let input = match self.prompt("Go to line: ", "line", None) {
+            Some(input) => input,
+            None => return,
+        };
+        match input.trim().parse::<usize>() {
+            Ok(line) => {
+                let y = line.clamp(1, self.document.rows.len());
+                let x = self.document.rows[y - 1].first_non_blank();
+                self.cursor_position = Position { x, y };
+            }
+            Err(_) => self.message = StatusMessage::new(format!("not a line number: {}", input)),
+        }
+    }
+
+    //BARS
+    fn welcome(&self) {
+        Terminal::move_cursor(
+            self.text_left() + (self.text_width() / 2).saturating_sub(1),
+            0,
+        );
+        println!(
+            "{}Welcome to Saphire!{}\r",
+            color::Fg(Blue),
+            color::Fg(Reset)
+        );
+    }
+    fn stats_bar(&mut self) {
+        Terminal::clear_row();
+        if self.message.message.is_empty() {
+            //with nothing else to say, the diagnostic on the cursor's line
+            let y = self.cursor_position.y;
+            if let Some(diagnostic) = self
+                .diagnostics()
+                .iter()
+                .filter(|diagnostic| diagnostic.start.line + 1 == y)
+                .max_by_key(|diagnostic| diagnostic.severity)
+            {
+                let message = diagnostic.message.lines().next().unwrap_or_default();
+                print!(
+                    "{}{}{} {}",
+                    Fg(diagnostic.severity.color()),
+                    diagnostic.severity.marker(),
+                    Fg(Reset),
+                    picker::fit(message, self.terminal.width.saturating_sub(2) as usize)
+                );
+            }
+        }
+        print!("{}", self.message.message);
+        if self.message.time.elapsed() >= MESSAGE_TIME {
+            if self.message_buffer.is_empty() {
+                self.message = StatusMessage::new("".to_string());
+            } else {
+                self.message = StatusMessage::new(self.message_buffer.remove(0));
+            }
+        }
+    }
+    fn message_bar(&self) {
+        print!(
             "{}{}\r",
             color::Bg(White),
             " ".repeat(self.terminal.width as usize)
         );
+        let diagnostics = self.diagnostics();
+        let count = |severity: Severity| {
+            diagnostics
+                .iter()
+                .filter(|diagnostic| diagnostic.severity == severity)
+                .count()
+        };
+        let problems = if diagnostics.is_empty() {
+            String::new()
+        } else {
+            format!(
+                " errors: {} warnings: {}",
+                count(Severity::Error),
+                count(Severity::Warning)
+            )
+        };
         let content = format!(
-            "{}{}{} lines: {} x: {} y: {} terminal width: {} terminal height: {} x offset: {} y offset: {} line length: {} highlighting: {:?}\r",
+            "{}{}{}{} lines: {} x: {} y: {} terminal width: {} terminal height: {} x offset: {} y offset: {} line length: {} highlighting: {:?}\r",
             color::Fg(Black),
             if let Some(path) = &self.document.path{
                 path.clone().into_os_string().into_string().unwrap()
@@ -486,6 +3158,7 @@ impl Editor {
             } else{
                 ""
             },
+            problems,
             self.document.rows.len(),
             self.cursor_position.x,
             self.cursor_position.y,
@@ -513,16 +3186,693 @@ impl Editor {
     ///
     /// panics if the terminal fails to initiate
     #[must_use]
-    pub fn new(doc: Document) -> Editor {
+    pub fn new(path: Option<PathBuf>, config: &Config) -> Editor {
+        let (keymap, mut problems) = Keymap::new(config);
+        problems.extend(config.errors.iter().cloned());
+        let settings = Settings::new(config, &mut problems);
+        let mut message_buffer = problems;
+        message_buffer.push("press ctrl+n to compose a status message".to_string());
         Editor {
             should_exit: false,
             terminal: Terminal::new(termion::terminal_size().unwrap()),
             cursor_position: Position { x: 0, y: 1 },
-            document: doc,
+            document: Document::new(path, settings.tab_width),
             offset: Position { x: 0, y: 0 },
-            message_buffer: vec!["press ctrl+n to compose a status message".to_string()],
+            message_buffer,
             message: StatusMessage::new("HELP: ctrl + q to quit".to_string()),
             unsaved_changes: false,
+            keymap,
+            pending_keys: Vec::new(),
+            recent_commands: Vec::new(),
+            settings,
+            histories: HashMap::new(),
+            explorer: None,
+            explorer_focused: false,
+            last_disk_check: Instant::now(),
+            swap: Swap::default(),
+            events: Events::start(),
+            deferred: Vec::new(),
+            selection: None,
+            last_click: None,
+            clicks: 0,
+            desired_x: None,
+            cursors: Vec::new(),
+            undo_stack: Undo::default(),
+            clipboard: Vec::new(),
+            folds: Folds::default(),
+            language_servers: HashMap::new(),
+            server_commands: config.section("lsp").to_vec(),
+            formatters: config.section("format").to_vec(),
+            popup: None,
+            completion: None,
+            snippet: None,
+            snippets: HashMap::new(),
+            shell: None,
+        }
+    }
+}
+//Word pieces that aren't spaces, so motions stop on words and on runs of punctuation.
+fn word_stops(row: &Row) -> Vec<Range<usize>> {
+    let is_punctuation = |word: &Range<usize>| {
+        !row.content[word.start]
+            .chars()
+            .any(|c| c.is_alphanumeric() || c == '_')
+    };
+    let mut stops: Vec<Range<usize>> = Vec::new();
+    for word in row.words() {
+        if row.content[word.start].trim().is_empty() {
+            continue;
+        }
+        match stops.last_mut() {
+            Some(last)
+                if last.end == word.start && is_punctuation(last) && is_punctuation(&word) =>
+            {
+                last.end = word.end;
+            }
+            _ => stops.push(word),
+        }
+    }
+    stops
+}
+//Where a word motion left from `position` lands: the start of the word before it,
+//or the end of the line above.
+fn word_left(document: &Document, position: &Position) -> Position {
+    let Position { x, y } = *position;
+    match word_stops(&document.rows[y - 1])
+        .into_iter()
+        .rev()
+        .find(|w| w.start < x)
+    {
+        Some(word) => Position { x: word.start, y },
+        None if y > 1 => Position {
+            x: document.rows[y - 2].content.len(),
+            y: y - 1,
+        },
+        None => Position { x: 0, y },
+    }
+}
+//Where a word motion right from `position` lands: the end of the word after it,
+//or the start of the line below.
+fn word_right(document: &Document, position: &Position) -> Position {
+    let Position { x, y } = *position;
+    let row = &document.rows[y - 1];
+    match word_stops(row).into_iter().find(|w| w.end > x) {
+        Some(word) => Position { x: word.end, y },
+        None if y < document.rows.len() => Position { x: 0, y: y + 1 },
+        None => Position {
+            x: row.content.len(),
+            y,
+        },
+    }
+}
+//The row a paragraph motion from row `y` lands on: past the next run of non-blank
+//lines to the blank line beyond it, or the first or last row.
+fn paragraph(document: &Document, mut y: usize, down: bool) -> usize {
+    let rows = &document.rows;
+    let step = |y: usize| {
+        if down {
+            (y < rows.len()).then(|| y + 1)
+        } else {
+            (y > 1).then(|| y - 1)
+        }
+    };
+    while rows[y - 1].is_blank() {
+        match step(y) {
+            Some(next) => y = next,
+            None => break,
+        }
+    }
+    while let Some(next) = step(y) {
+        y = next;
+        if rows[y - 1].is_blank() {
+            break;
+        }
+    }
+    y
+}
+//Runs `:s` over the rows start..=end of `document`. Rows can split, so the
+//folds over them can't be trusted any more and all go. Returns how many rows changed.
+fn substitute(
+    document: &mut Document,
+    folds: &mut Folds,
+    range: (usize, usize),
+    regex: &Regex,
+    replacement: &str,
+    global: bool,
+    tab_width: usize,
+) -> usize {
+    let changed = document.substitute(range, regex, replacement, global, tab_width);
+    if changed > 0 {
+        folds.clear();
+    }
+    changed
+}
+//Works out how to comment or uncomment the non-blank rows among `lines`, as
+//(y, x, graphemes to remove, text to insert) splices to make in order. Uses line
+//comments when the file type has them, otherwise block comments around each run
+//of rows. None when the file type has no comments at all.
+fn comment_splices(
+    document: &Document,
+    lines: &[usize],
+) -> Option<Vec<(usize, usize, usize, String)>> {
+    let comments = &document.file_type.comments;
+    let rows = &document.rows;
+    let lines: Vec<usize> = lines
+        .iter()
+        .copied()
+        .filter(|&y| !rows[y - 1].is_blank())
+        .collect();
+    let mut splices = Vec::new();
+    if lines.is_empty() {
+        return Some(splices);
+    }
+    let trimmed = |y: usize| {
+        let row = &rows[y - 1];
+        row.content[row.first_non_blank()..].concat()
+    };
+    let indent = lines
+        .iter()
+        .map(|&y| rows[y - 1].first_non_blank())
+        .min()
+        .unwrap_or(0);
+    if let Some(token) = &comments.line {
+        let commented = lines
+            .iter()
+            .all(|&y| trimmed(y).starts_with(token.as_str()));
+        for &y in &lines {
+            if commented {
+                let x = rows[y - 1].first_non_blank();
+                let mut remove = token.graphemes(true).count();
+                if rows[y - 1].content.get(x + remove) == Some(&" ".to_string()) {
+                    remove += 1;
+                }
+                splices.push((y, x, remove, String::new()));
+            } else {
+                splices.push((y, indent, 0, format!("{} ", token)));
+            }
+        }
+    } else if let Some((open, close)) = &comments.block {
+        //lines with only blank ones between them share one block comment
+        let mut runs: Vec<(usize, usize)> = Vec::new();
+        for &y in &lines {
+            match runs.last_mut() {
+                Some((_, last)) if rows[*last..y - 1].iter().all(Row::is_blank) => {
+                    *last = y;
+                }
+                _ => runs.push((y, y)),
+            }
+        }
+        let commented = runs.iter().all(|&(first, last)| {
+            trimmed(first).starts_with(open.as_str())
+                && rows[last - 1]
+                    .inner_string()
+                    .trim_end()
+                    .ends_with(close.as_str())
+        });
+        //bottom up, so taking the close marker off first doesn't move the open one
+        for &(first, last) in runs.iter().rev() {
+            if commented {
+                let row = &rows[last - 1];
+                let end = row.inner_string().trim_end().graphemes(true).count();
+                let mut from = end - close.graphemes(true).count();
+                if from > 0 && row.content[from - 1] == " " {
+                    from -= 1;
+                }
+                splices.push((last, from, end - from, String::new()));
+                let x = rows[first - 1].first_non_blank();
+                let mut remove = open.graphemes(true).count();
+                if rows[first - 1].content.get(x + remove) == Some(&" ".to_string()) {
+                    remove += 1;
+                }
+                splices.push((first, x, remove, String::new()));
+            } else {
+                let end = rows[last - 1].content.len();
+                splices.push((last, end, 0, format!(" {}", close)));
+                splices.push((first, indent, 0, format!("{} ", open)));
+            }
+        }
+    } else {
+        return None;
+    }
+    Some(splices)
+}
+//The change typing `c` over start..end makes, with `unit` as one level of
+//indentation. See `Editor::type_char`.
+fn typed_change(
+    document: &Document,
+    start: Position,
+    end: Position,
+    c: char,
+    unit: &str,
+    auto_pairs: bool,
+) -> Change {
+    let rules = &document.file_type.indent;
+    let row = &document.rows[start.y - 1];
+    let before = row.content[..start.x].concat();
+    let indent = row.content[..row.first_non_blank().min(start.x)].concat();
+    if c == '\t' {
+        return Change::new(start, end, unit.to_string());
+    }
+    if c == '\n' {
+        let mut change = Change::new(start, end, format!("\n{}", indent));
+        let opened = rules
+            .openers
+            .iter()
+            .any(|opener| before.trim_end().ends_with(opener.as_str()));
+        if opened {
+            change.text.push_str(unit);
+            //splitting a pair like {} leaves the closer on its own line
+            let rest = document.rows[change.end.y - 1].content[change.end.x..].concat();
+            if rules
+                .closers
+                .iter()
+                .any(|closer| rest.trim_start().starts_with(closer.as_str()))
+            {
+                change.after = format!("\n{}", indent);
+            }
+        }
+        return change;
+    }
+    let closes = rules.closers.iter().any(|closer| *closer == c.to_string());
+    if closes && start == end && !before.is_empty() && before.trim().is_empty() {
+        let outdent = start.x.min(unit.len());
+        let from = Position {
+            x: start.x - outdent,
+            y: start.y,
+        };
+        return Change::new(from, end, c.to_string());
+    }
+    if auto_pairs {
+        if let Some(change) = pair(document, &start, &end, &c.to_string()) {
+            return change;
+        }
+    }
+    Change::new(start, end, c.to_string())
+}
+//Auto-pairing for typing `typed` over start..end: wraps a selection in a pair,
+//steps over the closer that is already there, or adds the closer after an opener.
+//None when none of that applies. Nothing is paired inside strings or comments.
+fn pair(document: &Document, start: &Position, end: &Position, typed: &str) -> Option<Change> {
+    let row = &document.rows[start.y - 1];
+    let pairs = &document.file_type.pairs;
+    let (open, close) = pairs
+        .iter()
+        .find(|(open, close)| typed == open || typed == close)?;
+    if start != end {
+        if typed != open {
+            return None;
+        }
+        let inside = document.text(start, end);
+        return Some(Change {
+            start: start.clone(),
+            end: end.clone(),
+            text: format!("{}{}", open, inside),
+            after: close.clone(),
+        });
+    }
+    let next = row.content.get(start.x);
+    if typed == close && next == Some(close) {
+        let over = Position {
+            x: start.x + 1,
+            y: start.y,
+        };
+        return Some(Change::new(start.clone(), over, typed.to_string()));
+    }
+    if typed != open || in_text(row, start.x) {
+        return None;
+    }
+    //only pair where the closer can't end up glued to the next word
+    let free = |gr: Option<&String>| {
+        gr.is_none_or(|gr| gr.trim().is_empty() || pairs.iter().any(|(_, close)| gr == close))
+    };
+    if !free(next) {
+        return None;
+    }
+    //a quote right after a word is an apostrophe or a lifetime, not a string
+    let previous = start.x.checked_sub(1).and_then(|x| row.content.get(x));
+    if open == close && previous.is_some_and(|gr| gr.chars().all(char::is_alphanumeric)) {
+        return None;
+    }
+    Some(Change {
+        start: start.clone(),
+        end: end.clone(),
+        text: open.clone(),
+        after: close.clone(),
+    })
+}
+//Whether `x` in `row` is inside a string or comment, going by the highlighting.
+fn in_text(row: &Row, x: usize) -> bool {
+    let previous = match x.checked_sub(1) {
+        Some(previous) => previous,
+        None => return false,
+    };
+    match row.highlighting.get(previous) {
+        Some(Type::Comment) => true,
+        //a string's closing quote leaves the cursor outside it
+        Some(Type::String) => {
+            let quote = matches!(row.content[previous].as_str(), "\"" | "'");
+            let opening =
+                previous == 0 || row.highlighting.get(previous - 1) != Some(&Type::String);
+            !quote || opening
+        }
+        _ => false,
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(text: &str) -> Document {
+        let mut document = Document::new(None, 4);
+        document.set_contents(text, 4);
+        document
+    }
+    //Runs an ex substitute command the way the editor does.
+    fn run_substitute(document: &mut Document, folds: &mut Folds, command: &str) -> usize {
+        let last = document.rows.len();
+        match ex::parse(command, 1, last) {
+            Ok(Ex::Substitute {
+                start,
+                end,
+                pattern,
+                replacement,
+                global,
+            }) => {
+                let regex = Regex::new(&pattern).unwrap();
+                substitute(
+                    document,
+                    folds,
+                    (start, end),
+                    &regex,
+                    &replacement,
+                    global,
+                    4,
+                )
+            }
+            _ => panic!("{} isn't a substitution", command),
+        }
+    }
+    #[test]
+    fn substitute_splits_rows_on_line_breaks() {
+        let mut document = document("a,b,c\nd,e");
+        let mut folds = Folds::default();
+        assert_eq!(run_substitute(&mut document, &mut folds, "%s/,/\\r/g"), 2);
+        assert_eq!(document.contents(), "a\nb\nc\nd\ne\n");
+        assert_eq!(run_substitute(&mut document, &mut folds, "2,3s/$/;/"), 2);
+        assert_eq!(document.contents(), "a\nb;\nc;\nd\ne\n");
+    }
+    #[test]
+    fn substitute_drops_folds() {
+        let mut document = document("fn f() {\n    a\n}\nb");
+        let mut folds = Folds::default();
+        folds.close(1, 3);
+        assert_eq!(run_substitute(&mut document, &mut folds, "%s/x/y/"), 0);
+        assert!(folds.is_closed(1));
+        assert_eq!(run_substitute(&mut document, &mut folds, "1s/\\{/{\\r/"), 1);
+        assert!(folds.is_empty());
+        assert!(!folds.hidden(3));
+        assert_eq!(document.rows.len(), 5);
+    }
+    fn rust(text: &str) -> Document {
+        let mut document = Document::new(None, 4);
+        document.file_type = FileType::from(PathBuf::from("a.rs"));
+        document.set_contents(text, 4);
+        document
+    }
+    //What `pair` does when `typed` goes over x..end_x of the first row, as
+    //(start x, end x, text, text after the cursor).
+    fn paired(
+        document: &Document,
+        x: usize,
+        end_x: usize,
+        typed: &str,
+    ) -> Option<(usize, usize, String, String)> {
+        let start = Position { x, y: 1 };
+        let end = Position { x: end_x, y: 1 };
+        pair(document, &start, &end, typed)
+            .map(|change| (change.start.x, change.end.x, change.text, change.after))
+    }
+    fn change(x: usize, end_x: usize, text: &str, after: &str) -> (usize, usize, String, String) {
+        (x, end_x, text.to_string(), after.to_string())
+    }
+    #[test]
+    fn pairs_openers_and_steps_over_closers() {
+        let document = document("f x ()");
+        assert_eq!(paired(&document, 1, 1, "("), Some(change(1, 1, "(", ")")));
+        assert_eq!(paired(&document, 3, 3, "["), Some(change(3, 3, "[", "]")));
+        //not in front of a word
+        assert_eq!(paired(&document, 2, 2, "("), None);
+        assert_eq!(paired(&document, 5, 5, ")"), Some(change(5, 6, ")", "")));
+        assert_eq!(paired(&document, 6, 6, ")"), None);
+        assert_eq!(paired(&document, 1, 1, "x"), None);
+    }
+    #[test]
+    fn wraps_a_selection() {
+        let document = document("a + b");
+        assert_eq!(
+            paired(&document, 0, 5, "("),
+            Some(change(0, 5, "(a + b", ")"))
+        );
+        assert_eq!(
+            paired(&document, 0, 1, "\""),
+            Some(change(0, 1, "\"a", "\""))
+        );
+        assert_eq!(paired(&document, 0, 5, ")"), None);
+    }
+    #[test]
+    fn quotes_after_a_word_are_left_alone() {
+        let document = document("don x ");
+        assert_eq!(paired(&document, 3, 3, "'"), None);
+        assert_eq!(paired(&document, 6, 6, "'"), Some(change(6, 6, "'", "'")));
+        assert_eq!(paired(&document, 3, 3, "\""), None);
+        assert_eq!(
+            paired(&document, 6, 6, "\""),
+            Some(change(6, 6, "\"", "\""))
+        );
+        //rust leaves ' for lifetimes
+        assert_eq!(paired(&rust("x "), 2, 2, "'"), None);
+    }
+    #[test]
+    fn nothing_pairs_in_strings_or_comments() {
+        let document = rust("f(\"a b\", c); // d ");
+        let row = &document.rows[0];
+        assert!(!in_text(row, 0));
+        assert!(!in_text(row, 2));
+        assert!(in_text(row, 3));
+        assert!(in_text(row, 5));
+        //right after the closing quote is outside again
+        assert!(!in_text(row, 7));
+        assert!(in_text(row, 17));
+        assert_eq!(paired(&document, 5, 5, "("), None);
+        assert_eq!(paired(&document, 17, 17, "["), None);
+        assert_eq!(paired(&document, 7, 7, "("), None);
+        assert_eq!(paired(&document, 8, 8, "("), Some(change(8, 8, "(", ")")));
+        //closers are still stepped over
+        assert_eq!(
+            paired(&document, 10, 10, ")"),
+            Some(change(10, 11, ")", ""))
+        );
+    }
+    //Toggles comments over `lines` the way the editor does and returns the text.
+    fn toggled(document: &mut Document, lines: &[usize]) -> Option<String> {
+        for (y, x, remove, insert) in comment_splices(document, lines)? {
+            let inserted: Vec<String> = insert.graphemes(true).map(str::to_string).collect();
+            document.rows[y - 1].content.splice(x..x + remove, inserted);
         }
+        Some(document.contents())
+    }
+    #[test]
+    fn toggles_line_comments() {
+        let mut document = rust("fn f() {\n    a\n\n  b\n}");
+        let commented = "fn f() {\n  //   a\n\n  // b\n}\n";
+        assert_eq!(toggled(&mut document, &[2, 3, 4]).unwrap(), commented);
+        assert_eq!(
+            toggled(&mut document, &[2, 3, 4]).unwrap(),
+            "fn f() {\n    a\n\n  b\n}\n"
+        );
+        //a mix is commented as a whole, even the lines that already were
+        let mut document = rust("//a\nb");
+        assert_eq!(toggled(&mut document, &[1, 2]).unwrap(), "// //a\n// b\n");
+        //the space after the token only goes if there is one
+        let mut document = rust("//a\n// b");
+        assert_eq!(toggled(&mut document, &[1, 2]).unwrap(), "a\nb\n");
+        //blank lines alone are left be
+        let mut document = rust("a\n  \n");
+        assert_eq!(comment_splices(&document, &[2]), Some(Vec::new()));
+        assert_eq!(toggled(&mut document, &[2]).unwrap(), "a\n  \n");
+    }
+    #[test]
+    fn toggles_block_comments_without_a_line_token() {
+        let mut document = rust("a\n\n  b\nc");
+        document.file_type.comments.line = None;
+        assert_eq!(
+            toggled(&mut document, &[1, 2, 3]).unwrap(),
+            "/* a\n\n  b */\nc\n"
+        );
+        assert_eq!(toggled(&mut document, &[1, 2, 3]).unwrap(), "a\n\n  b\nc\n");
+        //one row, and a mix that is commented again as a whole
+        assert_eq!(toggled(&mut document, &[4]).unwrap(), "a\n\n  b\n/* c */\n");
+        assert_eq!(
+            toggled(&mut document, &[3, 4]).unwrap(),
+            "a\n\n/*   b\n/* c */ */\n"
+        );
+    }
+    #[test]
+    fn no_comment_syntax() {
+        let mut document = document("a\nb");
+        assert_eq!(toggled(&mut document, &[1, 2]), None);
+        assert_eq!(comment_splices(&document, &[]), Some(Vec::new()));
+    }
+    //What typing `c` at x, y with no selection does, as
+    //(start x, end x, text, text after the cursor).
+    fn typed_at(
+        document: &Document,
+        x: usize,
+        y: usize,
+        c: char,
+        unit: &str,
+    ) -> (usize, usize, String, String) {
+        let at = Position { x, y };
+        let change = typed_change(document, at.clone(), at, c, unit, false);
+        assert_eq!((change.start.y, change.end.y), (y, y));
+        (change.start.x, change.end.x, change.text, change.after)
+    }
+    #[test]
+    fn new_lines_indent_after_openers() {
+        let document = rust("fn f() {}\n    if x {\n  a\n");
+        assert_eq!(
+            typed_at(&document, 8, 1, '\n', "    "),
+            change(8, 8, "\n    ", "\n")
+        );
+        assert_eq!(
+            typed_at(&document, 9, 1, '\n', "    "),
+            change(9, 9, "\n", "")
+        );
+        assert_eq!(
+            typed_at(&document, 10, 2, '\n', "    "),
+            change(10, 10, "\n        ", "")
+        );
+        assert_eq!(
+            typed_at(&document, 3, 3, '\n', "    "),
+            change(3, 3, "\n  ", "")
+        );
+        //splitting the indentation keeps only what is left of the cursor
+        assert_eq!(
+            typed_at(&document, 1, 3, '\n', "    "),
+            change(1, 1, "\n ", "")
+        );
+    }
+    #[test]
+    fn closers_dedent_at_the_start_of_a_line() {
+        let document = rust("        \n  \n  a\n      ");
+        assert_eq!(
+            typed_at(&document, 8, 1, '}', "    "),
+            change(4, 8, "}", "")
+        );
+        assert_eq!(typed_at(&document, 8, 1, ')', "  "), change(6, 8, ")", ""));
+        assert_eq!(
+            typed_at(&document, 2, 2, ']', "    "),
+            change(0, 2, "]", "")
+        );
+        assert_eq!(
+            typed_at(&document, 6, 4, '}', "    "),
+            change(2, 6, "}", "")
+        );
+        //not after text, at the very start, or for anything but a closer
+        assert_eq!(
+            typed_at(&document, 3, 3, '}', "    "),
+            change(3, 3, "}", "")
+        );
+        assert_eq!(
+            typed_at(&document, 0, 1, '}', "    "),
+            change(0, 0, "}", "")
+        );
+        assert_eq!(
+            typed_at(&document, 8, 1, 'x', "    "),
+            change(8, 8, "x", "")
+        );
+        //nor over a selection
+        let (start, end) = (Position { x: 4, y: 1 }, Position { x: 8, y: 1 });
+        let selected = typed_change(&document, start, end, '}', "    ", false);
+        assert_eq!((selected.start.x, selected.text.as_str()), (4, "}"));
+    }
+    #[test]
+    fn indentation_follows_the_tab_width() {
+        let mut document = Document::new(None, 2);
+        document.file_type = FileType::from(PathBuf::from("a.rs"));
+        document.set_contents("\tif x {\n\t\t", 2);
+        assert_eq!(document.rows[0].inner_string(), "  if x {");
+        assert_eq!(
+            typed_at(&document, 8, 1, '\n', "  "),
+            change(8, 8, "\n    ", "")
+        );
+        assert_eq!(
+            typed_at(&document, 2, 1, '\t', "  "),
+            change(2, 2, "  ", "")
+        );
+        assert_eq!(typed_at(&document, 4, 2, '}', "  "), change(2, 4, "}", ""));
+    }
+    fn stops(text: &str) -> Vec<Range<usize>> {
+        word_stops(&Row::new(text, 4))
+    }
+    #[test]
+    fn word_stops_group_punctuation() {
+        //a dot between letters stays inside the word, as in Unicode word breaking
+        assert_eq!(stops("a.b  += c::d"), [0..3, 5..7, 8..9, 9..11, 11..12]);
+        assert_eq!(stops("a ,.; b"), [0..1, 2..5, 6..7]);
+        assert_eq!(stops("foo_bar(x);"), [0..7, 7..8, 8..9, 9..11]);
+        assert!(stops("   ").is_empty());
+        assert!(stops("").is_empty());
+    }
+    #[test]
+    fn word_stops_count_graphemes() {
+        assert_eq!(stops("héllo wörld"), [0..5, 6..11]);
+        assert_eq!(stops("e\u{301}t\u{e9} -> 👍🏽!"), [0..3, 4..6, 7..9]);
+        assert_eq!(stops("日本 語"), [0..1, 1..2, 3..4]);
+    }
+    fn left(document: &Document, x: usize, y: usize) -> (usize, usize) {
+        let position = word_left(document, &Position { x, y });
+        (position.x, position.y)
+    }
+    fn right(document: &Document, x: usize, y: usize) -> (usize, usize) {
+        let position = word_right(document, &Position { x, y });
+        (position.x, position.y)
+    }
+    #[test]
+    fn word_motions_cross_lines_at_the_ends() {
+        let document = document("  one, two\n\nthree");
+        assert_eq!(right(&document, 0, 1), (5, 1));
+        assert_eq!(right(&document, 5, 1), (6, 1));
+        assert_eq!(right(&document, 6, 1), (10, 1));
+        assert_eq!(right(&document, 10, 1), (0, 2));
+        assert_eq!(right(&document, 0, 2), (0, 3));
+        assert_eq!(right(&document, 2, 3), (5, 3));
+        //the last line stops at its end
+        assert_eq!(right(&document, 5, 3), (5, 3));
+        assert_eq!(left(&document, 3, 3), (0, 3));
+        assert_eq!(left(&document, 0, 3), (0, 2));
+        assert_eq!(left(&document, 0, 2), (10, 1));
+        assert_eq!(left(&document, 10, 1), (7, 1));
+        assert_eq!(left(&document, 7, 1), (5, 1));
+        assert_eq!(left(&document, 4, 1), (2, 1));
+        //the first line stops at its start
+        assert_eq!(left(&document, 2, 1), (0, 1));
+        assert_eq!(left(&document, 0, 1), (0, 1));
+    }
+    #[test]
+    fn paragraphs_skip_runs_of_blank_lines() {
+        let text = document("a\nb\n\n\n  \nc\n\nd");
+        assert_eq!(paragraph(&text, 1, true), 3);
+        //blank lines first are skipped, then the paragraph after them
+        assert_eq!(paragraph(&text, 3, true), 7);
+        assert_eq!(paragraph(&text, 7, true), 8);
+        assert_eq!(paragraph(&text, 8, true), 8);
+        assert_eq!(paragraph(&text, 8, false), 7);
+        assert_eq!(paragraph(&text, 6, false), 5);
+        assert_eq!(paragraph(&text, 5, false), 1);
+        assert_eq!(paragraph(&text, 1, false), 1);
+        let blank = document("\n\n");
+        assert_eq!(paragraph(&blank, 1, true), 2);
+        assert_eq!(paragraph(&blank, 2, false), 1);
     }
 }
@@ -0,0 +1,154 @@
+use std::{
+    io::stdin,
+    sync::mpsc::{channel, Receiver, Sender},
+    thread,
+    time::Instant,
+};
+
+use signal_hook::{
+    consts::{SIGHUP, SIGTERM, SIGWINCH},
+    iterator::Signals,
+};
+use termion::{
+    event::{Event as TermEvent, Key, MouseEvent},
+    input::TermRead,
+};
+
+use crate::editor::Editor;
+
+//Work finished on another thread, applied to the editor on the main thread.
+pub type Job = Box<dyn FnOnce(&mut Editor) + Send>;
+//The arrow keys, for the key presses termion only knows without modifiers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Arrow {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+impl Arrow {
+    #[must_use]
+    pub fn key(self) -> Key {
+        match self {
+            Arrow::Left => Key::Left,
+            Arrow::Right => Key::Right,
+            Arrow::Up => Key::Up,
+            Arrow::Down => Key::Down,
+        }
+    }
+}
+//A key as the editor sees it: one termion reads, or Ctrl held with an arrow,
+//which termion has no key for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyPress {
+    Key(Key),
+    Ctrl(Arrow),
+}
+impl KeyPress {
+    //The key without its modifier, for places that don't tell them apart.
+    #[must_use]
+    pub fn key(self) -> Key {
+        match self {
+            KeyPress::Key(key) => key,
+            KeyPress::Ctrl(arrow) => arrow.key(),
+        }
+    }
+}
+impl From<Key> for KeyPress {
+    fn from(key: Key) -> Self {
+        KeyPress::Key(key)
+    }
+}
+pub enum Event {
+    Key(KeyPress),
+    Mouse(MouseEvent),
+    //the window changed size
+    Resize,
+    Signal(i32),
+    Job(Job),
+    //another thread panicked; the editor carries on and says so
+    Failed(String),
+}
+//Terminal input, signals and worker results all arrive on one channel, so
+//waiting for any of them is a single blocking receive.
+//Keeping one reader for stdin also means no bytes get lost between keys.
+pub struct Events {
+    sender: Sender<Event>,
+    receiver: Receiver<Event>,
+}
+impl Events {
+    #[must_use]
+    pub fn start() -> Events {
+        let (sender, receiver) = channel();
+        let keys = sender.clone();
+        let signal_sender = sender.clone();
+        thread::spawn(move || {
+            for event in stdin().events() {
+                let event = match event {
+                    Ok(TermEvent::Key(key)) => Event::Key(KeyPress::Key(key)),
+                    Ok(TermEvent::Mouse(mouse)) => Event::Mouse(mouse),
+                    Ok(TermEvent::Unsupported(bytes)) => match ctrl_arrow(&bytes) {
+                        Some(arrow) => Event::Key(KeyPress::Ctrl(arrow)),
+                        None => {
+                            log::info!("unsupported input {:?}", bytes);
+                            continue;
+                        }
+                    },
+                    Err(_) => break,
+                };
+                if keys.send(event).is_err() {
+                    return;
+                }
+            }
+            //the terminal went away, which is as good as a hangup
+            let _ = keys.send(Event::Signal(SIGHUP));
+        });
+        match Signals::new([SIGTERM, SIGHUP, SIGWINCH]) {
+            Ok(mut signals) => {
+                thread::spawn(move || {
+                    for signal in signals.forever() {
+                        let event = if signal == SIGWINCH {
+                            Event::Resize
+                        } else {
+                            Event::Signal(signal)
+                        };
+                        if signal_sender.send(event).is_err() {
+                            return;
+                        }
+                    }
+                });
+            }
+            Err(e) => log::warn!("couldn't listen for signals: {}", e),
+        }
+        Events { sender, receiver }
+    }
+    //For worker threads to send their results back with.
+    #[must_use]
+    pub fn sender(&self) -> Sender<Event> {
+        self.sender.clone()
+    }
+    //Sleeps until an event comes in, or gives None once `deadline` passes.
+    ///# Panics
+    ///
+    /// never, the channel can't disconnect while `self` holds a sender
+    #[must_use]
+    pub fn next(&self, deadline: Option<Instant>) -> Option<Event> {
+        match deadline {
+            Some(deadline) => self
+                .receiver
+                .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                .ok(),
+            None => Some(self.receiver.recv().unwrap()),
+        }
+    }
+}
+//Ctrl+arrows as xterm sends them.
+fn ctrl_arrow(bytes: &[u8]) -> Option<Arrow> {
+    match bytes {
+        b"\x1b[1;5D" => Some(Arrow::Left),
+        b"\x1b[1;5C" => Some(Arrow::Right),
+        b"\x1b[1;5A" => Some(Arrow::Up),
+        b"\x1b[1;5B" => Some(Arrow::Down),
+        _ => None,
+    }
+}
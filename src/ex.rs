@@ -0,0 +1,550 @@
+use std::{fs::read_dir, path::Path};
+
+//A parsed `:` command. Line numbers are 1-based and ranges are inclusive.
+#[derive(Debug, PartialEq)]
+pub enum Ex {
+    Goto(usize),
+    Write {
+        path: Option<String>,
+        quit: bool,
+    },
+    Edit {
+        path: String,
+        force: bool,
+    },
+    Quit {
+        force: bool,
+    },
+    Substitute {
+        start: usize,
+        end: usize,
+        pattern: String,
+        replacement: String,
+        global: bool,
+    },
+    Set {
+        option: String,
+        value: Option<String>,
+    },
+    Sort {
+        start: usize,
+        end: usize,
+        reverse: bool,
+    },
+    //:[range]!command
+    Filter {
+        start: usize,
+        end: usize,
+        command: String,
+    },
+    //:!command, shown rather than put in the text
+    Shell {
+        command: String,
+    },
+    //:r !command, put in at the cursor
+    Read {
+        command: String,
+    },
+}
+type LineRange = (usize, usize);
+pub const EX_COMMANDS: &[&str] = &[
+    "edit",
+    "quit",
+    "read",
+    "set",
+    "sort",
+    "substitute",
+    "wq",
+    "write",
+    "x",
+];
+
+//Parses `input` (without the leading ':'). `current` and `last` are the cursor line and
+//the number of lines, used to resolve `.`, `$` and `%` and the default ranges.
+///# Errors
+///
+/// returns a message for the status bar if the command can't be parsed
+pub fn parse(input: &str, current: usize, last: usize) -> Result<Ex, String> {
+    let input = input.trim_start_matches(':').trim();
+    let (range, rest) = parse_range(input, current, last)?;
+    let rest = rest.trim_start();
+    let name_len = rest
+        .find(|c: char| !c.is_ascii_alphabetic())
+        .unwrap_or(rest.len());
+    let (name, rest) = rest.split_at(name_len);
+    let (bang, args) = match rest.strip_prefix('!') {
+        Some(args) => (true, args.trim()),
+        None => (false, rest.trim()),
+    };
+    let arg = if args.is_empty() {
+        None
+    } else {
+        Some(args.to_string())
+    };
+    //what a command is given that it has no use for is an error, not ignored
+    let takes = |takes_range: bool, takes_bang: bool, takes_arg: bool| {
+        if range.is_some() && !takes_range {
+            Err(format!("{} doesn't take a range", name))
+        } else if bang && !takes_bang {
+            Err(format!("{} doesn't take !", name))
+        } else if arg.is_some() && !takes_arg {
+            Err(format!("{} doesn't take an argument: {}", name, args))
+        } else {
+            Ok(())
+        }
+    };
+    let (start, end) = range.unwrap_or((1, last));
+    let command = match name {
+        "" if bang => {
+            let command = arg.ok_or("! needs a shell command")?;
+            match range {
+                Some((start, end)) => Ex::Filter {
+                    start,
+                    end,
+                    command,
+                },
+                None => Ex::Shell { command },
+            }
+        }
+        "" => match (range, arg) {
+            (_, Some(_)) => return Err(format!("trailing characters: {}", args)),
+            (Some((_, line)), None) => Ex::Goto(line.clamp(1, last)),
+            (None, None) => return Err("no command".to_string()),
+        },
+        "w" | "write" => {
+            takes(false, false, true)?;
+            Ex::Write {
+                path: arg,
+                quit: false,
+            }
+        }
+        "wq" | "x" => {
+            takes(false, false, true)?;
+            Ex::Write {
+                path: arg,
+                quit: true,
+            }
+        }
+        "q" | "quit" => {
+            takes(false, true, false)?;
+            Ex::Quit { force: bang }
+        }
+        "e" | "edit" => {
+            takes(false, true, true)?;
+            Ex::Edit {
+                path: arg.ok_or("edit needs a file name")?,
+                force: bang,
+            }
+        }
+        "s" | "substitute" => {
+            takes(true, false, true)?;
+            let (start, end) = range.unwrap_or((current, current));
+            parse_substitute(args, start, end)?
+        }
+        "set" => {
+            takes(false, false, true)?;
+            let arg = arg.ok_or("set needs an option")?;
+            match arg.split_once('=') {
+                Some((option, value)) => Ex::Set {
+                    option: option.trim().to_string(),
+                    value: Some(value.trim().to_string()),
+                },
+                None => Ex::Set {
+                    option: arg,
+                    value: None,
+                },
+            }
+        }
+        "r" | "read" => {
+            takes(false, true, true)?;
+            //both :r !command and :r!command
+            let command = if bang {
+                Some(args)
+            } else {
+                args.strip_prefix('!')
+            };
+            match command.map(str::trim) {
+                Some(command) if !command.is_empty() => Ex::Read {
+                    command: command.to_string(),
+                },
+                _ => return Err("read takes a shell command: r !command".to_string()),
+            }
+        }
+        "sort" => {
+            takes(true, true, false)?;
+            Ex::Sort {
+                start,
+                end,
+                reverse: bang,
+            }
+        }
+        other => return Err(format!("not a command: {}", other)),
+    };
+    Ok(command)
+}
+fn parse_range(
+    input: &str,
+    current: usize,
+    last: usize,
+) -> Result<(Option<LineRange>, &str), String> {
+    if let Some(rest) = input.strip_prefix('%') {
+        return Ok((Some((1, last)), rest));
+    }
+    let (start, rest) = parse_address(input, current, last)?;
+    let start = match start {
+        Some(start) => start,
+        None => return Ok((None, rest)),
+    };
+    if let Some(rest) = rest.strip_prefix(',') {
+        let (end, rest) = parse_address(rest, current, last)?;
+        let end = end.ok_or("expected a line after ','")?;
+        if end < start {
+            return Err("backwards range".to_string());
+        }
+        Ok((Some((start, end)), rest))
+    } else {
+        Ok((Some((start, start)), rest))
+    }
+}
+fn parse_address(
+    input: &str,
+    current: usize,
+    last: usize,
+) -> Result<(Option<usize>, &str), String> {
+    if let Some(rest) = input.strip_prefix('.') {
+        return Ok((Some(current), rest));
+    }
+    if let Some(rest) = input.strip_prefix('$') {
+        return Ok((Some(last), rest));
+    }
+    let digits = input
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(input.len());
+    if digits == 0 {
+        return Ok((None, input));
+    }
+    let line = input[..digits]
+        .parse::<usize>()
+        .map_err(|e| e.to_string())?;
+    Ok((Some(line.clamp(1, last.max(1))), &input[digits..]))
+}
+fn parse_substitute(args: &str, start: usize, end: usize) -> Result<Ex, String> {
+    let mut chars = args.chars();
+    let delimiter = chars.next().ok_or("substitute needs a pattern")?;
+    let mut parts = vec![String::new()];
+    let mut escaped = false;
+    for c in chars {
+        if escaped {
+            if c != delimiter {
+                parts.last_mut().unwrap().push('\\');
+            }
+            parts.last_mut().unwrap().push(c);
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == delimiter {
+            parts.push(String::new());
+        } else {
+            parts.last_mut().unwrap().push(c);
+        }
+    }
+    if parts.len() < 2 || parts.len() > 3 {
+        return Err("usage: s/pattern/replacement/flags".to_string());
+    }
+    let flags = parts.get(2).cloned().unwrap_or_default();
+    if let Some(flag) = flags.chars().find(|c| !"gi".contains(*c)) {
+        return Err(format!("unknown substitute flag: {}", flag));
+    }
+    let mut pattern = parts[0].clone();
+    if flags.contains('i') {
+        pattern.insert_str(0, "(?i)");
+    }
+    Ok(Ex::Substitute {
+        start,
+        end,
+        pattern,
+        replacement: vim_replacement(&parts[1]),
+        global: flags.contains('g'),
+    })
+}
+//Turns a vim style replacement into the form the regex crate expects: `&` and
+//`\0` are the whole match, `\1` a group, `\r` a line break, and `\&` and `\\`
+//the characters themselves.
+#[must_use]
+pub fn vim_replacement(replacement: &str) -> String {
+    let mut result = String::new();
+    let mut chars = replacement.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('\\', Some(d)) if d.is_ascii_digit() => {
+                result.push_str(&format!("${{{}}}", d));
+                chars.next();
+            }
+            ('\\', Some(&escaped @ ('\\' | '&'))) => {
+                result.push(escaped);
+                chars.next();
+            }
+            ('\\', Some('r')) => {
+                result.push('\n');
+                chars.next();
+            }
+            ('&', _) => result.push_str("${0}"),
+            ('$', _) => result.push_str("$$"),
+            _ => result.push(c),
+        }
+    }
+    result
+}
+//Completion candidates for the word being typed: command names for the first word,
+//file paths for anything after it. Each candidate is the whole new input.
+#[must_use]
+pub fn complete(input: &str) -> Vec<String> {
+    if let Some((command, arg)) = input.split_once(' ') {
+        complete_path(arg.trim_start())
+            .into_iter()
+            .map(|path| format!("{} {}", command, path))
+            .collect()
+    } else {
+        let name_start = input
+            .find(|c: char| c.is_ascii_alphabetic())
+            .unwrap_or(input.len());
+        let (range, name) = input.split_at(name_start);
+        EX_COMMANDS
+            .iter()
+            .filter(|c| c.starts_with(name))
+            .map(|c| format!("{}{}", range, c))
+            .collect()
+    }
+}
+#[must_use]
+pub fn complete_path(prefix: &str) -> Vec<String> {
+    let (dir, file) = match prefix.rfind('/') {
+        Some(i) => (&prefix[..=i], &prefix[i + 1..]),
+        None => ("", prefix),
+    };
+    let entries = match read_dir(if dir.is_empty() {
+        Path::new(".")
+    } else {
+        Path::new(dir)
+    }) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let mut result: Vec<String> = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            if !name.starts_with(file) || (name.starts_with('.') && !file.starts_with('.')) {
+                return None;
+            }
+            let slash = if entry.path().is_dir() { "/" } else { "" };
+            Some(format!("{}{}{}", dir, name, slash))
+        })
+        .collect();
+    result.sort();
+    result
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str) -> Result<Ex, String> {
+        super::parse(input, 4, 10)
+    }
+    fn write(path: Option<&str>, quit: bool) -> Ex {
+        Ex::Write {
+            path: path.map(str::to_string),
+            quit,
+        }
+    }
+    #[test]
+    fn parses_ranges() {
+        assert_eq!(parse_range("", 4, 10), Ok((None, "")));
+        assert_eq!(parse_range("%d", 4, 10), Ok((Some((1, 10)), "d")));
+        assert_eq!(parse_range(".,$", 4, 10), Ok((Some((4, 10)), "")));
+        assert_eq!(parse_range("2,5s", 4, 10), Ok((Some((2, 5)), "s")));
+        assert_eq!(parse_range("7", 4, 10), Ok((Some((7, 7)), "")));
+        //lines past the end are the last one
+        assert_eq!(parse_range("0,99", 4, 10), Ok((Some((1, 10)), "")));
+        assert!(parse_range("5,2", 4, 10).is_err());
+        assert!(parse_range("5,", 4, 10).is_err());
+    }
+    #[test]
+    fn parses_goto() {
+        assert_eq!(parse("5"), Ok(Ex::Goto(5)));
+        assert_eq!(parse(":$"), Ok(Ex::Goto(10)));
+        assert_eq!(parse("2,3"), Ok(Ex::Goto(3)));
+        assert!(parse("").is_err());
+    }
+    #[test]
+    fn rejects_trailing_junk() {
+        assert!(parse("5 junk").is_err());
+        assert!(parse("1,2,3d").is_err());
+        assert!(parse("1,2,3").is_err());
+        assert!(parse("q extra").is_err());
+        assert!(parse("q1").is_err());
+        assert!(parse("sort x").is_err());
+        assert!(parse("s/a/b/g junk").is_err());
+    }
+    #[test]
+    fn rejects_unused_bangs_and_ranges() {
+        assert!(parse("w! x").is_err());
+        assert!(parse("wq!").is_err());
+        assert!(parse("set! ts=4").is_err());
+        assert!(parse("1,2w").is_err());
+        assert!(parse("3q").is_err());
+        assert!(parse("%e file").is_err());
+    }
+    #[test]
+    fn parses_commands() {
+        assert_eq!(parse("w"), Ok(write(None, false)));
+        assert_eq!(
+            parse("write a file.rs"),
+            Ok(write(Some("a file.rs"), false))
+        );
+        assert_eq!(parse("wq"), Ok(write(None, true)));
+        assert_eq!(parse("x out.rs"), Ok(write(Some("out.rs"), true)));
+        assert_eq!(parse("q"), Ok(Ex::Quit { force: false }));
+        assert_eq!(parse("quit!"), Ok(Ex::Quit { force: true }));
+        assert_eq!(
+            parse("e! src/lib.rs"),
+            Ok(Ex::Edit {
+                path: "src/lib.rs".to_string(),
+                force: true
+            })
+        );
+        assert!(parse("e").is_err());
+        assert_eq!(
+            parse("set ts = 4"),
+            Ok(Ex::Set {
+                option: "ts".to_string(),
+                value: Some("4".to_string())
+            })
+        );
+        assert_eq!(
+            parse("set number"),
+            Ok(Ex::Set {
+                option: "number".to_string(),
+                value: None
+            })
+        );
+        assert_eq!(
+            parse("sort!"),
+            Ok(Ex::Sort {
+                start: 1,
+                end: 10,
+                reverse: true
+            })
+        );
+        assert_eq!(
+            parse("2,3 sort"),
+            Ok(Ex::Sort {
+                start: 2,
+                end: 3,
+                reverse: false
+            })
+        );
+        assert!(parse("frobnicate").is_err());
+    }
+    #[test]
+    fn parses_shell_commands() {
+        assert_eq!(
+            parse("!ls -l"),
+            Ok(Ex::Shell {
+                command: "ls -l".to_string()
+            })
+        );
+        assert_eq!(
+            parse("%!sort"),
+            Ok(Ex::Filter {
+                start: 1,
+                end: 10,
+                command: "sort".to_string()
+            })
+        );
+        let read = Ok(Ex::Read {
+            command: "echo hi".to_string(),
+        });
+        assert_eq!(parse("r !echo hi"), read);
+        assert_eq!(parse("r!echo hi"), read);
+        assert!(parse("!").is_err());
+        assert!(parse("r file").is_err());
+    }
+    #[test]
+    fn parses_substitute() {
+        assert_eq!(
+            parse_substitute("/a/b/", 2, 3),
+            Ok(Ex::Substitute {
+                start: 2,
+                end: 3,
+                pattern: "a".to_string(),
+                replacement: "b".to_string(),
+                global: false
+            })
+        );
+        assert_eq!(
+            parse("%s#x/y#z#gi"),
+            Ok(Ex::Substitute {
+                start: 1,
+                end: 10,
+                pattern: "(?i)x/y".to_string(),
+                replacement: "z".to_string(),
+                global: true
+            })
+        );
+        //the current line without a range, and escaped delimiters
+        assert_eq!(
+            parse("s/a\\/b/c\\/d"),
+            Ok(Ex::Substitute {
+                start: 4,
+                end: 4,
+                pattern: "a/b".to_string(),
+                replacement: "c/d".to_string(),
+                global: false
+            })
+        );
+        assert!(parse_substitute("", 1, 1).is_err());
+        assert!(parse_substitute("/a", 1, 1).is_err());
+        assert!(parse_substitute("/a/b/g/h", 1, 1).is_err());
+        assert!(parse_substitute("/a/b/q", 1, 1).is_err());
+    }
+    #[test]
+    fn translates_replacements() {
+        assert_eq!(vim_replacement("\\1-\\2"), "${1}-${2}");
+        assert_eq!(vim_replacement("<&>"), "<${0}>");
+        assert_eq!(vim_replacement("\\0"), "${0}");
+        assert_eq!(vim_replacement("a\\&b"), "a&b");
+        assert_eq!(vim_replacement("a\\\\b"), "a\\b");
+        assert_eq!(vim_replacement("\\\\&"), "\\${0}");
+        assert_eq!(vim_replacement("$5"), "$$5");
+        assert_eq!(vim_replacement("\\n"), "\\n");
+        assert_eq!(vim_replacement("a\\rb"), "a\nb");
+        assert_eq!(vim_replacement("\\\\r"), "\\r");
+    }
+    #[test]
+    fn replacements_work_with_regex() {
+        let regex = regex::Regex::new("(b+)").unwrap();
+        let replace = |vim: &str| {
+            regex
+                .replace("abbc", vim_replacement(vim).as_str())
+                .to_string()
+        };
+        assert_eq!(replace("[&]"), "a[bb]c");
+        assert_eq!(replace("\\&\\1"), "a&bbc");
+        assert_eq!(replace("$"), "a$c");
+    }
+    #[test]
+    fn completes_command_names() {
+        assert_eq!(complete("s"), vec!["set", "sort", "substitute"]);
+        assert_eq!(complete("1,3so"), vec!["1,3sort"]);
+        assert_eq!(complete("wr"), vec!["write"]);
+        assert!(complete("zz").is_empty());
+    }
+    #[test]
+    fn completes_paths_after_the_command() {
+        //tests run from the crate root
+        assert_eq!(complete("e src/li"), vec!["e src/lib.rs"]);
+        assert_eq!(complete("e sr"), vec!["e src/"]);
+        assert!(complete("e src/nothing").is_empty());
+    }
+}
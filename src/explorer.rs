@@ -0,0 +1,346 @@
+use std::{
+    collections::HashSet,
+    fs::{self, read_dir},
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+use ignore::overrides::Override;
+use termion::color::{Bg, Fg, Reset, Rgb};
+
+use crate::{picker::fit, workspace};
+
+pub struct Entry {
+    pub path: PathBuf,
+    pub depth: usize,
+    pub is_dir: bool,
+}
+//The sidebar tree. Only expanded folders are read, and they are re-read
+//whenever one of their modification times changes. Paths matching the user's
+//ignore globs are left out.
+pub struct Explorer {
+    pub root: PathBuf,
+    pub entries: Vec<Entry>,
+    pub selected: usize,
+    pub width: u16,
+    scroll: usize,
+    expanded: HashSet<PathBuf>,
+    ignored: Override,
+    stamps: Vec<(PathBuf, Option<SystemTime>)>,
+    last_check: Instant,
+}
+impl Explorer {
+    #[must_use]
+    pub fn new(root: PathBuf, width: u16, ignore: &[String]) -> Explorer {
+        let mut explorer = Explorer {
+            ignored: workspace::ignored(&root, ignore),
+            root,
+            entries: Vec::new(),
+            selected: 0,
+            width,
+            scroll: 0,
+            expanded: HashSet::new(),
+            stamps: Vec::new(),
+            last_check: Instant::now(),
+        };
+        explorer.refresh();
+        explorer
+    }
+    pub fn refresh(&mut self) {
+        let selected = self.selected_path().map(Path::to_path_buf);
+        self.entries.clear();
+        self.stamps.clear();
+        let root = self.root.clone();
+        self.read(&root, 0);
+        self.expanded.retain(|dir| dir.is_dir());
+        if let Some(selected) = selected {
+            if let Some(i) = self.entries.iter().position(|e| e.path == selected) {
+                self.selected = i;
+            }
+        }
+        self.selected = self.selected.min(self.entries.len().saturating_sub(1));
+        self.last_check = Instant::now();
+    }
+    fn read(&mut self, dir: &Path, depth: usize) {
+        self.stamps.push((dir.to_path_buf(), modified(dir)));
+        let mut children: Vec<(bool, PathBuf)> = match read_dir(dir) {
+            Ok(entries) => entries
+                .filter_map(Result::ok)
+                .map(|entry| (!entry.path().is_dir(), entry.path()))
+                .filter(|(is_file, path)| !self.ignored.matched(path, !is_file).is_ignore())
+                .collect(),
+            Err(_) => return,
+        };
+        //folders first, then files, each alphabetically
+        children.sort();
+        for (is_file, path) in children {
+            self.entries.push(Entry {
+                path: path.clone(),
+                depth,
+                is_dir: !is_file,
+            });
+            if !is_file && self.expanded.contains(&path) {
+                self.read(&path, depth + 1);
+            }
+        }
+    }
+    //Re-reads the tree if a shown folder changed on disk. Checks at most once a second.
+    pub fn refresh_if_changed(&mut self) -> bool {
+        if self.last_check.elapsed() < Duration::from_secs(1) {
+            return false;
+        }
+        self.last_check = Instant::now();
+        if self
+            .stamps
+            .iter()
+            .any(|(dir, stamp)| modified(dir) != *stamp)
+        {
+            self.refresh();
+            return true;
+        }
+        false
+    }
+    #[must_use]
+    pub fn selected_path(&self) -> Option<&Path> {
+        self.entries.get(self.selected).map(|e| e.path.as_path())
+    }
+    #[must_use]
+    pub fn selected_is_dir(&self) -> bool {
+        self.entries.get(self.selected).is_some_and(|e| e.is_dir)
+    }
+    //The folder new files go in: the selected folder if it's open, otherwise the selection's parent.
+    #[must_use]
+    pub fn target_dir(&self) -> PathBuf {
+        match self.entries.get(self.selected) {
+            Some(entry) if entry.is_dir && self.expanded.contains(&entry.path) => {
+                entry.path.clone()
+            }
+            Some(entry) => entry
+                .path
+                .parent()
+                .map_or_else(|| self.root.clone(), Path::to_path_buf),
+            None => self.root.clone(),
+        }
+    }
+    #[must_use]
+    pub fn selected_row(&self) -> usize {
+        self.selected + 1 - self.scroll.min(self.selected)
+    }
+    pub fn up(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+    pub fn down(&mut self) {
+        if self.selected + 1 < self.entries.len() {
+            self.selected += 1;
+        }
+    }
+    pub fn toggle(&mut self) {
+        if let Some(entry) = self.entries.get(self.selected) {
+            if entry.is_dir && !self.expanded.remove(&entry.path) {
+                self.expanded.insert(entry.path.clone());
+            }
+        }
+        self.refresh();
+    }
+    pub fn expand(&mut self) {
+        if self.selected_is_dir() {
+            let path = self.entries[self.selected].path.clone();
+            self.expanded.insert(path);
+            self.refresh();
+        }
+    }
+    //Collapses the selected folder, or jumps to the parent folder if it's already closed.
+    pub fn collapse(&mut self) {
+        let entry = match self.entries.get(self.selected) {
+            Some(entry) => entry,
+            None => return,
+        };
+        if entry.is_dir && self.expanded.remove(&entry.path) {
+            self.refresh();
+        } else if let Some(parent) = entry.path.parent() {
+            if let Some(i) = self.entries.iter().position(|e| e.path == parent) {
+                self.selected = i;
+            }
+        }
+    }
+    //Selects the entry drawn on screen row `row`. False for the header and empty rows.
+    pub fn select_row(&mut self, row: usize) -> bool {
+        match (row + self.scroll).checked_sub(1) {
+            Some(i) if row > 0 && i < self.entries.len() => {
+                self.selected = i;
+                true
+            }
+            _ => false,
+        }
+    }
+    pub fn select(&mut self, path: &Path) {
+        if let Some(i) = self.entries.iter().position(|e| e.path == path) {
+            self.selected = i;
+        }
+    }
+    //The text of screen row `row`, exactly `width` columns wide including the border.
+    #[must_use]
+    pub fn row(&mut self, row: usize, height: usize, focused: bool) -> String {
+        let width = self.width as usize - 1;
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected + 1 >= self.scroll + height {
+            self.scroll = self.selected + 2 - height;
+        }
+        let border = format!("{}│{}", Fg(Rgb(90, 90, 90)), Fg(Reset));
+        if row == 0 {
+            let name = self
+                .root
+                .canonicalize()
+                .ok()
+                .and_then(|p| p.file_name().map(|n| n.to_string_lossy().to_string()))
+                .unwrap_or_else(|| self.root.display().to_string());
+            return format!("{}{}", fit(&format!(" {}", name), width), border);
+        }
+        let i = row - 1 + self.scroll;
+        let entry = match self.entries.get(i) {
+            Some(entry) => entry,
+            None => return format!("{}{}", fit("", width), border),
+        };
+        let name = entry
+            .path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let marker = match (entry.is_dir, self.expanded.contains(&entry.path)) {
+            (true, true) => "▾ ",
+            (true, false) => "▸ ",
+            _ => "  ",
+        };
+        let text = fit(
+            &format!("{}{}{}", "  ".repeat(entry.depth), marker, name),
+            width,
+        );
+        if i == self.selected {
+            let background = if focused {
+                Rgb(12, 145, 194)
+            } else {
+                Rgb(60, 60, 60)
+            };
+            format!("{}{}{}{}", Bg(background), text, Bg(Reset), border)
+        } else {
+            format!("{}{}", text, border)
+        }
+    }
+}
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, process};
+
+    //A fresh folder holding `files`, with the folders they need.
+    fn tree(name: &str, files: &[&str]) -> PathBuf {
+        let root = env::temp_dir().join(format!("saphire-explorer-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for file in files {
+            let path = root.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "").unwrap();
+        }
+        root
+    }
+    //The entries as they are drawn: indented names with a marker on folders.
+    fn shown(explorer: &Explorer) -> Vec<String> {
+        explorer
+            .entries
+            .iter()
+            .map(|entry| {
+                let name = entry.path.file_name().unwrap().to_string_lossy();
+                let slash = if entry.is_dir { "/" } else { "" };
+                format!("{}{}{}", "  ".repeat(entry.depth), name, slash)
+            })
+            .collect()
+    }
+    #[test]
+    fn lists_folders_first_and_only_reads_expanded_ones() {
+        let root = tree(
+            "build",
+            &["b.txt", "a.txt", "src/main.rs", "src/x/y.rs", "doc/z"],
+        );
+        let mut explorer = Explorer::new(root.clone(), 30, &[]);
+        assert_eq!(shown(&explorer), ["doc/", "src/", "a.txt", "b.txt"]);
+        explorer.select(&root.join("src"));
+        explorer.expand();
+        assert_eq!(
+            shown(&explorer),
+            ["doc/", "src/", "  x/", "  main.rs", "a.txt", "b.txt"]
+        );
+        explorer.down();
+        explorer.toggle();
+        assert_eq!(explorer.selected_path(), Some(root.join("src/x").as_path()));
+        assert_eq!(explorer.target_dir(), root.join("src/x"));
+        assert_eq!(shown(&explorer)[3], "    y.rs");
+        //a new file shows up once its folder is read again
+        fs::write(root.join("src/new.rs"), "").unwrap();
+        explorer.refresh();
+        assert_eq!(shown(&explorer)[5], "  new.rs");
+        fs::remove_dir_all(root).unwrap();
+    }
+    #[test]
+    fn collapses_folders_and_steps_out_to_the_parent() {
+        let root = tree("collapse", &["src/lib.rs", "src/x/y.rs", "z.txt"]);
+        let mut explorer = Explorer::new(root.clone(), 30, &[]);
+        explorer.toggle();
+        explorer.down();
+        explorer.expand();
+        assert_eq!(
+            shown(&explorer),
+            ["src/", "  x/", "    y.rs", "  lib.rs", "z.txt"]
+        );
+        explorer.down();
+        assert_eq!(explorer.target_dir(), root.join("src/x"));
+        //from a file it goes to its folder, then closes that folder
+        explorer.collapse();
+        assert_eq!(explorer.selected_path(), Some(root.join("src/x").as_path()));
+        explorer.collapse();
+        assert_eq!(shown(&explorer), ["src/", "  x/", "  lib.rs", "z.txt"]);
+        assert_eq!(explorer.target_dir(), root.join("src"));
+        explorer.collapse();
+        explorer.collapse();
+        assert_eq!(explorer.selected, 0);
+        assert_eq!(shown(&explorer), ["src/", "z.txt"]);
+        //reopening remembers the folders opened inside it
+        explorer.expand();
+        assert_eq!(shown(&explorer).len(), 4);
+        //the selection follows its entry when the tree is read again
+        explorer.select(&root.join("z.txt"));
+        fs::remove_dir_all(root.join("src")).unwrap();
+        explorer.refresh();
+        assert_eq!(shown(&explorer), ["z.txt"]);
+        assert_eq!(explorer.selected_path(), Some(root.join("z.txt").as_path()));
+        explorer.collapse();
+        explorer.up();
+        assert_eq!(explorer.selected, 0);
+        fs::remove_dir_all(root).unwrap();
+    }
+    #[test]
+    fn leaves_out_ignored_paths() {
+        let root = tree(
+            "ignore",
+            &[
+                "target/debug/a",
+                "src/target.rs",
+                "src/a.log",
+                "keep.log",
+                ".git/HEAD",
+            ],
+        );
+        let ignore = [
+            ".git".to_string(),
+            "target".to_string(),
+            "src/*.log".to_string(),
+        ];
+        let mut explorer = Explorer::new(root.clone(), 30, &ignore);
+        explorer.expand();
+        assert_eq!(shown(&explorer), ["src/", "  target.rs", "keep.log"]);
+        fs::remove_dir_all(root).unwrap();
+    }
+}
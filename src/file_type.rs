@@ -1,8 +1,61 @@
 use std::{path::PathBuf, vec};
 
+use crate::snippet::Snippet;
+
 pub struct FileType {
     pub name: String,
     pub highlight_ops: HighlightingOptions,
+    pub indent: IndentRules,
+    //what auto-pairing closes, opener first
+    pub pairs: Vec<(String, String)>,
+    pub comments: CommentTokens,
+    //the command that runs its language server, unless the [lsp] config section says otherwise
+    pub language_server: Option<String>,
+    //the command that formats it from stdin to stdout, unless the [format] config section says otherwise
+    pub formatter: Option<String>,
+    //built in, before the ones from the user's snippet file
+    pub snippets: Vec<Snippet>,
+}
+#[derive(Default, Clone)]
+pub struct CommentTokens {
+    pub line: Option<String>,
+    //open and close
+    pub block: Option<(String, String)>,
+}
+//How new lines are indented.
+#[derive(Clone)]
+pub struct IndentRules {
+    //a line ending in one of these indents the next one a level deeper
+    pub openers: Vec<String>,
+    //typing one of these first on a line takes it a level back out
+    pub closers: Vec<String>,
+}
+//Snippets from (prefix, description, body).
+fn snippets(snippets: &[(&str, &str, &str)]) -> Vec<Snippet> {
+    snippets
+        .iter()
+        .map(|&(prefix, description, body)| Snippet {
+            prefix: prefix.to_string(),
+            description: description.to_string(),
+            body: body.to_string(),
+        })
+        .collect()
+}
+fn pairs(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+    pairs
+        .iter()
+        .map(|&(open, close)| (open.to_string(), close.to_string()))
+        .collect()
+}
+const BRACKETS_AND_QUOTES: [(&str, &str); 5] =
+    [("(", ")"), ("[", "]"), ("{", "}"), ("\"", "\""), ("'", "'")];
+impl Default for IndentRules {
+    fn default() -> Self {
+        IndentRules {
+            openers: vec!["{".to_string(), "(".to_string(), "[".to_string()],
+            closers: vec!["}".to_string(), ")".to_string(), "]".to_string()],
+        }
+    }
 }
 #[derive(Default, Clone)]
 pub struct HighlightingOptions {
@@ -20,6 +73,12 @@ impl Default for FileType {
         FileType {
             name: String::from("No Filetype"),
             highlight_ops: (HighlightingOptions::default()),
+            indent: IndentRules::default(),
+            pairs: pairs(&BRACKETS_AND_QUOTES),
+            comments: CommentTokens::default(),
+            language_server: None,
+            formatter: None,
+            snippets: Vec::new(),
         }
     }
 }
@@ -89,6 +148,98 @@ impl From<PathBuf> for FileType {
                                 "isize".to_string(),
                             ],
                         },
+                        indent: IndentRules::default(),
+                        //' starts lifetimes as often as chars
+                        pairs: pairs(&BRACKETS_AND_QUOTES[..4]),
+                        comments: CommentTokens {
+                            line: Some("//".to_string()),
+                            block: Some(("/*".to_string(), "*/".to_string())),
+                        },
+                        language_server: Some("rust-analyzer".to_string()),
+                        formatter: Some("rustfmt --edition 2021".to_string()),
+                        snippets: snippets(&[
+                            ("fn", "function", "fn ${1:name}($2) {\n\t$0\n}"),
+                            (
+                                "impl",
+                                "trait implementation",
+                                "impl ${1:Trait} for ${2:Type} {\n\t$0\n}",
+                            ),
+                            (
+                                "test",
+                                "test function",
+                                "#[test]\nfn ${1:name}() {\n\t$0\n}",
+                            ),
+                            (
+                                "match",
+                                "match with a catch-all arm",
+                                "match ${1:value} {\n\t${2:pattern} => $3,\n\t_ => $0,\n}",
+                            ),
+                        ]),
+                    },
+                    "py" => FileType {
+                        name: "Python".to_string(),
+                        highlight_ops: HighlightingOptions {
+                            numbers: true,
+                            strings: true,
+                            key_words: vec![
+                                "def".to_string(),
+                                "class".to_string(),
+                                "import".to_string(),
+                                "from".to_string(),
+                                "as".to_string(),
+                                "if".to_string(),
+                                "elif".to_string(),
+                                "else".to_string(),
+                                "for".to_string(),
+                                "while".to_string(),
+                                "in".to_string(),
+                                "return".to_string(),
+                                "with".to_string(),
+                                "try".to_string(),
+                                "except".to_string(),
+                                "finally".to_string(),
+                                "lambda".to_string(),
+                                "pass".to_string(),
+                                "break".to_string(),
+                                "continue".to_string(),
+                            ],
+                            types: vec![
+                                "None".to_string(),
+                                "True".to_string(),
+                                "False".to_string(),
+                                "self".to_string(),
+                            ],
+                            ..HighlightingOptions::default()
+                        },
+                        indent: IndentRules {
+                            openers: vec![
+                                ":".to_string(),
+                                "{".to_string(),
+                                "(".to_string(),
+                                "[".to_string(),
+                            ],
+                            ..IndentRules::default()
+                        },
+                        pairs: pairs(&BRACKETS_AND_QUOTES),
+                        comments: CommentTokens {
+                            line: Some("#".to_string()),
+                            block: None,
+                        },
+                        language_server: Some("pylsp".to_string()),
+                        formatter: Some("black --quiet -".to_string()),
+                        snippets: snippets(&[
+                            ("def", "function", "def ${1:name}($2):\n\t${0:pass}"),
+                            (
+                                "class",
+                                "class",
+                                "class ${1:Name}:\n\tdef __init__(self$2):\n\t\t${0:pass}",
+                            ),
+                            (
+                                "main",
+                                "run when executed as a script",
+                                "if __name__ == \"__main__\":\n\t${0:main()}",
+                            ),
+                        ]),
                     },
                     _ => Self::default(),
                 }
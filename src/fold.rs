@@ -0,0 +1,246 @@
+use crate::{
+    document::{quoted, Document},
+    Position,
+};
+
+//The rows a fold headed by row `y` would hide, as the last of them. A row that
+//opens a bracket closed further down folds up to the row before the closer, so
+//the closer stays in view. Otherwise the rows indented deeper than `y` below it
+//fold under it. Rows are 1-based.
+#[must_use]
+pub fn region(document: &Document, y: usize) -> Option<usize> {
+    let row = document.rows.get(y.checked_sub(1)?)?;
+    if row.is_blank() {
+        return None;
+    }
+    let bracket = (0..row.content.len())
+        .rev()
+        .filter(|&x| matches!(row.content[x].as_str(), "(" | "[" | "{") && !quoted(row, x))
+        .find_map(|x| {
+            let partner = document.matching_bracket(&Position { x, y })?;
+            (partner.y > y).then_some(partner.y)
+        });
+    if let Some(close) = bracket {
+        return (close - 1 > y).then_some(close - 1);
+    }
+    let indent = row.first_non_blank();
+    let mut end = y;
+    for (i, below) in document.rows.iter().enumerate().skip(y) {
+        if below.is_blank() {
+            continue;
+        }
+        if below.first_non_blank() <= indent {
+            break;
+        }
+        end = i + 1;
+    }
+    (end > y).then_some(end)
+}
+//The folds that are closed, as (header row, last hidden row). The header stays
+//in view and stands for the whole fold.
+#[derive(Default)]
+pub struct Folds {
+    closed: Vec<(usize, usize)>,
+    //the document revision `regions` holds the regions of, and for each row
+    //the region once it has been asked for
+    revision: usize,
+    regions: Vec<Option<Option<usize>>>,
+}
+impl Folds {
+    //The `region` of row `y`, worked out once per revision of the document.
+    pub fn region(&mut self, document: &Document, y: usize) -> Option<usize> {
+        if self.revision != document.revision() {
+            self.revision = document.revision();
+            self.regions.clear();
+        }
+        self.regions.resize(document.rows.len(), None);
+        let cached = self.regions.get_mut(y.checked_sub(1)?)?;
+        *cached.get_or_insert_with(|| region(document, y))
+    }
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.closed.is_empty()
+    }
+    //Whether row `y` heads a closed fold.
+    #[must_use]
+    pub fn is_closed(&self, y: usize) -> bool {
+        self.closed.iter().any(|&(header, _)| header == y)
+    }
+    #[must_use]
+    pub fn hidden(&self, y: usize) -> bool {
+        self.closed
+            .iter()
+            .any(|&(header, end)| header < y && y <= end)
+    }
+    pub fn close(&mut self, header: usize, end: usize) {
+        if !self.is_closed(header) {
+            self.closed.push((header, end));
+        }
+    }
+    //Opens the fold headed by `y`. False if there wasn't one.
+    pub fn open(&mut self, y: usize) -> bool {
+        let before = self.closed.len();
+        self.closed.retain(|&(header, _)| header != y);
+        self.closed.len() != before
+    }
+    //Opens every fold that hides row `y`.
+    pub fn reveal(&mut self, y: usize) {
+        self.closed
+            .retain(|&(header, end)| !(header < y && y <= end));
+    }
+    pub fn clear(&mut self) {
+        self.closed.clear();
+    }
+    //The first row at or after `y` that isn't hidden.
+    #[must_use]
+    pub fn visible_from(&self, mut y: usize) -> usize {
+        while let Some(end) = self
+            .closed
+            .iter()
+            .filter(|&&(header, end)| header < y && y <= end)
+            .map(|&(_, end)| end)
+            .max()
+        {
+            y = end + 1;
+        }
+        y
+    }
+    //The row drawn below `y`, if the document has one.
+    #[must_use]
+    pub fn next(&self, y: usize, rows: usize) -> Option<usize> {
+        let next = self.visible_from(y + 1);
+        (next <= rows).then_some(next)
+    }
+    //The row drawn above `y`, if there is one.
+    #[must_use]
+    pub fn previous(&self, y: usize) -> Option<usize> {
+        let mut previous = y.checked_sub(1).filter(|&y| y > 0)?;
+        while let Some(header) = self
+            .closed
+            .iter()
+            .filter(|&&(header, end)| header < previous && previous <= end)
+            .map(|&(header, _)| header)
+            .min()
+        {
+            previous = header;
+        }
+        Some(previous)
+    }
+    //How many rows are drawn from `from` to `to`, both included.
+    #[must_use]
+    pub fn count(&self, from: usize, to: usize) -> usize {
+        (from..=to).filter(|&y| !self.hidden(y)).count()
+    }
+    //Keeps the folds in step with an edit that replaced rows start..=end with
+    //start..=new_end. Folds the edit touches open, the ones below it move.
+    pub fn edited(&mut self, start: usize, end: usize, new_end: usize) {
+        self.closed.retain_mut(|(header, last)| {
+            if *last < start {
+                true
+            } else if end < *header {
+                *header = *header + new_end - end;
+                *last = *last + new_end - end;
+                true
+            } else {
+                //typing on the header row alone leaves the fold be
+                start == *header && end == *header && new_end == *header
+            }
+        });
+    }
+}
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::file_type::FileType;
+
+    fn rust(text: &str) -> Document {
+        let mut document = Document::new(None, 4);
+        document.file_type = FileType::from(PathBuf::from("a.rs"));
+        document.set_contents(text, 4);
+        document
+    }
+    #[test]
+    fn brackets_fold_up_to_the_row_before_the_closer() {
+        let document = rust("fn a() {\n    b();\n    c();\n}\n");
+        assert_eq!(region(&document, 1), Some(3));
+        assert_eq!(region(&document, 2), None);
+        assert_eq!(region(&document, 4), None);
+    }
+    #[test]
+    fn a_bracket_closed_on_the_next_row_hides_nothing() {
+        let document = rust("fn a() {\n}\n");
+        assert_eq!(region(&document, 1), None);
+    }
+    #[test]
+    fn brackets_in_strings_and_comments_do_not_count() {
+        let document = rust("let a = \"{\";\nb();\n// {\nc();\n}\n");
+        assert_eq!(region(&document, 1), None);
+        assert_eq!(region(&document, 3), None);
+    }
+    #[test]
+    fn deeper_indentation_folds_without_brackets() {
+        let document = rust("def a:\n    b\n\n    c\nd\n");
+        assert_eq!(region(&document, 1), Some(4));
+        assert_eq!(region(&document, 3), None);
+        assert_eq!(region(&document, 5), None);
+        assert_eq!(region(&document, 0), None);
+        assert_eq!(region(&document, 9), None);
+    }
+    #[test]
+    fn regions_are_kept_until_the_document_changes() {
+        let mut document = rust("a {\n    b\n}\n");
+        let mut folds = Folds::default();
+        assert_eq!(folds.region(&document, 1), Some(2));
+        let start = Position { x: 0, y: 2 };
+        document.replace(&start, &start, "    x\n");
+        assert_eq!(folds.region(&document, 1), Some(3));
+        assert_eq!(folds.region(&document, 4), None);
+    }
+    #[test]
+    fn closed_folds_hide_their_rows() {
+        let mut folds = Folds::default();
+        folds.close(2, 4);
+        folds.close(2, 5);
+        assert!(folds.is_closed(2));
+        assert!(!folds.hidden(2));
+        assert!(folds.hidden(3) && folds.hidden(4));
+        assert!(!folds.hidden(5));
+        assert_eq!(folds.visible_from(3), 5);
+        assert_eq!(folds.next(2, 10), Some(5));
+        assert_eq!(folds.next(10, 10), None);
+        assert_eq!(folds.previous(5), Some(2));
+        assert_eq!(folds.previous(1), None);
+        assert_eq!(folds.count(1, 6), 4);
+        assert!(folds.open(2));
+        assert!(!folds.open(2));
+        assert!(folds.is_empty());
+    }
+    #[test]
+    fn nested_folds_hide_to_the_outer_end() {
+        let mut folds = Folds::default();
+        folds.close(3, 4);
+        folds.close(1, 6);
+        assert_eq!(folds.visible_from(2), 7);
+        assert_eq!(folds.previous(7), Some(1));
+        folds.reveal(4);
+        assert!(folds.is_empty());
+    }
+    #[test]
+    fn edits_move_or_open_folds() {
+        let mut folds = Folds::default();
+        folds.close(5, 7);
+        folds.close(10, 12);
+        //two rows added above both
+        folds.edited(2, 2, 4);
+        assert!(folds.is_closed(7) && folds.is_closed(12));
+        //typing on a header leaves its fold closed
+        folds.edited(7, 7, 7);
+        assert!(folds.is_closed(7));
+        //an edit inside a fold opens it
+        folds.edited(8, 8, 9);
+        assert!(!folds.is_closed(7));
+        assert!(folds.is_closed(13));
+    }
+}
@@ -0,0 +1,91 @@
+//Scores `candidate` against `pattern` as a case-insensitive subsequence match.
+//Returns None if some character of the pattern is missing. Higher is better:
+//consecutive matches and matches at the start of a word are rewarded, gaps are penalised.
+#[must_use]
+#[allow(clippy::cast_possible_wrap)]
+pub fn score(pattern: &str, candidate: &str) -> Option<i64> {
+    if pattern.is_empty() {
+        return Some(0);
+    }
+    let pattern: Vec<char> = pattern.chars().flat_map(char::to_lowercase).collect();
+    let chars: Vec<char> = candidate.chars().collect();
+    let mut score = 0;
+    let mut p = 0;
+    let mut last_match: Option<usize> = None;
+    for (i, c) in chars.iter().enumerate() {
+        if p == pattern.len() {
+            break;
+        }
+        if c.to_lowercase().eq(pattern[p].to_lowercase()) {
+            score += 10;
+            if i == 0 || !chars[i - 1].is_alphanumeric() {
+                score += 15;
+            } else if chars[i - 1].is_lowercase() && c.is_uppercase() {
+                score += 10;
+            }
+            match last_match {
+                Some(last) if last + 1 == i => score += 20,
+                Some(last) => score -= (i - last) as i64,
+                None => score -= i as i64,
+            }
+            last_match = Some(i);
+            p += 1;
+        }
+    }
+    if p == pattern.len() {
+        Some(score - (chars.len() as i64) / 4)
+    } else {
+        None
+    }
+}
+//Returns the indices of `candidates` that match `pattern`, best match first.
+//Ties keep the original order so callers can pre-sort by recency.
+#[must_use]
+pub fn filter<S: AsRef<str>>(pattern: &str, candidates: &[S]) -> Vec<usize> {
+    let mut scored: Vec<(usize, i64)> = candidates
+        .iter()
+        .enumerate()
+        .filter_map(|(i, c)| score(pattern, c.as_ref()).map(|s| (i, s)))
+        .collect();
+    scored.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    scored.into_iter().map(|(i, _)| i).collect()
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_subsequences_ignoring_case() {
+        assert!(score("fb", "FooBar").is_some());
+        assert!(score("FOO", "foo").is_some());
+        assert!(score("bf", "FooBar").is_none());
+        assert!(score("x", "").is_none());
+        assert_eq!(score("", "anything"), Some(0));
+    }
+    #[test]
+    fn prefers_consecutive_matches() {
+        assert!(score("abc", "abcxx") > score("abc", "axbxc"));
+    }
+    #[test]
+    fn prefers_word_starts() {
+        assert!(score("of", "open_file") > score("of", "proof"));
+        assert!(score("of", "openFile") > score("of", "toffee"));
+    }
+    #[test]
+    fn prefers_earlier_and_shorter_candidates() {
+        assert!(score("a", "ab") > score("a", "ba"));
+        assert!(score("abc", "abc") > score("abc", "abc_with_a_long_tail"));
+    }
+    #[test]
+    fn filters_best_first() {
+        let candidates = ["src/editor.rs", "README", "src/document.rs", "edit"];
+        assert_eq!(filter("edit", &candidates), vec![3, 0]);
+        assert!(filter("zzz", &candidates).is_empty());
+    }
+    #[test]
+    fn filter_keeps_the_order_of_ties() {
+        let candidates = ["b", "a", "c"];
+        assert_eq!(filter("", &candidates), vec![0, 1, 2]);
+        assert_eq!(filter("x", &["ax", "bx"]), vec![0, 1]);
+    }
+}
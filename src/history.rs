@@ -0,0 +1,50 @@
+use std::{
+    env,
+    fs::{create_dir_all, read_to_string, write},
+    path::PathBuf,
+};
+
+const MAX_ENTRIES: usize = 200;
+
+//Input history kept across sessions in the data directory, one entry per line, oldest first.
+pub struct History {
+    pub entries: Vec<String>,
+    path: Option<PathBuf>,
+}
+impl History {
+    #[must_use]
+    pub fn load(name: &str) -> History {
+        let path = data_dir().map(|dir| dir.join(format!("{}_history", name)));
+        let entries = path
+            .as_ref()
+            .and_then(|path| read_to_string(path).ok())
+            .map(|content| content.lines().map(str::to_string).collect())
+            .unwrap_or_default();
+        History { entries, path }
+    }
+    pub fn push(&mut self, entry: &str) {
+        if entry.is_empty() || entry.contains('\n') {
+            return;
+        }
+        self.entries.retain(|e| e != entry);
+        self.entries.push(entry.to_string());
+        if self.entries.len() > MAX_ENTRIES {
+            self.entries.remove(0);
+        }
+        if let Some(path) = &self.path {
+            if let Some(dir) = path.parent() {
+                let _ = create_dir_all(dir);
+            }
+            if let Err(e) = write(path, self.entries.join("\n") + "\n") {
+                log::warn!("couldn't save history: {}", e);
+            }
+        }
+    }
+}
+#[must_use]
+pub fn data_dir() -> Option<PathBuf> {
+    if let Some(dir) = env::var_os("XDG_DATA_HOME") {
+        return Some(PathBuf::from(dir).join("saphire"));
+    }
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share/saphire"))
+}
@@ -0,0 +1,283 @@
+use termion::event::Key;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+use crate::{
+    events::{Arrow, KeyPress},
+    terminal::Terminal,
+};
+
+//Returns every full replacement for the current input, e.g. ex::complete_path.
+pub type Completer = fn(&str) -> Vec<String>;
+pub enum InputEvent {
+    Submit(String),
+    Cancel,
+    Continue,
+}
+//A single line text field. Content is kept as graphemes so the cursor never
+//lands inside a character, and drawn by display width so wide characters fit.
+pub struct LineInput {
+    pub content: Vec<String>,
+    pub cursor: usize,
+    scroll: usize,
+    history: Vec<String>,
+    history_index: usize,
+    draft: String,
+    completer: Option<Completer>,
+    completions: Vec<String>,
+    completion_index: usize,
+}
+impl LineInput {
+    #[must_use]
+    pub fn new(history: Vec<String>, completer: Option<Completer>) -> LineInput {
+        LineInput {
+            content: Vec::new(),
+            cursor: 0,
+            scroll: 0,
+            history_index: history.len(),
+            history,
+            draft: String::new(),
+            completer,
+            completions: Vec::new(),
+            completion_index: 0,
+        }
+    }
+    #[must_use]
+    pub fn text(&self) -> String {
+        self.content.concat()
+    }
+    pub fn set_text(&mut self, text: &str) {
+        self.content = text.graphemes(true).map(str::to_string).collect();
+        self.cursor = self.content.len();
+    }
+    pub fn handle(&mut self, press: KeyPress) -> InputEvent {
+        let key = press.key();
+        if key != Key::Char('\t') {
+            self.completions.clear();
+        }
+        match press {
+            KeyPress::Ctrl(Arrow::Left) => {
+                self.cursor = self.word_start();
+                return InputEvent::Continue;
+            }
+            KeyPress::Ctrl(Arrow::Right) => {
+                self.cursor = self.word_end();
+                return InputEvent::Continue;
+            }
+            _ => (),
+        }
+        match key {
+            Key::Esc => return InputEvent::Cancel,
+            Key::Char('\n') => return InputEvent::Submit(self.text()),
+            Key::Char('\t') => self.complete(),
+            Key::Left | Key::Ctrl('b') => self.cursor = self.cursor.saturating_sub(1),
+            Key::Right | Key::Ctrl('f') => self.cursor = (self.cursor + 1).min(self.content.len()),
+            Key::Home | Key::Ctrl('a') => self.cursor = 0,
+            Key::End | Key::Ctrl('e') => self.cursor = self.content.len(),
+            Key::Alt('b') => self.cursor = self.word_start(),
+            Key::Alt('f') => self.cursor = self.word_end(),
+            Key::Backspace if self.cursor > 0 => {
+                self.cursor -= 1;
+                self.content.remove(self.cursor);
+            }
+            Key::Delete | Key::Ctrl('d') if self.cursor < self.content.len() => {
+                self.content.remove(self.cursor);
+            }
+            Key::Ctrl('w') | Key::Alt('\x7f') => {
+                let start = self.word_start();
+                self.content.drain(start..self.cursor);
+                self.cursor = start;
+            }
+            Key::Alt('d') => {
+                let end = self.word_end();
+                self.content.drain(self.cursor..end);
+            }
+            Key::Ctrl('u') => {
+                self.content.drain(..self.cursor);
+                self.cursor = 0;
+            }
+            Key::Ctrl('k') => self.content.truncate(self.cursor),
+            Key::Up | Key::Ctrl('p') if self.history_index > 0 => {
+                if self.history_index == self.history.len() {
+                    self.draft = self.text();
+                }
+                self.history_index -= 1;
+                let entry = self.history[self.history_index].clone();
+                self.set_text(&entry);
+            }
+            Key::Down | Key::Ctrl('n') if self.history_index < self.history.len() => {
+                self.history_index += 1;
+                let entry = self
+                    .history
+                    .get(self.history_index)
+                    .cloned()
+                    .unwrap_or_else(|| self.draft.clone());
+                self.set_text(&entry);
+            }
+            Key::Char(c) if !c.is_control() => {
+                let rest = self.content.split_off(self.cursor);
+                let mut text = self.content.concat();
+                text.push(c);
+                //Re-segment so combining characters join the grapheme before them.
+                self.content = text.graphemes(true).map(str::to_string).collect();
+                self.cursor = self.content.len();
+                self.content.extend(rest);
+            }
+            _ => (),
+        }
+        InputEvent::Continue
+    }
+    fn complete(&mut self) {
+        let completer = match self.completer {
+            Some(completer) => completer,
+            None => return,
+        };
+        if self.completions.is_empty() {
+            self.completions = completer(&self.text());
+            self.completion_index = 0;
+        } else {
+            self.completion_index = (self.completion_index + 1) % self.completions.len();
+        }
+        if let Some(completion) = self.completions.get(self.completion_index).cloned() {
+            self.set_text(&completion);
+        }
+    }
+    fn is_word(&self, i: usize) -> bool {
+        self.content[i].chars().any(char::is_alphanumeric)
+    }
+    fn word_start(&self) -> usize {
+        let mut i = self.cursor;
+        while i > 0 && !self.is_word(i - 1) {
+            i -= 1;
+        }
+        while i > 0 && self.is_word(i - 1) {
+            i -= 1;
+        }
+        i
+    }
+    fn word_end(&self) -> usize {
+        let mut i = self.cursor;
+        while i < self.content.len() && !self.is_word(i) {
+            i += 1;
+        }
+        while i < self.content.len() && self.is_word(i) {
+            i += 1;
+        }
+        i
+    }
+    //Draws `label` and the visible part of the input on row `y`, scrolling horizontally
+    //to keep the cursor on screen. Returns the screen column of the cursor.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn draw(&mut self, label: &str, y: u16, width: u16) -> u16 {
+        let label_width = label.width();
+        let room = (width as usize).saturating_sub(label_width + 1).max(1);
+        if self.cursor < self.scroll {
+            self.scroll = self.cursor;
+        }
+        while self.content[self.scroll..self.cursor].concat().width() >= room {
+            self.scroll += 1;
+        }
+        let mut visible = String::new();
+        let mut used = 0;
+        for gr in &self.content[self.scroll..] {
+            if used + gr.width() > room {
+                break;
+            }
+            used += gr.width();
+            visible.push_str(gr);
+        }
+        Terminal::move_cursor(0, y);
+        Terminal::clear_row();
+        print!("{}{}", label, visible);
+        (label_width + self.content[self.scroll..self.cursor].concat().width()) as u16
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(input: &mut LineInput, keys: &[Key]) {
+        for key in keys {
+            input.handle(KeyPress::Key(*key));
+        }
+    }
+    fn typed(text: &str) -> LineInput {
+        let mut input = LineInput::new(Vec::new(), None);
+        for c in text.chars() {
+            input.handle(KeyPress::Key(Key::Char(c)));
+        }
+        input
+    }
+    #[test]
+    fn moves_the_cursor() {
+        let mut input = typed("one two");
+        keys(&mut input, &[Key::Left, Key::Ctrl('b')]);
+        assert_eq!(input.cursor, 5);
+        keys(&mut input, &[Key::Home, Key::Left]);
+        assert_eq!(input.cursor, 0);
+        keys(&mut input, &[Key::Right, Key::End, Key::Ctrl('f')]);
+        assert_eq!(input.cursor, 7);
+        input.handle(KeyPress::Ctrl(Arrow::Left));
+        assert_eq!(input.cursor, 4);
+        keys(&mut input, &[Key::Alt('b')]);
+        assert_eq!(input.cursor, 0);
+        input.handle(KeyPress::Ctrl(Arrow::Right));
+        assert_eq!(input.cursor, 3);
+        keys(&mut input, &[Key::Alt('f'), Key::Alt('f')]);
+        assert_eq!(input.cursor, 7);
+    }
+    #[test]
+    fn deletes_words_and_line_ends() {
+        let mut input = typed("cp a.txt  b");
+        keys(&mut input, &[Key::Ctrl('w')]);
+        assert_eq!(input.text(), "cp a.txt  ");
+        //punctuation and spaces before the cursor go with the word before them
+        keys(&mut input, &[Key::Alt('\x7f')]);
+        assert_eq!(input.text(), "cp a.");
+        keys(&mut input, &[Key::Home, Key::Alt('d')]);
+        assert_eq!((input.text().as_str(), input.cursor), (" a.", 0));
+        keys(&mut input, &[Key::Right, Key::Ctrl('k')]);
+        assert_eq!(input.text(), " ");
+        keys(&mut input, &[Key::End, Key::Ctrl('u')]);
+        assert_eq!((input.text().as_str(), input.cursor), ("", 0));
+        keys(&mut input, &[Key::Backspace, Key::Delete, Key::Ctrl('w')]);
+        assert_eq!(input.text(), "");
+    }
+    #[test]
+    fn recalls_history_and_keeps_the_draft() {
+        let history = vec!["first".to_string(), "second".to_string()];
+        let mut input = LineInput::new(history, None);
+        input.set_text("dra");
+        keys(&mut input, &[Key::Up]);
+        assert_eq!((input.text().as_str(), input.cursor), ("second", 6));
+        keys(&mut input, &[Key::Ctrl('p'), Key::Up]);
+        assert_eq!(input.text(), "first");
+        keys(&mut input, &[Key::Down]);
+        assert_eq!(input.text(), "second");
+        keys(&mut input, &[Key::Ctrl('n'), Key::Down]);
+        assert_eq!(input.text(), "dra");
+        assert!(matches!(
+            input.handle(KeyPress::Key(Key::Char('\n'))),
+            InputEvent::Submit(text) if text == "dra"
+        ));
+        assert!(matches!(
+            input.handle(KeyPress::Key(Key::Esc)),
+            InputEvent::Cancel
+        ));
+    }
+    #[test]
+    fn edits_whole_graphemes() {
+        let mut input = typed("añ日👍🏽");
+        assert_eq!(input.content, ["a", "ñ", "日", "👍🏽"]);
+        keys(&mut input, &[Key::Left, Key::Backspace]);
+        assert_eq!(input.text(), "añ👍🏽");
+        keys(&mut input, &[Key::Home, Key::Right, Key::Delete]);
+        assert_eq!(input.text(), "a👍🏽");
+        //a combining accent joins the character before it
+        input.set_text("e");
+        keys(&mut input, &[Key::Char('\u{301}'), Key::Char('x')]);
+        assert_eq!(input.content, ["e\u{301}", "x"]);
+        keys(&mut input, &[Key::Home, Key::Alt('d')]);
+        assert_eq!(input.text(), "");
+    }
+}
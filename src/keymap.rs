@@ -0,0 +1,335 @@
+use termion::event::Key;
+
+use crate::{
+    command::Command,
+    config::Config,
+    events::{Arrow, KeyPress},
+};
+
+const DEFAULT_BINDINGS: &[(&str, &str)] = &[
+    ("ctrl+q", "quit"),
+    ("ctrl+s", "save"),
+    ("ctrl+f", "search"),
+    ("ctrl+d", "inner_words"),
+    ("ctrl+n", "next_message"),
+    ("ctrl+p", "command_palette"),
+    ("ctrl+e", "command_line"),
+    ("ctrl+o", "open_file"),
+    ("ctrl+g", "grep"),
+    ("ctrl+b", "toggle_explorer"),
+    ("ctrl+left", "word_left"),
+    ("ctrl+right", "word_right"),
+    ("ctrl+up", "paragraph_up"),
+    ("ctrl+down", "paragraph_down"),
+    ("alt+m", "matching_bracket"),
+    ("ctrl+l", "goto_line"),
+    ("ctrl+z", "undo"),
+    ("ctrl+y", "redo"),
+    ("ctrl+c", "copy"),
+    ("ctrl+x", "cut"),
+    ("ctrl+v", "paste"),
+    ("alt+k", "add_cursor_above"),
+    ("alt+j", "add_cursor_below"),
+    ("alt+n", "add_next_occurrence"),
+    ("alt+i", "cursors_on_lines"),
+    ("shift+tab", "outdent"),
+    ("ctrl+/", "toggle_comment"),
+    ("alt+-", "fold"),
+    ("alt+=", "unfold"),
+    ("alt+0", "toggle_folds"),
+    ("alt+h", "hover"),
+    ("f12", "goto_definition"),
+    ("alt+r", "find_references"),
+    ("f2", "rename"),
+    ("alt+a", "code_action"),
+    ("ctrl+space", "complete"),
+    ("alt+q", "format"),
+    ("alt+|", "filter"),
+];
+pub enum Lookup {
+    Command(Command),
+    Prefix,
+    None,
+}
+pub struct Keymap {
+    pub bindings: Vec<(Vec<KeyPress>, Command)>,
+}
+impl Keymap {
+    //Builds the default keymap and applies the [keys] section of the config on top of it.
+    //Returns every problem found so the editor can report them at startup.
+    #[must_use]
+    pub fn new(config: &Config) -> (Keymap, Vec<String>) {
+        let mut keymap = Keymap {
+            bindings: Vec::new(),
+        };
+        let mut problems = Vec::new();
+        for (keys, name) in DEFAULT_BINDINGS {
+            keymap.bind(parse_keys(keys).unwrap(), Command::from_name(name).unwrap());
+        }
+        let mut user: Vec<(Vec<KeyPress>, Command)> = Vec::new();
+        for (keys, name) in config.section("keys") {
+            let sequence = if let Some(sequence) = parse_keys(keys) {
+                sequence
+            } else {
+                problems.push(format!("keymap: can't parse key sequence '{}'", keys));
+                continue;
+            };
+            let command = if let Some(command) = Command::from_name(name) {
+                command
+            } else {
+                problems.push(format!("keymap: unknown command '{}'", name));
+                continue;
+            };
+            if let Some((_, other)) = user.iter().find(|(k, c)| *k == sequence && *c != command) {
+                problems.push(format!(
+                    "keymap: {} is bound to both {} and {}",
+                    keys_to_string(&sequence),
+                    other.name(),
+                    command.name()
+                ));
+            }
+            user.push((sequence.clone(), command));
+            keymap.bind(sequence, command);
+        }
+        for (i, (a, _)) in keymap.bindings.iter().enumerate() {
+            for (b, _) in &keymap.bindings[i + 1..] {
+                let (short, long) = if a.len() < b.len() { (a, b) } else { (b, a) };
+                if short.len() != long.len() && long.starts_with(short) {
+                    problems.push(format!(
+                        "keymap: {} hides {}",
+                        keys_to_string(short),
+                        keys_to_string(long)
+                    ));
+                }
+            }
+        }
+        (keymap, problems)
+    }
+    pub fn bind(&mut self, keys: Vec<KeyPress>, command: Command) {
+        self.bindings.retain(|(k, _)| *k != keys);
+        self.bindings.push((keys, command));
+    }
+    #[must_use]
+    pub fn lookup(&self, keys: &[KeyPress]) -> Lookup {
+        let mut prefix = false;
+        for (sequence, command) in &self.bindings {
+            if sequence == keys {
+                return Lookup::Command(*command);
+            } else if sequence.starts_with(keys) {
+                prefix = true;
+            }
+        }
+        if prefix {
+            Lookup::Prefix
+        } else {
+            Lookup::None
+        }
+    }
+    #[must_use]
+    pub fn keys_for(&self, command: Command) -> Option<&[KeyPress]> {
+        self.bindings
+            .iter()
+            .find(|(_, c)| *c == command)
+            .map(|(k, _)| k.as_slice())
+    }
+}
+#[must_use]
+pub fn parse_keys(string: &str) -> Option<Vec<KeyPress>> {
+    let keys: Option<Vec<KeyPress>> = string.split_whitespace().map(parse_key).collect();
+    keys.filter(|k| !k.is_empty())
+}
+#[must_use]
+pub fn parse_key(string: &str) -> Option<KeyPress> {
+    let lower = string.to_lowercase();
+    match lower.as_str() {
+        "ctrl+left" => return Some(KeyPress::Ctrl(Arrow::Left)),
+        "ctrl+right" => return Some(KeyPress::Ctrl(Arrow::Right)),
+        "ctrl+up" => return Some(KeyPress::Ctrl(Arrow::Up)),
+        "ctrl+down" => return Some(KeyPress::Ctrl(Arrow::Down)),
+        //terminals send ctrl+/ as 0x1f, which termion reads as ctrl+7
+        "ctrl+/" => return Some(KeyPress::Key(Key::Ctrl('7'))),
+        //and ctrl+space as a null byte
+        "ctrl+space" => return Some(KeyPress::Key(Key::Null)),
+        _ => (),
+    }
+    if let Some(rest) = lower.strip_prefix("ctrl+") {
+        return single_char(rest).map(|c| KeyPress::Key(Key::Ctrl(c)));
+    }
+    if let Some(rest) = lower.strip_prefix("alt+") {
+        return single_char(rest).map(|c| KeyPress::Key(Key::Alt(c)));
+    }
+    Some(KeyPress::Key(match lower.as_str() {
+        "backspace" => Key::Backspace,
+        "left" => Key::Left,
+        "right" => Key::Right,
+        "up" => Key::Up,
+        "down" => Key::Down,
+        "home" => Key::Home,
+        "end" => Key::End,
+        "pageup" => Key::PageUp,
+        "pagedown" => Key::PageDown,
+        "backtab" | "shift+tab" => Key::BackTab,
+        "delete" => Key::Delete,
+        "insert" => Key::Insert,
+        "esc" => Key::Esc,
+        "tab" => Key::Char('\t'),
+        "enter" => Key::Char('\n'),
+        "space" => Key::Char(' '),
+        _ => {
+            if let Some(n) = lower.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+                Key::F(n)
+            } else {
+                Key::Char(single_char(string)?)
+            }
+        }
+    }))
+}
+fn single_char(string: &str) -> Option<char> {
+    let mut chars = string.chars();
+    let c = chars.next()?;
+    if chars.next().is_none() {
+        Some(c)
+    } else {
+        None
+    }
+}
+#[must_use]
+pub fn key_to_string(key: KeyPress) -> String {
+    let key = match key {
+        KeyPress::Key(key) => key,
+        KeyPress::Ctrl(arrow) => return format!("Ctrl+{:?}", arrow),
+    };
+    match key {
+        Key::Ctrl('7') => "Ctrl+/".to_string(),
+        Key::Null => "Ctrl+Space".to_string(),
+        Key::Ctrl(c) => format!("Ctrl+{}", c.to_ascii_uppercase()),
+        Key::Alt(c) => format!("Alt+{}", c),
+        Key::F(n) => format!("F{}", n),
+        Key::Char('\t') => "Tab".to_string(),
+        Key::Char('\n') => "Enter".to_string(),
+        Key::Char(' ') => "Space".to_string(),
+        Key::Char(c) => c.to_string(),
+        Key::BackTab => "Shift+Tab".to_string(),
+        other => format!("{:?}", other),
+    }
+}
+#[must_use]
+pub fn keys_to_string(keys: &[KeyPress]) -> String {
+    keys.iter()
+        .map(|k| key_to_string(*k))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keymap(config: &str) -> (Keymap, Vec<String>) {
+        Keymap::new(&Config::parse(config))
+    }
+    #[test]
+    fn parses_keys() {
+        assert_eq!(parse_key("Ctrl+S"), Some(KeyPress::Key(Key::Ctrl('s'))));
+        assert_eq!(parse_key("alt+|"), Some(KeyPress::Key(Key::Alt('|'))));
+        assert_eq!(parse_key("ctrl+/"), Some(KeyPress::Key(Key::Ctrl('7'))));
+        assert_eq!(parse_key("ctrl+space"), Some(KeyPress::Key(Key::Null)));
+        assert_eq!(parse_key("ctrl+left"), Some(KeyPress::Ctrl(Arrow::Left)));
+        assert_eq!(parse_key("shift+tab"), Some(KeyPress::Key(Key::BackTab)));
+        assert_eq!(parse_key("F12"), Some(KeyPress::Key(Key::F(12))));
+        assert_eq!(parse_key("enter"), Some(KeyPress::Key(Key::Char('\n'))));
+        assert_eq!(parse_key("x"), Some(KeyPress::Key(Key::Char('x'))));
+        assert_eq!(parse_key("ctrl+xy"), None);
+        assert_eq!(parse_key("nonsense"), None);
+    }
+    #[test]
+    fn modified_arrows_are_not_alt_keys() {
+        assert_ne!(parse_key("ctrl+left"), parse_key("alt+b"));
+        assert_ne!(parse_key("ctrl+down"), parse_key("alt+}"));
+    }
+    #[test]
+    fn parses_sequences() {
+        assert_eq!(
+            parse_keys("ctrl+k  ctrl+s"),
+            Some(vec![
+                KeyPress::Key(Key::Ctrl('k')),
+                KeyPress::Key(Key::Ctrl('s'))
+            ])
+        );
+        assert_eq!(parse_keys("  "), None);
+        assert_eq!(parse_keys("ctrl+k bogus"), None);
+    }
+    #[test]
+    fn names_keys() {
+        for name in [
+            "Ctrl+S",
+            "Alt+m",
+            "Ctrl+/",
+            "Ctrl+Space",
+            "Ctrl+Left",
+            "F2",
+            "Tab",
+        ] {
+            let key = parse_key(name).unwrap();
+            assert_eq!(key_to_string(key), name);
+        }
+        assert_eq!(
+            keys_to_string(&parse_keys("ctrl+k enter").unwrap()),
+            "Ctrl+K Enter"
+        );
+    }
+    #[test]
+    fn defaults_parse_without_problems() {
+        let (keymap, problems) = keymap("");
+        assert!(problems.is_empty(), "{:?}", problems);
+        assert_eq!(keymap.bindings.len(), DEFAULT_BINDINGS.len());
+    }
+    #[test]
+    fn looks_up_chords() {
+        let (keymap, problems) = keymap("[keys]\nctrl+k ctrl+s = save\n");
+        assert!(problems.is_empty(), "{:?}", problems);
+        let ctrl_k = parse_keys("ctrl+k").unwrap();
+        assert!(matches!(keymap.lookup(&ctrl_k), Lookup::Prefix));
+        assert!(matches!(
+            keymap.lookup(&parse_keys("ctrl+k ctrl+s").unwrap()),
+            Lookup::Command(Command::Save)
+        ));
+        assert!(matches!(
+            keymap.lookup(&parse_keys("ctrl+k ctrl+x").unwrap()),
+            Lookup::None
+        ));
+    }
+    #[test]
+    fn user_bindings_replace_defaults() {
+        let (keymap, problems) = keymap("[keys]\nctrl+s = quit\n");
+        assert!(problems.is_empty(), "{:?}", problems);
+        assert!(matches!(
+            keymap.lookup(&parse_keys("ctrl+s").unwrap()),
+            Lookup::Command(Command::Quit)
+        ));
+        assert_eq!(keymap.keys_for(Command::Save), None);
+    }
+    #[test]
+    fn reports_conflicts() {
+        let (_, problems) = keymap("[keys]\nctrl+t = save\nctrl+t = quit\n");
+        assert_eq!(
+            problems,
+            vec!["keymap: Ctrl+T is bound to both save and quit"]
+        );
+    }
+    #[test]
+    fn reports_hidden_chords() {
+        let (_, problems) = keymap("[keys]\nctrl+k ctrl+s = save\nctrl+k = quit\n");
+        assert_eq!(problems, vec!["keymap: Ctrl+K hides Ctrl+K Ctrl+S"]);
+    }
+    #[test]
+    fn reports_bad_entries() {
+        let (_, problems) = keymap("[keys]\nctrl+xy = save\nctrl+t = nothing\n");
+        assert_eq!(
+            problems,
+            vec![
+                "keymap: can't parse key sequence 'ctrl+xy'",
+                "keymap: unknown command 'nothing'"
+            ]
+        );
+    }
+}
@@ -1,16 +1,56 @@
-use std::time::Instant;
+//Lints from newer toolchains than the original code was written against.
+#![allow(
+    unused_mut,
+    clippy::collapsible_match,
+    clippy::needless_borrows_for_generic_args,
+    clippy::needless_return
+)]
+use std::{cmp::Ordering, time::Instant};
 
+pub mod command;
+pub mod completion;
+pub mod config;
+pub mod diff;
 pub mod document;
 pub mod editor;
+pub mod events;
+pub mod ex;
+pub mod explorer;
 pub mod file_type;
+pub mod fold;
+pub mod fuzzy;
 pub mod highlight;
+pub mod history;
+pub mod input;
+pub mod keymap;
+pub mod lsp;
+pub mod picker;
+pub mod popup;
 pub mod row;
+pub mod settings;
+pub mod shell;
+pub mod snippet;
+pub mod swap;
 pub mod terminal;
-#[derive(Clone)]
+pub mod undo;
+pub mod viewer;
+pub mod workspace;
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Position {
     x: usize,
     y: usize,
 }
+//Positions order the way text reads: by row, then by column.
+impl Ord for Position {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.y, self.x).cmp(&(other.y, other.x))
+    }
+}
+impl PartialOrd for Position {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
 pub struct StatusMessage {
     pub message: String,
     pub time: Instant,
@@ -0,0 +1,893 @@
+use std::{
+    collections::HashMap,
+    env,
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde_json::{json, Value};
+use termion::color::Rgb;
+
+use crate::{
+    completion::{Item, Source},
+    document::Document,
+    editor::Editor,
+    events::{Event, Job},
+    Position,
+};
+
+//How long a server gets to exit on its own before it is killed.
+const EXIT_TIME: Duration = Duration::from_millis(200);
+
+//What a request to the server was for, so its response can be dealt with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Request {
+    Initialize,
+    Shutdown,
+    Hover,
+    Definition,
+    References,
+    Rename,
+    CodeAction,
+    ExecuteCommand,
+    Completion,
+}
+//A message from the server, with responses matched up with their request.
+pub enum Message {
+    Response {
+        request: Request,
+        result: Result<Value, String>,
+    },
+    //the server asking the editor for something, answered with `Client::respond`
+    Request {
+        id: Value,
+        method: String,
+        params: Value,
+    },
+    Notification {
+        method: String,
+        params: Value,
+    },
+}
+//How the server wants to hear about edits.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Sync {
+    None,
+    Full,
+    Incremental,
+}
+//A place in a document the way the protocol counts: both 0-based, `character`
+//in UTF-16 code units.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LspPosition {
+    pub line: usize,
+    pub character: usize,
+}
+impl LspPosition {
+    fn parse(value: &Value) -> Option<LspPosition> {
+        Some(LspPosition {
+            line: usize::try_from(value.get("line")?.as_u64()?).ok()?,
+            character: usize::try_from(value.get("character")?.as_u64()?).ok()?,
+        })
+    }
+    #[must_use]
+    pub fn to_json(&self) -> Value {
+        json!({"line": self.line, "character": self.character})
+    }
+}
+//Worst last, so the worst of several is the largest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Hint,
+    Information,
+    Warning,
+    Error,
+}
+impl Severity {
+    #[must_use]
+    pub fn marker(self) -> char {
+        match self {
+            Severity::Hint => 'H',
+            Severity::Information => 'I',
+            Severity::Warning => 'W',
+            Severity::Error => 'E',
+        }
+    }
+    #[must_use]
+    pub fn color(self) -> Rgb {
+        match self {
+            Severity::Hint => Rgb(135, 129, 128),
+            Severity::Information => Rgb(80, 150, 220),
+            Severity::Warning => Rgb(220, 180, 50),
+            Severity::Error => Rgb(220, 60, 60),
+        }
+    }
+}
+pub struct Diagnostic {
+    pub start: LspPosition,
+    pub end: LspPosition,
+    pub severity: Severity,
+    pub message: String,
+    //as the server sent it, for handing back with code action requests
+    pub raw: Value,
+}
+pub struct TextEdit {
+    pub start: LspPosition,
+    pub end: LspPosition,
+    pub text: String,
+}
+pub struct Location {
+    pub path: PathBuf,
+    pub start: LspPosition,
+}
+//A language server running as a child process, spoken to over its stdin and
+//stdout. A thread reads its messages and hands them to the editor as jobs, and
+//another writes ours, so a slow server never holds up the editor.
+pub struct Client {
+    outbox: Sender<Value>,
+    next_id: u64,
+    pending: HashMap<u64, Request>,
+    sync: Sync,
+    pub initialized: bool,
+    //the uri of the document the server has open, and its version
+    pub open: Option<String>,
+    version: i64,
+    //edits went by that the server hasn't seen, so it needs the whole text again
+    stale: bool,
+    pub diagnostics: HashMap<String, Vec<Diagnostic>>,
+}
+impl Client {
+    //Starts `command` for file type `name` and asks it to initialize. Its
+    //messages come back through `sender` as calls to `Editor::lsp_message`.
+    ///# Errors
+    ///
+    /// returns the error if the command can't be run
+    pub fn start(name: &str, command: &str, sender: Sender<Event>) -> io::Result<Client> {
+        let name = name.to_string();
+        Client::spawn(command, move |message| {
+            let name = name.clone();
+            let job: Job = match message {
+                Some(message) => Box::new(move |editor: &mut Editor| {
+                    editor.lsp_message(&name, message);
+                }),
+                None => Box::new(move |editor: &mut Editor| editor.lsp_exited(&name)),
+            };
+            sender.send(Event::Job(job)).is_ok()
+        })
+    }
+    //Starts `command` and asks it to initialize. Each message it sends is handed
+    //to `deliver` on a thread of its own, then None once it stops; false from
+    //`deliver` stops the reading.
+    ///# Errors
+    ///
+    /// returns the error if the command can't be run
+    pub fn spawn<F>(command: &str, mut deliver: F) -> io::Result<Client>
+    where
+        F: FnMut(Option<Value>) -> bool + Send + 'static,
+    {
+        let mut words = command.split_whitespace();
+        let program = words
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "empty command"))?;
+        let mut child = Command::new(program)
+            .args(words)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+        thread::spawn(move || {
+            let mut reader = BufReader::new(stdout);
+            while let Some(message) = read_message(&mut reader) {
+                if !deliver(Some(message)) {
+                    return;
+                }
+            }
+            deliver(None);
+        });
+        let (outbox, messages) = mpsc::channel();
+        thread::spawn(move || write_messages(child, stdin, &messages));
+        let mut client = Client {
+            outbox,
+            next_id: 0,
+            pending: HashMap::new(),
+            sync: Sync::None,
+            initialized: false,
+            open: None,
+            version: 0,
+            stale: false,
+            diagnostics: HashMap::new(),
+        };
+        let root = env::current_dir().unwrap_or_default();
+        client.request(
+            Request::Initialize,
+            "initialize",
+            json!({
+                "processId": std::process::id(),
+                "rootUri": uri(&root),
+                "workspaceFolders": [{"uri": uri(&root), "name": "root"}],
+                "capabilities": {
+                    "textDocument": {
+                        "synchronization": {"didSave": true},
+                        "hover": {"contentFormat": ["plaintext", "markdown"]},
+                        "completion": {
+                            "completionItem": {
+                                "snippetSupport": true,
+                                "documentationFormat": ["plaintext", "markdown"],
+                            }
+                        },
+                        "publishDiagnostics": {},
+                        "definition": {},
+                        "references": {},
+                        "rename": {},
+                        "codeAction": {
+                            "codeActionLiteralSupport": {
+                                "codeActionKind": {"valueSet": [
+                                    "", "quickfix", "refactor", "refactor.extract",
+                                    "refactor.inline", "refactor.rewrite", "source",
+                                ]}
+                            }
+                        },
+                    },
+                    "workspace": {"applyEdit": true, "workspaceEdit": {"documentChanges": true}},
+                },
+            }),
+        );
+        Ok(client)
+    }
+    pub fn request(&mut self, request: Request, method: &str, params: Value) {
+        self.next_id += 1;
+        self.pending.insert(self.next_id, request);
+        self.send(
+            &json!({"jsonrpc": "2.0", "id": self.next_id, "method": method, "params": params}),
+        );
+    }
+    pub fn notify(&mut self, method: &str, params: Value) {
+        self.send(&json!({"jsonrpc": "2.0", "method": method, "params": params}));
+    }
+    pub fn respond(&mut self, id: Value, result: Value) {
+        self.send(&json!({"jsonrpc": "2.0", "id": id, "result": result}));
+    }
+    pub fn respond_error(&mut self, id: Value, code: i64, message: &str) {
+        self.send(
+            &json!({"jsonrpc": "2.0", "id": id, "error": {"code": code, "message": message}}),
+        );
+    }
+    fn send(&mut self, message: &Value) {
+        if self.outbox.send(message.clone()).is_err() {
+            log::warn!("couldn't write to the language server: it has stopped");
+        }
+    }
+    //Sorts out what `message` is, matching a response to the request it answers.
+    //None for responses to nothing we asked.
+    pub fn receive(&mut self, mut message: Value) -> Option<Message> {
+        let method = message
+            .get("method")
+            .and_then(Value::as_str)
+            .map(str::to_string);
+        let params = message
+            .get_mut("params")
+            .map(Value::take)
+            .unwrap_or_default();
+        match (message.get_mut("id").map(Value::take), method) {
+            (Some(id), Some(method)) => Some(Message::Request { id, method, params }),
+            (None, Some(method)) => Some(Message::Notification { method, params }),
+            (Some(id), None) => {
+                let request = self.pending.remove(&id.as_u64()?)?;
+                let result = match message.get("error") {
+                    Some(error) => Err(error
+                        .get("message")
+                        .and_then(Value::as_str)
+                        .unwrap_or("unknown error")
+                        .to_string()),
+                    None => Ok(message
+                        .get_mut("result")
+                        .map(Value::take)
+                        .unwrap_or_default()),
+                };
+                Some(Message::Response { request, result })
+            }
+            (None, None) => None,
+        }
+    }
+    //Takes in the answer to `initialize` and tells the server we're ready.
+    pub fn initialized(&mut self, result: &Value) {
+        let sync = &result["capabilities"]["textDocumentSync"];
+        let kind = sync.as_u64().or_else(|| sync["change"].as_u64());
+        self.sync = match kind {
+            Some(2) => Sync::Incremental,
+            Some(1) => Sync::Full,
+            _ => Sync::None,
+        };
+        self.initialized = true;
+        self.notify("initialized", json!({}));
+    }
+    pub fn did_open(&mut self, uri: &str, language: &str, text: &str) {
+        self.version = 1;
+        self.stale = false;
+        self.open = Some(uri.to_string());
+        self.notify(
+            "textDocument/didOpen",
+            json!({"textDocument": {
+                "uri": uri, "languageId": language, "version": self.version, "text": text,
+            }}),
+        );
+    }
+    pub fn did_close(&mut self) {
+        if let Some(uri) = self.open.take() {
+            self.diagnostics.remove(&uri);
+            self.notify(
+                "textDocument/didClose",
+                json!({"textDocument": {"uri": uri}}),
+            );
+        }
+    }
+    pub fn did_save(&mut self) {
+        if let Some(uri) = self.open.clone() {
+            self.notify(
+                "textDocument/didSave",
+                json!({"textDocument": {"uri": uri}}),
+            );
+        }
+    }
+    //Tells the server `start..end` of the open document became `text`. Servers
+    //that only take the whole document, or that missed an edit already, get all
+    //of it at the next `sync` instead.
+    pub fn edited(&mut self, start: &LspPosition, end: &LspPosition, text: &str) {
+        //the diagnostics below the edit move with it until the server sends new ones
+        let added = text.matches('\n').count();
+        if let Some(diagnostics) = self
+            .open
+            .as_ref()
+            .and_then(|uri| self.diagnostics.get_mut(uri))
+        {
+            for diagnostic in diagnostics {
+                for position in [&mut diagnostic.start, &mut diagnostic.end] {
+                    if position.line > end.line {
+                        position.line = position.line + start.line + added - end.line;
+                    }
+                }
+            }
+        }
+        if self.stale || self.sync != Sync::Incremental {
+            self.stale = true;
+            return;
+        }
+        self.did_change(&json!({"range": range(start, end), "text": text}));
+    }
+    //The whole open document changed.
+    pub fn invalidate(&mut self) {
+        self.stale = true;
+    }
+    //Sends the whole text if the server missed any edits.
+    pub fn sync(&mut self, text: impl FnOnce() -> String) {
+        if self.stale && self.sync != Sync::None {
+            self.did_change(&json!({ "text": text() }));
+        }
+        self.stale = false;
+    }
+    fn did_change(&mut self, change: &Value) {
+        let uri = match &self.open {
+            Some(uri) => uri.clone(),
+            None => return,
+        };
+        self.version += 1;
+        self.notify(
+            "textDocument/didChange",
+            json!({
+                "textDocument": {"uri": uri, "version": self.version},
+                "contentChanges": [change],
+            }),
+        );
+    }
+    //Asks the server to wind down. Once the client is dropped the server gets a
+    //moment to exit before it is killed.
+    pub fn shutdown(&mut self) {
+        self.request(Request::Shutdown, "shutdown", Value::Null);
+        self.notify("exit", Value::Null);
+    }
+}
+//Writes the client's messages to the server until the client is dropped, then
+//waits for the server to exit, killing it if it takes too long.
+fn write_messages(mut child: Child, mut stdin: ChildStdin, messages: &Receiver<Value>) {
+    for message in messages {
+        if let Err(e) = write_message(&mut stdin, &message) {
+            log::warn!("couldn't write to the language server: {}", e);
+            break;
+        }
+    }
+    drop(stdin);
+    let deadline = Instant::now() + EXIT_TIME;
+    while Instant::now() < deadline {
+        if let Ok(Some(_)) = child.try_wait() {
+            return;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    let _ = child.kill();
+    let _ = child.wait();
+}
+//FRAMING
+//Writes one message with its Content-Length header.
+///# Errors
+///
+/// returns the error if writing fails
+pub fn write_message(writer: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+}
+//Reads one message, or None once the stream ends or stops making sense.
+pub fn read_message(reader: &mut impl BufRead) -> Option<Value> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).ok()? == 0 {
+            return None;
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let mut body = vec![0; length?];
+    reader.read_exact(&mut body).ok()?;
+    serde_json::from_slice(&body).ok()
+}
+//CONVERSIONS
+#[must_use]
+pub fn uri(path: &Path) -> String {
+    let path = path
+        .canonicalize()
+        .unwrap_or_else(|_| env::current_dir().unwrap_or_default().join(path));
+    let mut uri = "file://".to_string();
+    for byte in path.to_string_lossy().bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~/".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    uri
+}
+#[must_use]
+pub fn path(uri: &str) -> Option<PathBuf> {
+    let encoded = uri.strip_prefix("file://")?.as_bytes();
+    let mut bytes = Vec::new();
+    let mut i = 0;
+    while i < encoded.len() {
+        let escaped = (encoded[i] == b'%')
+            .then(|| std::str::from_utf8(encoded.get(i + 1..i + 3)?).ok())
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                bytes.push(byte);
+                i += 3;
+            }
+            None => {
+                bytes.push(encoded[i]);
+                i += 1;
+            }
+        }
+    }
+    let path = PathBuf::from(String::from_utf8(bytes).ok()?);
+    //paths inside the project show the way the rest of the editor shows them
+    let relative = env::current_dir()
+        .ok()
+        .and_then(|dir| path.strip_prefix(dir).ok().map(Path::to_path_buf))
+        .filter(|relative| !relative.as_os_str().is_empty());
+    Some(relative.unwrap_or(path))
+}
+#[must_use]
+pub fn to_lsp(document: &Document, position: &Position) -> LspPosition {
+    let character = document.rows.get(position.y - 1).map_or(0, |row| {
+        row.content
+            .iter()
+            .take(position.x)
+            .map(|gr| gr.encode_utf16().count())
+            .sum()
+    });
+    LspPosition {
+        line: position.y - 1,
+        character,
+    }
+}
+//The grapheme the server means, clamped to the document.
+#[must_use]
+pub fn from_lsp(document: &Document, position: &LspPosition) -> Position {
+    let y = (position.line + 1).min(document.rows.len());
+    let content = &document.rows[y - 1].content;
+    if position.line + 1 > document.rows.len() {
+        return Position {
+            x: content.len(),
+            y,
+        };
+    }
+    let mut units = 0;
+    let x = content
+        .iter()
+        .take_while(|gr| {
+            units += gr.encode_utf16().count();
+            units <= position.character
+        })
+        .count();
+    Position { x, y }
+}
+#[must_use]
+pub fn range(start: &LspPosition, end: &LspPosition) -> Value {
+    json!({"start": start.to_json(), "end": end.to_json()})
+}
+fn parse_range(value: &Value) -> Option<(LspPosition, LspPosition)> {
+    Some((
+        LspPosition::parse(value.get("start")?)?,
+        LspPosition::parse(value.get("end")?)?,
+    ))
+}
+//Where the UTF-16 `position` falls in `text`, as a byte offset.
+fn offset(text: &str, position: &LspPosition) -> usize {
+    let mut start = 0;
+    for _ in 0..position.line {
+        match text[start..].find('\n') {
+            Some(i) => start += i + 1,
+            None => return text.len(),
+        }
+    }
+    let line = &text[start..];
+    let line = &line[..line.find('\n').unwrap_or(line.len())];
+    let mut units = 0;
+    for (i, c) in line.char_indices() {
+        if units >= position.character {
+            return start + i;
+        }
+        units += c.len_utf16();
+    }
+    start + line.len()
+}
+//Applies edits from the server to the text of a file that isn't open.
+#[must_use]
+pub fn apply_edits(text: &str, edits: &[TextEdit]) -> String {
+    let mut spans: Vec<(usize, usize, &str)> = edits
+        .iter()
+        .map(|edit| {
+            (
+                offset(text, &edit.start),
+                offset(text, &edit.end),
+                edit.text.as_str(),
+            )
+        })
+        .collect();
+    //from the back, so earlier offsets stay right; edits at the same spot go in in order
+    spans.reverse();
+    spans.sort_by_key(|&(start, _, _)| std::cmp::Reverse(start));
+    let mut text = text.to_string();
+    for (start, end, new) in spans {
+        text.replace_range(start..end.max(start), new);
+    }
+    text
+}
+//PARSING RESULTS
+#[must_use]
+pub fn diagnostics(params: &Value) -> (String, Vec<Diagnostic>) {
+    let uri = params["uri"].as_str().unwrap_or_default().to_string();
+    let diagnostics = params["diagnostics"]
+        .as_array()
+        .map(|list| {
+            list.iter()
+                .filter_map(|raw| {
+                    let (start, end) = parse_range(raw.get("range")?)?;
+                    let severity = match raw["severity"].as_u64() {
+                        Some(2) => Severity::Warning,
+                        Some(3) => Severity::Information,
+                        Some(4) => Severity::Hint,
+                        _ => Severity::Error,
+                    };
+                    Some(Diagnostic {
+                        start,
+                        end,
+                        severity,
+                        message: raw["message"].as_str().unwrap_or_default().to_string(),
+                        raw: raw.clone(),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    (uri, diagnostics)
+}
+//The lines of a hover result, whichever of the shapes it comes in.
+#[must_use]
+pub fn hover_text(result: &Value) -> Vec<String> {
+    markup(&result["contents"])
+}
+fn markup(contents: &Value) -> Vec<String> {
+    fn text(contents: &Value) -> String {
+        match contents {
+            Value::String(text) => text.clone(),
+            Value::Array(parts) => parts.iter().map(text).collect::<Vec<_>>().join("\n"),
+            _ => contents["value"].as_str().unwrap_or_default().to_string(),
+        }
+    }
+    text(contents)
+        .lines()
+        //markdown code fences only get in the way in a plain popup
+        .filter(|line| !line.trim_start().starts_with("```"))
+        .map(str::to_string)
+        .collect()
+}
+//The candidates in a completion result, which is either a list of them or an
+//object holding one. Their ranges are taken as they stand in `document`.
+#[must_use]
+pub fn completion_items(document: &Document, result: &Value) -> Vec<Item> {
+    let list = result.get("items").unwrap_or(result);
+    list.as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|item| {
+            let label = item["label"].as_str()?.to_string();
+            let insert = item["textEdit"]["newText"]
+                .as_str()
+                .or_else(|| item["insertText"].as_str())
+                .unwrap_or(&label);
+            let detail = item["detail"]
+                .as_str()
+                .map(str::to_string)
+                .or_else(|| item["kind"].as_u64().and_then(kind).map(str::to_string))
+                .unwrap_or_default();
+            let edit = &item["textEdit"];
+            let replace = edit
+                .get("range")
+                .or_else(|| edit.get("replace"))
+                .and_then(parse_range)
+                .map(|(start, end)| (from_lsp(document, &start), from_lsp(document, &end)));
+            Some(Item {
+                insert: insert.to_string(),
+                snippet: item["insertTextFormat"] == 2,
+                label,
+                detail,
+                documentation: markup(&item["documentation"]),
+                source: Source::LanguageServer,
+                replace,
+            })
+        })
+        .collect()
+}
+//The name of a CompletionItemKind, for the ones worth telling apart.
+fn kind(kind: u64) -> Option<&'static str> {
+    Some(match kind {
+        2 => "method",
+        3 => "function",
+        4 => "constructor",
+        5 => "field",
+        6 => "variable",
+        7 => "class",
+        8 => "interface",
+        9 => "module",
+        10 => "property",
+        13 => "enum",
+        14 => "keyword",
+        15 => "snippet",
+        21 => "constant",
+        22 => "struct",
+        _ => return None,
+    })
+}
+//The locations in a definition or references result: a location, a list of
+//them, or a list of links.
+#[must_use]
+pub fn locations(result: &Value) -> Vec<Location> {
+    let list = match result {
+        Value::Array(list) => list.clone(),
+        Value::Null => Vec::new(),
+        single => vec![single.clone()],
+    };
+    list.iter()
+        .filter_map(|location| {
+            let uri = location
+                .get("uri")
+                .or_else(|| location.get("targetUri"))?
+                .as_str()?;
+            let range = location
+                .get("range")
+                .or_else(|| location.get("targetSelectionRange"))?;
+            Some(Location {
+                path: path(uri)?,
+                start: parse_range(range)?.0,
+            })
+        })
+        .collect()
+}
+//The files a workspace edit changes and what it does to each, from either its
+//`changes` map or its `documentChanges` list.
+#[must_use]
+pub fn workspace_edit(edit: &Value) -> Vec<(PathBuf, Vec<TextEdit>)> {
+    let text_edits = |edits: &Value| -> Vec<TextEdit> {
+        edits
+            .as_array()
+            .map(|edits| {
+                edits
+                    .iter()
+                    .filter_map(|edit| {
+                        let (start, end) = parse_range(edit.get("range")?)?;
+                        Some(TextEdit {
+                            start,
+                            end,
+                            text: edit["newText"].as_str()?.to_string(),
+                        })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+    let mut files = Vec::new();
+    if let Some(changes) = edit["changes"].as_object() {
+        for (uri, edits) in changes {
+            if let Some(path) = path(uri) {
+                files.push((path, text_edits(edits)));
+            }
+        }
+    }
+    if let Some(changes) = edit["documentChanges"].as_array() {
+        for change in changes {
+            if let Some(path) = change["textDocument"]["uri"].as_str().and_then(path) {
+                files.push((path, text_edits(&change["edits"])));
+            }
+        }
+    }
+    files
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(line: usize, character: usize) -> LspPosition {
+        LspPosition { line, character }
+    }
+    fn edit(start: LspPosition, end: LspPosition, text: &str) -> TextEdit {
+        TextEdit {
+            start,
+            end,
+            text: text.to_string(),
+        }
+    }
+    fn range_json(line: usize) -> Value {
+        range(&at(line, 0), &at(line, 1))
+    }
+    #[test]
+    fn frames_messages() {
+        let mut out = Vec::new();
+        write_message(&mut out, &json!({"id": 1, "text": "é"})).unwrap();
+        write_message(&mut out, &json!({"id": 2})).unwrap();
+        let written = String::from_utf8(out.clone()).unwrap();
+        assert!(written.starts_with("Content-Length: 20\r\n\r\n{"));
+        let mut reader = out.as_slice();
+        assert_eq!(
+            read_message(&mut reader),
+            Some(json!({"id": 1, "text": "é"}))
+        );
+        assert_eq!(read_message(&mut reader), Some(json!({"id": 2})));
+        assert_eq!(read_message(&mut reader), None);
+    }
+    #[test]
+    fn reads_other_headers_and_stops_at_nonsense() {
+        let mut reader: &[u8] =
+            b"content-length: 2\r\nContent-Type: application/vscode-jsonrpc\r\n\r\n{}";
+        assert_eq!(read_message(&mut reader), Some(json!({})));
+        let mut reader: &[u8] = b"Content-Length: 10\r\n\r\n{}";
+        assert_eq!(read_message(&mut reader), None);
+        let mut reader: &[u8] = b"\r\n{}";
+        assert_eq!(read_message(&mut reader), None);
+    }
+    #[test]
+    fn converts_positions_in_utf16() {
+        let mut document = Document::new(None, 4);
+        document.set_contents("a👍🏽é\nb", 4);
+        //👍🏽 is one grapheme of four UTF-16 units
+        let position = Position { x: 2, y: 1 };
+        assert_eq!(to_lsp(&document, &position), at(0, 5));
+        assert_eq!(from_lsp(&document, &at(0, 5)), position);
+        //the middle of a grapheme goes to its start
+        assert_eq!(from_lsp(&document, &at(0, 3)), Position { x: 1, y: 1 });
+        assert_eq!(from_lsp(&document, &at(0, 99)), Position { x: 3, y: 1 });
+        assert_eq!(from_lsp(&document, &at(7, 0)), Position { x: 1, y: 2 });
+    }
+    #[test]
+    fn applies_edits_from_the_back() {
+        let text = "one two\nthree 👍 four\n";
+        let edits = [
+            edit(at(0, 0), at(0, 3), "1"),
+            edit(at(1, 9), at(1, 13), "4"),
+            edit(at(0, 4), at(1, 5), "2\n3"),
+        ];
+        assert_eq!(apply_edits(text, &edits), "1 2\n3 👍 4\n");
+    }
+    #[test]
+    fn applies_inserts_at_one_place_in_order() {
+        let edits = [edit(at(0, 0), at(0, 0), "A"), edit(at(0, 0), at(0, 0), "B")];
+        assert_eq!(apply_edits("xyz", &edits), "ABxyz");
+        let edits = [edit(at(0, 0), at(0, 0), "A"), edit(at(0, 0), at(0, 1), "B")];
+        assert_eq!(apply_edits("xyz", &edits), "AByz");
+        //past the end of the text is its end
+        let edits = [edit(at(5, 0), at(5, 0), "!")];
+        assert_eq!(apply_edits("xyz", &edits), "xyz!");
+    }
+    #[test]
+    fn reads_workspace_edits_in_both_shapes() {
+        let range =
+            json!({"start": {"line": 0, "character": 0}, "end": {"line": 0, "character": 1}});
+        let changes = workspace_edit(&json!({"changes": {
+            "file:///tmp/a%20b.rs": [{"range": range, "newText": "x"}],
+        }}));
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].0, PathBuf::from("/tmp/a b.rs"));
+        assert_eq!(changes[0].1[0].text, "x");
+        let changes = workspace_edit(&json!({"documentChanges": [
+            {"textDocument": {"uri": "file:///tmp/c.rs", "version": 3},
+                "edits": [{"range": range, "newText": "y"}, {"newText": "no range"}]},
+        ]}));
+        assert_eq!(changes[0].0, PathBuf::from("/tmp/c.rs"));
+        assert_eq!(changes[0].1.len(), 1);
+        assert_eq!(changes[0].1[0].end, at(0, 1));
+    }
+    #[test]
+    fn escapes_uris() {
+        let path = PathBuf::from("/tmp/no such dir/ü.rs");
+        assert_eq!(uri(&path), "file:///tmp/no%20such%20dir/%C3%BC.rs");
+        assert_eq!(self::path(&uri(&path)), Some(path));
+        assert_eq!(self::path("https://example.com"), None);
+    }
+    #[test]
+    fn reads_diagnostics_and_results() {
+        let (uri, diagnostics) = diagnostics(&json!({"uri": "file:///a.rs", "diagnostics": [
+            {"range": {"start": {"line": 2, "character": 1}, "end": {"line": 2, "character": 4}},
+                "severity": 2, "message": "careful"},
+            {"range": {"start": {"line": 0, "character": 0}, "end": {"line": 0, "character": 0}},
+                "message": "no severity"},
+        ]}));
+        assert_eq!(uri, "file:///a.rs");
+        assert_eq!(diagnostics[0].severity, Severity::Warning);
+        assert_eq!(diagnostics[0].start, at(2, 1));
+        assert_eq!(diagnostics[1].severity, Severity::Error);
+        let hover =
+            json!({"contents": {"kind": "markdown", "value": "```rust\nfn f()\n```\ndoes f"}});
+        assert_eq!(hover_text(&hover), ["fn f()", "does f"]);
+        assert_eq!(
+            hover_text(&json!({"contents": ["a", {"value": "b"}]})),
+            ["a", "b"]
+        );
+        let links = locations(&json!([{"targetUri": "file:///b.rs",
+            "targetRange": {}, "targetSelectionRange": range_json(4)}]));
+        assert_eq!(links[0].path, PathBuf::from("/b.rs"));
+        assert_eq!(links[0].start, at(4, 0));
+        assert!(locations(&Value::Null).is_empty());
+    }
+    #[test]
+    fn completion_items_take_their_edit_range() {
+        let mut document = Document::new(None, 4);
+        document.set_contents("let 👍 = valxyz;", 4);
+        let result = json!({"items": [
+            {"label": "value", "textEdit": {"newText": "value",
+                "range": {"start": {"line": 0, "character": 9},
+                    "end": {"line": 0, "character": 15}}}},
+            {"label": "other", "insertText": "other()"},
+        ]});
+        let items = completion_items(&document, &result);
+        assert_eq!(items[0].insert, "value");
+        assert_eq!(
+            items[0].replace,
+            Some((Position { x: 8, y: 1 }, Position { x: 14, y: 1 }))
+        );
+        assert_eq!(items[1].insert, "other()");
+        assert_eq!(items[1].replace, None);
+    }
+}
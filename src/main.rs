@@ -1,12 +1,63 @@
 #![allow(clippy::implicit_return)]
+use std::{
+    backtrace::{Backtrace, BacktraceStatus},
+    panic::{self, AssertUnwindSafe},
+    process,
+    sync::{mpsc::Sender, Arc, Mutex},
+    thread,
+};
+
 use clap::Parser;
-use text_editor::{document::Document, editor::Editor};
+use text_editor::{config::Config, editor::Editor, events::Event, terminal::Terminal};
 fn main() {
     simple_logging::log_to_file("last log.log", log::LevelFilter::Debug).unwrap();
     let args = Args::parse();
-    let document = Document::new(args.path);
-    let mut editor = Editor::new(document);
-    editor.run();
+    //The default hook would print into the raw terminal, so the report is kept
+    //and printed once the terminal has been restored. It's set before the
+    //editor takes over the terminal so a panic while starting up is covered too.
+    //Only the main thread brings the editor down; a panic on any other thread
+    //is sent to the main loop as an error once there is one.
+    let main_thread = thread::current().id();
+    let report = Arc::new(Mutex::new(String::new()));
+    let main_loop: Arc<Mutex<Option<Sender<Event>>>> = Arc::default();
+    let hook_report = Arc::clone(&report);
+    let hook_main_loop = Arc::clone(&main_loop);
+    panic::set_hook(Box::new(move |info| {
+        let backtrace = Backtrace::capture();
+        log::error!("{}\n{}", info, backtrace);
+        if thread::current().id() != main_thread {
+            if let Some(sender) = hook_main_loop.lock().ok().and_then(|s| s.clone()) {
+                let error = format!("a background thread crashed: {}", info);
+                let _ = sender.send(Event::Failed(error.replace('\n', " ")));
+            }
+            return;
+        }
+        let mut text = format!("saphire crashed: {}", info);
+        if backtrace.status() == BacktraceStatus::Captured {
+            text = format!("{}\n{}", text, backtrace);
+        }
+        if let Ok(mut report) = hook_report.lock() {
+            *report = text;
+        }
+    }));
+    let config = Config::load();
+    let started = panic::catch_unwind(AssertUnwindSafe(|| Editor::new(args.path, &config)));
+    let mut editor = match started {
+        Ok(editor) => editor,
+        Err(_) => {
+            //raw mode ended when the half built editor was dropped
+            Terminal::leave_screen();
+            eprintln!("{}", report.lock().map(|r| r.clone()).unwrap_or_default());
+            process::exit(101);
+        }
+    };
+    if let Ok(mut main_loop) = main_loop.lock() {
+        *main_loop = Some(editor.sender());
+    }
+    if panic::catch_unwind(AssertUnwindSafe(|| editor.run())).is_err() {
+        let report = report.lock().map(|r| r.clone()).unwrap_or_default();
+        editor.emergency_exit(&report, 101);
+    }
 }
 #[derive(Parser)]
 struct Args {
@@ -0,0 +1,148 @@
+use termion::color::{Bg, Black, Fg, Reset, Rgb, White};
+
+use crate::{fuzzy, terminal::Terminal};
+
+pub struct PickerItem {
+    pub label: String,
+    pub detail: String,
+}
+//A list overlay filtered by a fuzzy query, shared by every "choose one of these" screen.
+pub struct Picker {
+    pub title: String,
+    pub items: Vec<PickerItem>,
+    pub query: String,
+    pub matches: Vec<usize>,
+    pub selected: usize,
+    //Keep matches in item order instead of best match first, for lists that are grouped.
+    pub keep_order: bool,
+    scroll: usize,
+}
+impl Picker {
+    #[must_use]
+    pub fn new(title: &str, items: Vec<PickerItem>) -> Picker {
+        let matches = (0..items.len()).collect();
+        Picker {
+            title: title.to_string(),
+            items,
+            query: String::new(),
+            matches,
+            selected: 0,
+            keep_order: false,
+            scroll: 0,
+        }
+    }
+    //Typing only narrows the query, so only the current matches need scoring again.
+    pub fn push(&mut self, c: char) {
+        self.query.push(c);
+        let mut pool = self.matches.clone();
+        pool.sort_unstable();
+        self.refilter(pool);
+    }
+    pub fn pop(&mut self) {
+        self.query.pop();
+        self.refilter((0..self.items.len()).collect());
+    }
+    fn refilter(&mut self, pool: Vec<usize>) {
+        let labels: Vec<&str> = pool.iter().map(|i| self.items[*i].label.as_str()).collect();
+        self.matches = fuzzy::filter(&self.query, &labels)
+            .into_iter()
+            .map(|i| pool[i])
+            .collect();
+        if self.keep_order {
+            self.matches.sort_unstable();
+        }
+        self.selected = 0;
+        self.scroll = 0;
+    }
+    pub fn up(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+    pub fn down(&mut self) {
+        if self.selected + 1 < self.matches.len() {
+            self.selected += 1;
+        }
+    }
+    #[must_use]
+    pub fn selection(&self) -> Option<usize> {
+        self.matches.get(self.selected).copied()
+    }
+    pub fn draw(&mut self, x: u16, y: u16, width: u16, height: u16) {
+        let width = width as usize;
+        let list_height = height.saturating_sub(2) as usize;
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + list_height {
+            self.scroll = self.selected + 1 - list_height;
+        }
+        Terminal::move_cursor(x, y);
+        print!(
+            "{}{}{}{}{}",
+            Bg(White),
+            Fg(Black),
+            fit(
+                &format!(
+                    " {} ({}/{})",
+                    self.title,
+                    self.matches.len(),
+                    self.items.len()
+                ),
+                width
+            ),
+            Fg(Reset),
+            Bg(Reset)
+        );
+        for row in 0..list_height {
+            Terminal::move_cursor(x, y + 1 + row as u16);
+            let line =
+                if let Some(item) = self.matches.get(row + self.scroll).map(|i| &self.items[*i]) {
+                    let room = width.saturating_sub(item.detail.chars().count() + 1);
+                    format!(
+                        "{}{}",
+                        fit(&format!(" {}", item.label), room),
+                        fit(&item.detail, width - room)
+                    )
+                } else {
+                    fit("", width)
+                };
+            if row + self.scroll == self.selected {
+                print!(
+                    "{}{}{}{}{}",
+                    Bg(Rgb(12, 145, 194)),
+                    Fg(Black),
+                    line,
+                    Fg(Reset),
+                    Bg(Reset)
+                );
+            } else {
+                print!("{}{}{}", Bg(Rgb(40, 40, 40)), line, Bg(Reset));
+            }
+        }
+        Terminal::move_cursor(x, y + height - 1);
+        print!(
+            "{}{}{}",
+            Bg(Rgb(40, 40, 40)),
+            fit(&format!("> {}", self.query), width),
+            Bg(Reset)
+        );
+    }
+}
+//Pads or truncates a string to exactly `width` characters.
+#[must_use]
+pub fn fit(string: &str, width: usize) -> String {
+    let mut result: String = string.chars().take(width).collect();
+    let len = result.chars().count();
+    result.push_str(&" ".repeat(width - len));
+    result
+}
+pub fn draw_preview(lines: &[String], x: u16, y: u16, width: u16, height: u16) {
+    for row in 0..height {
+        Terminal::move_cursor(x, y + row);
+        let line = lines.get(row as usize).map_or("", String::as_str);
+        print!(
+            "{}{}{}",
+            Bg(Rgb(25, 25, 25)),
+            fit(&format!(" {}", line.replace('\t', "    ")), width as usize),
+            Bg(Reset)
+        );
+    }
+}
@@ -0,0 +1,75 @@
+use termion::color::{Bg, Black, Fg, Reset, Rgb};
+
+use crate::{picker::fit, terminal::Terminal};
+
+const BACKGROUND: Rgb = Rgb(50, 50, 60);
+const SELECTED: Rgb = Rgb(12, 145, 194);
+const MAX_WIDTH: usize = 70;
+const MAX_HEIGHT: usize = 12;
+
+//A small box drawn over the text next to the cursor, such as hover information.
+//It doesn't take keys itself; the editor closes it on the next one.
+pub struct Popup {
+    pub lines: Vec<String>,
+    //the highlighted line, for popups the user picks from
+    pub selected: Option<usize>,
+    scroll: usize,
+}
+impl Popup {
+    #[must_use]
+    pub fn new(lines: Vec<String>) -> Popup {
+        Popup {
+            lines,
+            selected: None,
+            scroll: 0,
+        }
+    }
+    //The size the box wants, as (width, height), border columns included.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn size(&self) -> (u16, u16) {
+        let width = self
+            .lines
+            .iter()
+            .map(|line| line.chars().count())
+            .max()
+            .unwrap_or(0)
+            .min(MAX_WIDTH);
+        (width as u16 + 2, self.lines.len().min(MAX_HEIGHT) as u16)
+    }
+    //Draws the box with its top left corner at (x, y), cut down to fit in
+    //`width` by `height`.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn draw(&mut self, x: u16, y: u16, width: u16, height: u16) {
+        let (wanted_width, wanted_height) = self.size();
+        let width = wanted_width.min(width) as usize;
+        let height = wanted_height.min(height) as usize;
+        if let Some(selected) = self.selected {
+            if selected < self.scroll {
+                self.scroll = selected;
+            } else if selected >= self.scroll + height {
+                self.scroll = selected + 1 - height;
+            }
+        }
+        for row in 0..height {
+            let i = row + self.scroll;
+            let line = fit(
+                &format!(" {}", self.lines.get(i).map_or("", String::as_str)),
+                width,
+            );
+            Terminal::move_cursor(x, y + row as u16);
+            if self.selected == Some(i) {
+                print!(
+                    "{}{}{}{}{}",
+                    Bg(SELECTED),
+                    Fg(Black),
+                    line,
+                    Fg(Reset),
+                    Bg(Reset)
+                );
+            } else {
+                print!("{}{}{}", Bg(BACKGROUND), line, Bg(Reset));
+            }
+        }
+    }
+}
@@ -1,7 +1,8 @@
-use std::ops::RangeInclusive;
+use std::ops::{Range, RangeInclusive};
 
 use regex::Regex;
-use termion::color::{Fg, Rgb};
+use termion::color::{Bg, Fg, Reset, Rgb};
+use unicode_segmentation::UnicodeSegmentation;
 
 use crate::{file_type::HighlightingOptions, highlight::Type};
 #[derive(Debug)]
@@ -10,11 +11,38 @@ pub struct Row {
     pub highlighting: Vec<Type>,
 }
 impl Row {
+    #[must_use]
+    pub fn new(line: &str, tab_width: usize) -> Row {
+        let mut row = Row {
+            content: line.graphemes(true).map(str::to_string).collect(),
+            highlighting: Vec::new(),
+        };
+        row.parse_specials(tab_width);
+        row
+    }
     #[must_use]
     pub fn to_string(&self, range: RangeInclusive<usize>) -> String {
+        self.to_string_marked(range, &|_| None)
+    }
+    //Like `to_string`, with a background colour on the graphemes `marked` picks out.
+    #[must_use]
+    pub fn to_string_marked(
+        &self,
+        range: RangeInclusive<usize>,
+        marked: &dyn Fn(usize) -> Option<Rgb>,
+    ) -> String {
         let mut result = String::from(&format!("{}", Fg(Rgb(255, 255, 255))));
         let mut last = Type::None;
+        let mut last_mark = None;
         for gr in range {
+            let mark = marked(gr);
+            if mark != last_mark {
+                match mark {
+                    Some(color) => result += &format!("{}", Bg(color)),
+                    None => result += &format!("{}", Bg(Reset)),
+                }
+                last_mark = mark;
+            }
             if self.highlighting[gr] == last {
                 result += &self.content[gr].clone();
             } else {
@@ -26,16 +54,52 @@ impl Row {
         }
         result
     }
-    pub fn parse_specials(&mut self) {
-        for (i, gr) in self.content.clone().iter().enumerate() {
+    //The row split at Unicode word boundaries, as grapheme ranges. Runs of
+    //spaces and single punctuation marks come out as their own pieces.
+    #[must_use]
+    pub fn words(&self) -> Vec<Range<usize>> {
+        let mut words = Vec::new();
+        let mut start = 0;
+        for word in self.inner_string().split_word_bounds() {
+            let len = word.graphemes(true).count();
+            words.push(start..start + len);
+            start += len;
+        }
+        words
+    }
+    //The column of the first character that isn't a space.
+    #[must_use]
+    pub fn first_non_blank(&self) -> usize {
+        self.content
+            .iter()
+            .position(|gr| !gr.trim().is_empty())
+            .unwrap_or(self.content.len())
+    }
+    #[must_use]
+    pub fn is_blank(&self) -> bool {
+        self.first_non_blank() == self.content.len()
+    }
+    //The word piece under `x`, or the one just before it at the end of the row.
+    #[must_use]
+    pub fn word_at(&self, x: usize) -> Range<usize> {
+        let words = self.words();
+        words
+            .iter()
+            .find(|word| word.contains(&x))
+            .or_else(|| words.last())
+            .cloned()
+            .unwrap_or(x..x)
+    }
+    pub fn parse_specials(&mut self, tab_width: usize) {
+        let mut content = Vec::new();
+        for gr in self.content.drain(..) {
             if gr == "\t" {
-                self.content.remove(i);
-                self.content.insert(i, " ".to_string());
-                self.content.insert(i, " ".to_string());
-                self.content.insert(i, " ".to_string());
-                self.content.insert(i, " ".to_string());
+                content.extend((0..tab_width).map(|_| " ".to_string()));
+            } else {
+                content.push(gr);
             }
         }
+        self.content = content;
     }
     #[allow(clippy::must_use_candidate)]
     pub fn search(&self, string: &str) -> Option<usize> {
@@ -149,7 +213,8 @@ impl Row {
             .map(|s| s.to_string())
             .collect()
     }
-    fn inner_string(&self) -> String {
+    #[must_use]
+    pub fn inner_string(&self) -> String {
         let mut bit_buffer = String::new();
         for gr in &self.content {
             bit_buffer += gr;
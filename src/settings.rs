@@ -0,0 +1,77 @@
+use crate::config::Config;
+
+//Options that can be changed while running with `:set name=value`
+//and given defaults in the [editor] section of the config file.
+pub struct Settings {
+    pub tab_width: usize,
+    //Globs left out of the file finder, on top of .gitignore, and the explorer.
+    pub ignore: Vec<String>,
+    //Whether typing an opening bracket or quote adds the closing one.
+    pub auto_pairs: bool,
+    //Whether the completion popup opens by itself while typing a word.
+    pub auto_complete: bool,
+    //Whether saving runs the file type's formatter first.
+    pub format_on_save: bool,
+}
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            tab_width: 4,
+            ignore: vec![".git".to_string(), "target".to_string()],
+            auto_pairs: true,
+            auto_complete: true,
+            format_on_save: false,
+        }
+    }
+}
+impl Settings {
+    #[must_use]
+    pub fn new(config: &Config, problems: &mut Vec<String>) -> Settings {
+        let mut settings = Settings::default();
+        for (option, value) in config.section("editor") {
+            if let Err(e) = settings.set(option, Some(value)) {
+                problems.push(format!("config: {}", e));
+            }
+        }
+        settings
+    }
+    ///# Errors
+    ///
+    /// returns a message if the option doesn't exist or the value doesn't fit it
+    pub fn set(&mut self, option: &str, value: Option<&str>) -> Result<(), String> {
+        match option {
+            "tabstop" | "ts" => {
+                let value = value.ok_or("tabstop needs a value")?;
+                self.tab_width = match value.parse::<usize>() {
+                    Ok(width) if (1..=16).contains(&width) => width,
+                    _ => return Err(format!("invalid tabstop: {}", value)),
+                };
+            }
+            "ignore" => {
+                self.ignore = value
+                    .unwrap_or_default()
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|glob| !glob.is_empty())
+                    .map(str::to_string)
+                    .collect();
+            }
+            "autopairs" | "ap" => self.auto_pairs = parse_bool(option, value)?,
+            "noautopairs" | "noap" => self.auto_pairs = false,
+            "autocomplete" | "ac" => self.auto_complete = parse_bool(option, value)?,
+            "noautocomplete" | "noac" => self.auto_complete = false,
+            "formatonsave" | "fos" => self.format_on_save = parse_bool(option, value)?,
+            "noformatonsave" | "nofos" => self.format_on_save = false,
+            _ => return Err(format!("unknown option: {}", option)),
+        }
+        Ok(())
+    }
+}
+//A bare `:set option` turns it on.
+fn parse_bool(option: &str, value: Option<&str>) -> Result<bool, String> {
+    match value.map(str::to_lowercase).as_deref() {
+        None | Some("true" | "on" | "yes" | "1") => Ok(true),
+        Some("false" | "off" | "no" | "0") => Ok(false),
+        Some(value) => Err(format!("invalid {}: {}", option, value)),
+    }
+}
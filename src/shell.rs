@@ -0,0 +1,117 @@
+use std::{
+    io::{Read, Write},
+    process::{Command, Stdio},
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+//How long a command gets to finish before it is killed.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+//Runs `command` with the shell, feeding it `input`, and returns what it printed.
+///# Errors
+///
+/// returns the first line the command printed to stderr, or its exit status, if
+/// it couldn't be run or exited with an error, and says so if it timed out
+pub fn pipe(command: &str, input: &str) -> Result<String, String> {
+    pipe_until(command, input, &AtomicBool::new(false))
+}
+//Like `pipe`, killing the command early once `cancel` is set.
+///# Errors
+///
+/// as for `pipe`, and says so if it was cancelled
+pub fn pipe_until(command: &str, input: &str, cancel: &AtomicBool) -> Result<String, String> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| e.to_string())?;
+    let mut stdin = child.stdin.take().ok_or("couldn't write to the command")?;
+    let mut stdout = child
+        .stdout
+        .take()
+        .ok_or("couldn't read from the command")?;
+    let mut stderr = child
+        .stderr
+        .take()
+        .ok_or("couldn't read from the command")?;
+    let input = input.to_string();
+    //written and read on other threads, so a command that prints before it has
+    //read everything can't leave both sides waiting on each other
+    let writer = thread::spawn(move || stdin.write_all(input.as_bytes()));
+    let out = thread::spawn(move || {
+        let mut bytes = Vec::new();
+        let _ = stdout.read_to_end(&mut bytes);
+        bytes
+    });
+    let err = thread::spawn(move || {
+        let mut bytes = Vec::new();
+        let _ = stderr.read_to_end(&mut bytes);
+        bytes
+    });
+    let deadline = Instant::now() + TIMEOUT;
+    let status = loop {
+        if let Some(status) = child.try_wait().map_err(|e| e.to_string())? {
+            break status;
+        }
+        let cancelled = cancel.load(Ordering::Relaxed);
+        if cancelled || Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(if cancelled {
+                "cancelled".to_string()
+            } else {
+                format!("no answer after {} seconds", TIMEOUT.as_secs())
+            });
+        }
+        thread::sleep(Duration::from_millis(10));
+    };
+    let _ = writer.join();
+    let stdout = out.join().unwrap_or_default();
+    if !status.success() {
+        let stderr = err.join().unwrap_or_default();
+        let stderr = String::from_utf8_lossy(&stderr);
+        return Err(stderr
+            .lines()
+            .find(|line| !line.trim().is_empty())
+            .map_or_else(|| status.to_string(), str::to_string));
+    }
+    String::from_utf8(stdout).map_err(|_| "the output isn't UTF-8".to_string())
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn feeds_input_and_returns_output() {
+        assert_eq!(pipe("tr a-z A-Z", "shout\n"), Ok("SHOUT\n".to_string()));
+        let long = "line\n".repeat(100_000);
+        assert_eq!(pipe("cat", &long), Ok(long));
+    }
+    #[test]
+    fn reports_failures() {
+        assert_eq!(
+            pipe("echo >&2; echo broken >&2; exit 3", ""),
+            Err("broken".to_string())
+        );
+        assert_eq!(pipe("exit 4", ""), Err("exit status: 4".to_string()));
+        assert_eq!(
+            pipe("printf '\\377'", ""),
+            Err("the output isn't UTF-8".to_string())
+        );
+    }
+    #[test]
+    fn stops_when_cancelled() {
+        let cancel = AtomicBool::new(true);
+        let start = Instant::now();
+        assert_eq!(
+            pipe_until("sleep 10", "", &cancel),
+            Err("cancelled".to_string())
+        );
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,453 @@
+use std::{collections::HashMap, fs, iter::Peekable, str::Chars};
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{config::config_dir, document, Position};
+
+//A piece of boilerplate that a short prefix expands to. The body uses the
+//usual snippet syntax: $1 and ${1:default} are tab stops, visited in order,
+//with $0 where the cursor ends up. A stop that appears more than once is
+//linked, so typing in one types in all of them. Tabs in the body are indent
+//levels and new lines keep the indentation of the line it's expanded on.
+#[derive(Clone)]
+pub struct Snippet {
+    pub prefix: String,
+    pub description: String,
+    pub body: String,
+}
+//Snippet files are a list of `snippet <prefix> <description>` headers, each
+//followed by its body with every line indented by a tab. Lines starting with #
+//outside a body are comments.
+//
+//Returns the snippets and a message for every line that fits neither.
+#[must_use]
+pub fn parse_file(content: &str) -> (Vec<Snippet>, Vec<String>) {
+    let mut snippets: Vec<Snippet> = Vec::new();
+    let mut errors = Vec::new();
+    for (i, line) in content.lines().enumerate() {
+        if let Some(header) = line.strip_prefix("snippet ") {
+            let (prefix, description) = header.trim().split_once(' ').unwrap_or((header, ""));
+            snippets.push(Snippet {
+                prefix: prefix.trim().to_string(),
+                description: description.trim().to_string(),
+                body: String::new(),
+            });
+        } else if let (Some(body), Some(snippet)) = (line.strip_prefix('\t'), snippets.last_mut()) {
+            snippet.body.push_str(body);
+            snippet.body.push('\n');
+        } else if line.trim().is_empty() {
+            if let Some(snippet) = snippets.last_mut() {
+                snippet.body.push('\n');
+            }
+        } else if !line.starts_with('#') {
+            errors.push(format!(
+                "snippets line {}: expected `snippet <prefix>` or a line indented by a tab",
+                i + 1
+            ));
+        }
+    }
+    for snippet in &mut snippets {
+        let body = snippet.body.trim_end_matches('\n').len();
+        snippet.body.truncate(body);
+    }
+    (snippets, errors)
+}
+//The snippets the user wrote for `file_type`, from snippets/<file type>.snippets
+//in the config directory.
+///# Errors
+///
+/// returns the problems found in the file; a missing file is not one
+pub fn load(file_type: &str) -> Result<Vec<Snippet>, Vec<String>> {
+    let path = match config_dir() {
+        Some(dir) => dir
+            .join("snippets")
+            .join(format!("{}.snippets", file_type.to_lowercase())),
+        None => return Ok(Vec::new()),
+    };
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(_) => return Ok(Vec::new()),
+    };
+    match parse_file(&content) {
+        (snippets, errors) if errors.is_empty() => Ok(snippets),
+        (_, errors) => Err(errors
+            .into_iter()
+            .map(|e| format!("{}: {}", path.display(), e))
+            .collect()),
+    }
+}
+//EXPANSION
+enum Part {
+    Text(String),
+    Stop(usize, Vec<Part>),
+}
+//A place in an expansion as (line, grapheme), counted from its start.
+type Offset = (usize, usize);
+//What a snippet body comes to: its text, and where each tab stop is in it as
+//(stop, start, end).
+pub struct Expansion {
+    pub text: String,
+    pub stops: Vec<(usize, Offset, Offset)>,
+}
+//Expands `body` for a line indented by `indent`, where an indent level is `unit`.
+#[must_use]
+pub fn expand(body: &str, indent: &str, unit: &str) -> Expansion {
+    let parts = parse(&mut body.chars().peekable(), false);
+    let mut defaults = HashMap::new();
+    collect_defaults(&parts, &mut defaults);
+    let mut expansion = Expansion {
+        text: String::new(),
+        stops: Vec::new(),
+    };
+    expansion.render(&parts, &defaults, indent, unit);
+    expansion
+}
+//The body as the text it would insert, for showing in a preview.
+#[must_use]
+pub fn preview(body: &str) -> String {
+    expand(body, "", "    ").text
+}
+fn parse(chars: &mut Peekable<Chars>, nested: bool) -> Vec<Part> {
+    let mut parts = Vec::new();
+    let mut text = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.peek() {
+                Some(&escaped @ ('$' | '}' | '\\')) => {
+                    chars.next();
+                    text.push(escaped);
+                }
+                _ => text.push(c),
+            },
+            '}' if nested => break,
+            '$' => {
+                let part = match chars.peek() {
+                    Some(c) if c.is_ascii_digit() => Some(Part::Stop(number(chars), Vec::new())),
+                    Some('{') => {
+                        chars.next();
+                        placeholder(chars)
+                    }
+                    //variables such as $TM_FILENAME have nothing to stand for here
+                    Some(c) if c.is_alphabetic() || *c == '_' => {
+                        while chars
+                            .next_if(|c| c.is_alphanumeric() || *c == '_')
+                            .is_some()
+                        {}
+                        None
+                    }
+                    _ => {
+                        text.push('$');
+                        continue;
+                    }
+                };
+                if !text.is_empty() {
+                    parts.push(Part::Text(std::mem::take(&mut text)));
+                }
+                parts.extend(part);
+            }
+            c => text.push(c),
+        }
+    }
+    if !text.is_empty() {
+        parts.push(Part::Text(text));
+    }
+    parts
+}
+fn number(chars: &mut Peekable<Chars>) -> usize {
+    let mut digits = String::new();
+    while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+        digits.push(digit);
+    }
+    digits.parse().unwrap_or(0)
+}
+//What follows a ${: a stop with its default, the first of a choice of
+//defaults, or a variable, which only leaves its default.
+fn placeholder(chars: &mut Peekable<Chars>) -> Option<Part> {
+    if !chars.peek().is_some_and(char::is_ascii_digit) {
+        while chars
+            .next_if(|c| c.is_alphanumeric() || *c == '_')
+            .is_some()
+        {}
+        let default = if chars.next_if_eq(&':').is_some() {
+            parse(chars, true)
+        } else {
+            chars.next_if_eq(&'}');
+            Vec::new()
+        };
+        let text: String = default.iter().map(flatten).collect();
+        return (!text.is_empty()).then_some(Part::Text(text));
+    }
+    let n = number(chars);
+    match chars.next() {
+        Some(':') => Some(Part::Stop(n, parse(chars, true))),
+        Some('|') => {
+            let mut choices = String::new();
+            for c in chars.by_ref() {
+                if c == '|' {
+                    break;
+                }
+                choices.push(c);
+            }
+            chars.next_if_eq(&'}');
+            let first = choices.split(',').next().unwrap_or_default();
+            Some(Part::Stop(n, vec![Part::Text(first.to_string())]))
+        }
+        _ => Some(Part::Stop(n, Vec::new())),
+    }
+}
+fn flatten(part: &Part) -> String {
+    match part {
+        Part::Text(text) => text.clone(),
+        Part::Stop(_, default) => default.iter().map(flatten).collect(),
+    }
+}
+//The first default given for each stop, which its other appearances mirror.
+fn collect_defaults<'a>(parts: &'a [Part], defaults: &mut HashMap<usize, &'a [Part]>) {
+    for part in parts {
+        if let Part::Stop(n, default) = part {
+            if !default.is_empty() {
+                defaults.entry(*n).or_insert(default.as_slice());
+                collect_defaults(default, defaults);
+            }
+        }
+    }
+}
+impl Expansion {
+    fn render(
+        &mut self,
+        parts: &[Part],
+        defaults: &HashMap<usize, &[Part]>,
+        indent: &str,
+        unit: &str,
+    ) {
+        for part in parts {
+            match part {
+                Part::Text(text) => {
+                    for c in text.chars() {
+                        match c {
+                            '\n' => {
+                                self.text.push('\n');
+                                self.text.push_str(indent);
+                            }
+                            '\t' => self.text.push_str(unit),
+                            c => self.text.push(c),
+                        }
+                    }
+                }
+                Part::Stop(n, default) => {
+                    let start = self.end();
+                    let default = match default.as_slice() {
+                        [] => defaults.get(n).copied().unwrap_or_default(),
+                        default => default,
+                    };
+                    self.render(default, defaults, indent, unit);
+                    self.stops.push((*n, start, self.end()));
+                }
+            }
+        }
+    }
+    //Where the text so far ends.
+    fn end(&self) -> Offset {
+        let line = self.text.matches('\n').count();
+        let last = self.text.rsplit('\n').next().unwrap_or_default();
+        (line, last.graphemes(true).count())
+    }
+}
+//SESSIONS
+//A tab stop of an expanded snippet, at every place it appears.
+pub struct Stop {
+    pub number: usize,
+    pub ranges: Vec<(Position, Position)>,
+}
+//An expanded snippet whose tab stops are being filled in. Stops are visited
+//by number with $0 last, where the session ends.
+pub struct Session {
+    pub stops: Vec<Stop>,
+    pub current: usize,
+}
+impl Session {
+    //The session for `expansion` inserted at `at`.
+    #[must_use]
+    pub fn new(expansion: &Expansion, at: &Position) -> Session {
+        let position = |(line, x): Offset| Position {
+            x: if line == 0 { at.x + x } else { x },
+            y: at.y + line,
+        };
+        let mut stops: Vec<Stop> = Vec::new();
+        for &(number, start, end) in &expansion.stops {
+            let range = (position(start), position(end));
+            match stops.iter_mut().find(|stop| stop.number == number) {
+                Some(stop) => stop.ranges.push(range),
+                None => stops.push(Stop {
+                    number,
+                    ranges: vec![range],
+                }),
+            }
+        }
+        if !stops.iter().any(|stop| stop.number == 0) {
+            let end = position(expansion.end());
+            stops.push(Stop {
+                number: 0,
+                ranges: vec![(end.clone(), end)],
+            });
+        }
+        stops.sort_by_key(|stop| {
+            if stop.number == 0 {
+                usize::MAX
+            } else {
+                stop.number
+            }
+        });
+        Session { stops, current: 0 }
+    }
+    #[must_use]
+    pub fn stop(&self) -> &Stop {
+        &self.stops[self.current]
+    }
+    //Moves on to the next stop. False if there is none, or back from the first.
+    pub fn step(&mut self, forward: bool) -> bool {
+        let next = if forward {
+            self.current + 1
+        } else {
+            match self.current.checked_sub(1) {
+                Some(previous) => previous,
+                None => return false,
+            }
+        };
+        if next < self.stops.len() {
+            self.current = next;
+            true
+        } else {
+            false
+        }
+    }
+    //Whether `position` is in the stop being filled in.
+    #[must_use]
+    pub fn contains(&self, position: &Position) -> bool {
+        self.stop()
+            .ranges
+            .iter()
+            .any(|(start, end)| start <= position && position <= end)
+    }
+    //Keeps the stops on their text after start..end was replaced by text ending
+    //at `new_end`. What is typed at the start of the current stop goes into it.
+    pub fn edited(&mut self, start: &Position, end: &Position, new_end: &Position) {
+        for (i, stop) in self.stops.iter_mut().enumerate() {
+            for (from, to) in &mut stop.ranges {
+                if !(i == self.current && from == start) {
+                    *from = document::shift(from, start, end, new_end);
+                }
+                *to = document::shift(to, start, end, new_end);
+            }
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(x: usize, y: usize) -> Position {
+        Position { x, y }
+    }
+    #[test]
+    fn parses_snippet_files() {
+        let (snippets, errors) = parse_file(
+            "# comment\nsnippet fn a function\n\tfn ${1:name}() {\n\n\t\t$0\n\t}\n\nsnippet p\n\tprintln!();\n",
+        );
+        assert!(errors.is_empty());
+        assert_eq!(snippets.len(), 2);
+        assert_eq!(snippets[0].prefix, "fn");
+        assert_eq!(snippets[0].description, "a function");
+        assert_eq!(snippets[0].body, "fn ${1:name}() {\n\n\t$0\n}");
+        assert_eq!(snippets[1].prefix, "p");
+        assert_eq!(snippets[1].description, "");
+        assert_eq!(snippets[1].body, "println!();");
+    }
+    #[test]
+    fn reports_stray_lines() {
+        let (snippets, errors) = parse_file("stray\nsnippet a\n\tx\n  spaces\n");
+        assert_eq!(snippets.len(), 1);
+        assert_eq!(
+            errors,
+            vec![
+                "snippets line 1: expected `snippet <prefix>` or a line indented by a tab",
+                "snippets line 4: expected `snippet <prefix>` or a line indented by a tab"
+            ]
+        );
+    }
+    #[test]
+    fn expands_stops_with_their_defaults() {
+        let expansion = expand("let ${1:x} = $2;$0", "", "    ");
+        assert_eq!(expansion.text, "let x = ;");
+        assert_eq!(
+            expansion.stops,
+            vec![
+                (1, (0, 4), (0, 5)),
+                (2, (0, 8), (0, 8)),
+                (0, (0, 9), (0, 9))
+            ]
+        );
+    }
+    #[test]
+    fn expands_new_lines_with_the_indent_and_tabs_as_the_unit() {
+        let expansion = expand("if $1 {\n\t$0\n}", "  ", "    ");
+        assert_eq!(expansion.text, "if  {\n      \n  }");
+        assert_eq!(
+            expansion.stops,
+            vec![(1, (0, 3), (0, 3)), (0, (1, 6), (1, 6))]
+        );
+    }
+    #[test]
+    fn linked_stops_mirror_the_first_default() {
+        let expansion = expand("${1:name}: $1, ${2:a ${1}}", "", "\t");
+        assert_eq!(expansion.text, "name: name, a name");
+        let ones: Vec<_> = expansion.stops.iter().filter(|s| s.0 == 1).collect();
+        assert_eq!(ones.len(), 3);
+    }
+    #[test]
+    fn expands_choices_variables_and_escapes() {
+        assert_eq!(expand("${1|one,two|}", "", "").text, "one");
+        assert_eq!(expand("$TM_FILENAME-${TM_X:def}", "", "").text, "-def");
+        assert_eq!(expand("\\$1 \\} $ x", "", "").text, "$1 } $ x");
+    }
+    #[test]
+    fn sessions_visit_stops_in_order_with_zero_last() {
+        let mut session = Session::new(&expand("$0a${2:b}${1:c}", "", ""), &at(4, 3));
+        let numbers: Vec<usize> = session.stops.iter().map(|stop| stop.number).collect();
+        assert_eq!(numbers, vec![1, 2, 0]);
+        assert_eq!(session.stop().ranges, vec![(at(6, 3), at(7, 3))]);
+        assert!(!session.step(false));
+        assert!(session.step(true));
+        assert!(session.step(true));
+        assert_eq!(session.stop().ranges, vec![(at(4, 3), at(4, 3))]);
+        assert!(!session.step(true));
+    }
+    #[test]
+    fn sessions_end_at_the_end_without_a_zero_stop() {
+        let session = Session::new(&expand("a\nbc$1", " ", ""), &at(2, 1));
+        let last = session.stops.last().unwrap();
+        assert_eq!(last.number, 0);
+        assert_eq!(last.ranges, vec![(at(3, 2), at(3, 2))]);
+    }
+    #[test]
+    fn typing_in_the_current_stop_grows_it() {
+        let mut session = Session::new(&expand("${1:x} $1 $2", "", ""), &at(0, 1));
+        //the default is replaced by "abc" typed over it
+        session.edited(&at(0, 1), &at(1, 1), &at(3, 1));
+        assert_eq!(session.stop().ranges[0], (at(0, 1), at(3, 1)));
+        //the linked copy after it moves along
+        assert_eq!(session.stop().ranges[1], (at(4, 1), at(5, 1)));
+        assert_eq!(session.stops[1].ranges[0], (at(6, 1), at(6, 1)));
+        //typing at the start of the current stop stays in it
+        session.edited(&at(0, 1), &at(0, 1), &at(1, 1));
+        assert_eq!(session.stop().ranges[0], (at(0, 1), at(4, 1)));
+        assert!(session.contains(&at(2, 1)));
+        assert!(!session.contains(&at(7, 1)));
+    }
+    #[test]
+    fn new_lines_above_move_the_stops_down() {
+        let mut session = Session::new(&expand("$1", "", ""), &at(2, 5));
+        session.edited(&at(0, 1), &at(0, 1), &at(0, 2));
+        assert_eq!(session.stop().ranges, vec![(at(2, 6), at(2, 6))]);
+    }
+}
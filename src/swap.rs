@@ -0,0 +1,195 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    fs::{self, create_dir_all, read_to_string},
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+    process,
+    time::{Duration, Instant},
+};
+
+use crate::history::data_dir;
+
+const HEADER: &str = "saphire swap";
+const INTERVAL: Duration = Duration::from_secs(2);
+
+//Journal of the unsaved buffer, kept in the data directory so a crash doesn't lose edits.
+//The file holds a header line, the process id that wrote it, then the buffer.
+pub struct Swap {
+    file: Option<PathBuf>,
+    last_hash: u64,
+    last_write: Instant,
+}
+pub struct Recovered {
+    pub contents: String,
+    pub pid: u32,
+    pub file: PathBuf,
+}
+impl Recovered {
+    //Whether the process that wrote the swap file still seems to be running.
+    #[must_use]
+    pub fn running(&self) -> bool {
+        self.pid != process::id() && Path::new(&format!("/proc/{}", self.pid)).exists()
+    }
+}
+impl Default for Swap {
+    fn default() -> Self {
+        Swap {
+            file: None,
+            last_hash: 0,
+            last_write: Instant::now(),
+        }
+    }
+}
+impl Swap {
+    #[must_use]
+    pub fn file_for(document: Option<&Path>) -> Option<PathBuf> {
+        let dir = data_dir()?.join("swap");
+        let name = match document {
+            Some(path) => {
+                let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+                escape(&path.to_string_lossy())
+            }
+            None => format!("unnamed-{}", process::id()),
+        };
+        Some(dir.join(format!("{}.swp", name)))
+    }
+    //True once enough time has passed since the last write.
+    #[must_use]
+    pub fn due(&self) -> bool {
+        self.last_write.elapsed() >= INTERVAL
+    }
+    #[must_use]
+    pub fn next_due(&self) -> Instant {
+        self.last_write + INTERVAL
+    }
+    //Writes the buffer unless it's unchanged since the last write.
+    pub fn update(&mut self, document: Option<&Path>, contents: &str) {
+        self.last_write = Instant::now();
+        let mut hasher = DefaultHasher::new();
+        contents.hash(&mut hasher);
+        let hash = hasher.finish();
+        let file = Swap::file_for(document);
+        if hash == self.last_hash && file == self.file {
+            return;
+        }
+        if file != self.file {
+            self.remove();
+        }
+        if let Some(file) = &file {
+            if let Some(dir) = file.parent() {
+                let _ = create_dir_all(dir);
+            }
+            let data = format!("{}\n{}\n{}", HEADER, process::id(), contents);
+            if let Err(e) = fs::write(file, data) {
+                log::warn!("couldn't write swap file: {}", e);
+            }
+        }
+        self.file = file;
+        self.last_hash = hash;
+    }
+    pub fn remove(&mut self) {
+        if let Some(file) = self.file.take() {
+            let _ = fs::remove_file(file);
+        }
+        self.last_hash = 0;
+    }
+    //A swap file left behind for `document` by an earlier session, if there is one.
+    #[must_use]
+    pub fn find(document: &Path) -> Option<Recovered> {
+        let file = Swap::file_for(Some(document))?;
+        let data = read_to_string(&file).ok()?;
+        let mut parts = data.splitn(3, '\n');
+        if parts.next()? != HEADER {
+            return None;
+        }
+        let pid = parts.next()?.parse().ok()?;
+        Some(Recovered {
+            contents: parts.next()?.to_string(),
+            pid,
+            file,
+        })
+    }
+}
+//Turns a path into a single file name that no other path maps to, by escaping `%`
+//and `/` the way URLs do.
+fn escape(path: &str) -> String {
+    let mut name = String::with_capacity(path.len());
+    for c in path.chars() {
+        match c {
+            '%' => name.push_str("%25"),
+            '/' => name.push_str("%2F"),
+            c => name.push(c),
+        }
+    }
+    name
+}
+//Writes `contents` beside the document as `<name>.emergency`, or into the data
+//directory if that isn't possible. Returns where it went.
+#[must_use]
+pub fn write_emergency(document: Option<&Path>, contents: &str) -> Option<PathBuf> {
+    let beside = document.map(|path| {
+        let mut name = path.as_os_str().to_os_string();
+        name.push(".emergency");
+        PathBuf::from(name)
+    });
+    let fallback = Swap::file_for(document).map(|file| file.with_extension("emergency"));
+    for file in beside.into_iter().chain(fallback) {
+        if let Some(dir) = file.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            let _ = create_dir_all(dir);
+        }
+        if fs::write(&file, contents).is_ok() {
+            return Some(file);
+        }
+    }
+    None
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn unescape(name: &str) -> String {
+        name.replace("%2F", "/").replace("%25", "%")
+    }
+    #[test]
+    fn file_names_are_unambiguous() {
+        let paths = [
+            "/a%/b", "/a/%b", "/a%%b", "/a%2Fb", "/a/2Fb", "/a%25", "/a/b",
+        ];
+        let mut names: Vec<String> = paths.iter().map(|path| escape(path)).collect();
+        for (path, name) in paths.iter().zip(&names) {
+            assert!(!name.contains('/'));
+            assert_eq!(&unescape(name), path);
+        }
+        names.sort();
+        names.dedup();
+        assert_eq!(names.len(), paths.len());
+        let (Some(a), Some(b)) = (
+            Swap::file_for(Some(Path::new("/a%/b"))),
+            Swap::file_for(Some(Path::new("/a/%b"))),
+        ) else {
+            return;
+        };
+        assert_ne!(a, b);
+    }
+    #[test]
+    fn writes_finds_and_removes_the_journal() {
+        let document = env::temp_dir().join(format!("saphire-swap-{}/a%b.txt", process::id()));
+        let Some(file) = Swap::file_for(Some(&document)) else {
+            return;
+        };
+        assert!(Swap::find(&document).is_none());
+        let mut swap = Swap::default();
+        swap.update(Some(&document), "one\ntwo");
+        let recovered = Swap::find(&document).expect("the journal was written");
+        assert_eq!(recovered.contents, "one\ntwo");
+        assert_eq!(recovered.pid, process::id());
+        assert_eq!(recovered.file, file);
+        assert!(!recovered.running());
+        swap.update(Some(&document), "three");
+        assert_eq!(Swap::find(&document).unwrap().contents, "three");
+        swap.remove();
+        assert!(!file.exists());
+        assert!(Swap::find(&document).is_none());
+    }
+}
@@ -4,10 +4,14 @@ use termion::raw::{IntoRawMode, RawTerminal};
 
 use crate::Position;
 
+//Button presses, drags and the wheel, reported in SGR form.
+const MOUSE_ON: &str = "\x1b[?1000h\x1b[?1002h\x1b[?1006h";
+const MOUSE_OFF: &str = "\x1b[?1006l\x1b[?1002l\x1b[?1000l";
+
 pub struct Terminal {
     pub width: u16,
     pub height: u16,
-    _stdout: RawTerminal<Stdout>,
+    stdout: RawTerminal<Stdout>,
 }
 impl Terminal {
     ///# Panics
@@ -15,11 +19,19 @@ impl Terminal {
     /// Will panic if the terminal cannot enter raw mode
     #[must_use]
     pub fn new(tuple: (u16, u16)) -> Terminal {
-        Terminal {
-            width: tuple.0,
-            height: tuple.1.saturating_sub(2),
-            _stdout: stdout().into_raw_mode().unwrap(),
-        }
+        let stdout = stdout().into_raw_mode().unwrap();
+        print!("{}{}", termion::screen::ToAlternateScreen, MOUSE_ON);
+        let mut terminal = Terminal {
+            width: 0,
+            height: 0,
+            stdout,
+        };
+        terminal.resize(tuple);
+        terminal
+    }
+    pub fn resize(&mut self, tuple: (u16, u16)) {
+        self.width = tuple.0;
+        self.height = tuple.1.saturating_sub(2);
     }
     pub fn clear_screen() {
         print!("{}{}", termion::clear::All, termion::cursor::Goto(1, 1));
@@ -43,6 +55,29 @@ impl Terminal {
     pub fn clear_row() {
         print!("{}", termion::clear::CurrentLine);
     }
+    //Hands the terminal back in a usable state: cooked mode, mouse reporting off,
+    //cursor shown, main screen.
+    //Errors are ignored since this runs when the terminal may already be gone.
+    pub fn restore(&self) {
+        Terminal::leave_screen();
+        let _ = self.stdout.suspend_raw_mode();
+    }
+    //Everything `restore` does but leaving raw mode, for when there's no terminal
+    //left to do that with: dropping one leaves raw mode by itself.
+    pub fn leave_screen() {
+        let mut out = stdout();
+        let _ = write!(
+            out,
+            "{}{}{}{}{}{}",
+            MOUSE_OFF,
+            termion::style::Reset,
+            termion::clear::All,
+            termion::cursor::Goto(1, 1),
+            termion::cursor::Show,
+            termion::screen::ToMainScreen
+        );
+        let _ = out.flush();
+    }
     ///# Panics
     ///
     /// Will panic if flush fails
@@ -50,3 +85,16 @@ impl Terminal {
         stdout().flush().unwrap();
     }
 }
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let mut out = stdout();
+        let _ = write!(
+            out,
+            "{}{}{}",
+            MOUSE_OFF,
+            termion::cursor::Show,
+            termion::screen::ToMainScreen
+        );
+        let _ = out.flush();
+    }
+}
@@ -0,0 +1,163 @@
+use std::time::{Duration, Instant};
+
+use crate::Position;
+
+//Edits of the same kind this close together are undone as one step, so a
+//typed word comes back in one go.
+const STEP_TIME: Duration = Duration::from_secs(1);
+const MAX_STEPS: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditKind {
+    Insert,
+    Delete,
+    Other,
+}
+//The text and the cursors as they were before an edit.
+pub struct Snapshot {
+    pub rows: Vec<Vec<String>>,
+    pub cursors: Vec<Position>,
+}
+//Every state of the text the history can get back to has a revision number, so
+//stepping back to the one that was saved can tell the buffer is clean again.
+pub struct Undo {
+    undo: Vec<(Snapshot, usize)>,
+    redo: Vec<(Snapshot, usize)>,
+    last: Option<(EditKind, Instant)>,
+    revision: usize,
+    next_revision: usize,
+    //the revision the file on disk has, if any
+    saved: Option<usize>,
+}
+//A fresh history is at the text as it was opened.
+impl Default for Undo {
+    fn default() -> Self {
+        Undo {
+            undo: Vec::new(),
+            redo: Vec::new(),
+            last: None,
+            revision: 0,
+            next_revision: 1,
+            saved: Some(0),
+        }
+    }
+}
+impl Undo {
+    //Whether an edit of `kind` starts a new step, and so needs a snapshot pushed.
+    pub fn starts_step(&mut self, kind: EditKind) -> bool {
+        let joins = matches!(self.last, Some((last, time))
+            if last == kind && kind != EditKind::Other && time.elapsed() < STEP_TIME);
+        self.last = Some((kind, Instant::now()));
+        !joins
+    }
+    //Starts a step from `snapshot`, the text before it.
+    pub fn push(&mut self, snapshot: Snapshot) {
+        self.undo.push((snapshot, self.revision));
+        if self.undo.len() > MAX_STEPS {
+            self.undo.remove(0);
+        }
+        self.redo.clear();
+        self.revision = self.next_revision;
+        self.next_revision += 1;
+    }
+    //Ends the current step, so the next edit can't join it.
+    pub fn break_step(&mut self) {
+        self.last = None;
+    }
+    //Trades `current` for the state before the last step.
+    pub fn undo(&mut self, current: Snapshot) -> Option<Snapshot> {
+        let (snapshot, revision) = self.undo.pop()?;
+        self.redo.push((current, self.revision));
+        self.revision = revision;
+        self.last = None;
+        Some(snapshot)
+    }
+    pub fn redo(&mut self, current: Snapshot) -> Option<Snapshot> {
+        let (snapshot, revision) = self.redo.pop()?;
+        self.undo.push((current, self.revision));
+        self.revision = revision;
+        self.last = None;
+        Some(snapshot)
+    }
+    pub fn clear(&mut self) {
+        *self = Undo::default();
+    }
+    //The text as it is now is what's on disk. Later edits start a step of their own,
+    //since one that joined the current step would share its revision.
+    pub fn mark_saved(&mut self) {
+        self.saved = Some(self.revision);
+        self.last = None;
+    }
+    //What's on disk is none of the states in the history.
+    pub fn mark_unsaved(&mut self) {
+        self.saved = None;
+    }
+    #[must_use]
+    pub fn is_saved(&self) -> bool {
+        self.saved == Some(self.revision)
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(text: &str) -> Snapshot {
+        Snapshot {
+            rows: vec![vec![text.to_string()]],
+            cursors: Vec::new(),
+        }
+    }
+    fn edit(undo: &mut Undo, before: &str) {
+        if undo.starts_step(EditKind::Other) {
+            undo.push(snapshot(before));
+        }
+    }
+    #[test]
+    fn undoing_back_to_the_saved_text_is_clean() {
+        let mut undo = Undo::default();
+        assert!(undo.is_saved());
+        edit(&mut undo, "a");
+        assert!(!undo.is_saved());
+        let back = undo.undo(snapshot("b")).unwrap();
+        assert_eq!(back.rows, vec![vec!["a".to_string()]]);
+        assert!(undo.is_saved());
+        undo.redo(snapshot("a")).unwrap();
+        assert!(!undo.is_saved());
+    }
+    #[test]
+    fn saving_moves_the_clean_state() {
+        let mut undo = Undo::default();
+        edit(&mut undo, "a");
+        undo.mark_saved();
+        assert!(undo.is_saved());
+        edit(&mut undo, "b");
+        undo.undo(snapshot("c"));
+        assert!(undo.is_saved());
+        undo.undo(snapshot("b"));
+        assert!(!undo.is_saved());
+    }
+    #[test]
+    fn edits_after_a_save_do_not_join_its_step() {
+        let mut undo = Undo::default();
+        assert!(undo.starts_step(EditKind::Insert));
+        undo.push(snapshot(""));
+        undo.mark_saved();
+        assert!(undo.starts_step(EditKind::Insert));
+    }
+    #[test]
+    fn a_changed_file_matches_no_state() {
+        let mut undo = Undo::default();
+        undo.mark_unsaved();
+        edit(&mut undo, "a");
+        undo.undo(snapshot("b"));
+        assert!(!undo.is_saved());
+    }
+    #[test]
+    fn new_edits_drop_the_redo_steps() {
+        let mut undo = Undo::default();
+        edit(&mut undo, "a");
+        undo.undo(snapshot("b"));
+        edit(&mut undo, "a");
+        assert!(undo.redo(snapshot("c")).is_none());
+    }
+}
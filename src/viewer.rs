@@ -0,0 +1,72 @@
+use termion::{
+    color::{Bg, Black, Fg, Reset, Rgb, White},
+    event::Key,
+};
+
+use crate::{picker::fit, terminal::Terminal};
+
+//A read-only scrolling overlay for text like diffs. Lines starting with
+//'+' or '-' are coloured as added or removed.
+pub struct Viewer {
+    pub title: String,
+    pub lines: Vec<String>,
+    pub scroll: usize,
+}
+impl Viewer {
+    #[must_use]
+    pub fn new(title: &str, lines: Vec<String>) -> Viewer {
+        Viewer {
+            title: title.to_string(),
+            lines,
+            scroll: 0,
+        }
+    }
+    //Returns false once the viewer should close.
+    pub fn handle(&mut self, key: Key, height: usize) -> bool {
+        let last = self.lines.len().saturating_sub(height.saturating_sub(1));
+        match key {
+            Key::Esc | Key::Char('q' | '\n') => return false,
+            Key::Up => self.scroll = self.scroll.saturating_sub(1),
+            Key::Down => self.scroll = (self.scroll + 1).min(last),
+            Key::PageUp => self.scroll = self.scroll.saturating_sub(height),
+            Key::PageDown | Key::Char(' ') => self.scroll = (self.scroll + height).min(last),
+            Key::Home => self.scroll = 0,
+            Key::End => self.scroll = last,
+            _ => (),
+        }
+        true
+    }
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn draw(&self, x: u16, y: u16, width: u16, height: u16) {
+        let width = width as usize;
+        Terminal::move_cursor(x, y);
+        print!(
+            "{}{}{}{}{}",
+            Bg(White),
+            Fg(Black),
+            fit(&format!(" {} (Esc to close)", self.title), width),
+            Fg(Reset),
+            Bg(Reset)
+        );
+        for row in 1..height as usize {
+            Terminal::move_cursor(x, y + row as u16);
+            let line = self
+                .lines
+                .get(self.scroll + row - 1)
+                .map_or("", String::as_str);
+            let color = match line.chars().next() {
+                Some('+') => Rgb(17, 209, 55),
+                Some('-') => Rgb(230, 60, 60),
+                _ => Rgb(255, 255, 255),
+            };
+            print!(
+                "{}{}{}{}{}",
+                Bg(Rgb(25, 25, 25)),
+                Fg(color),
+                fit(&line.replace('\t', "    "), width),
+                Fg(Reset),
+                Bg(Reset)
+            );
+        }
+    }
+}
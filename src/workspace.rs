@@ -0,0 +1,214 @@
+use std::{
+    fs::{read_to_string, write},
+    path::{Path, PathBuf},
+    sync::mpsc::channel,
+};
+
+use ignore::{
+    overrides::{Override, OverrideBuilder},
+    Walk, WalkBuilder, WalkState,
+};
+use regex::Regex;
+
+pub struct Hit {
+    pub path: PathBuf,
+    //1-based, like the editor's rows
+    pub line: usize,
+    //byte offset of the match in `text`
+    pub byte: usize,
+    pub text: String,
+}
+
+//Walks `root` honouring .gitignore files plus the user's own ignore globs.
+#[must_use]
+pub fn walker(root: &Path, ignore: &[String]) -> WalkBuilder {
+    let mut builder = WalkBuilder::new(root);
+    builder.require_git(false);
+    builder.overrides(ignored(root, ignore));
+    builder
+}
+//Matches the paths under `root` that the user's ignore globs leave out.
+#[must_use]
+pub fn ignored(root: &Path, ignore: &[String]) -> Override {
+    let mut overrides = OverrideBuilder::new(root);
+    for glob in ignore {
+        if let Err(e) = overrides.add(&format!("!{}", glob)) {
+            log::warn!("bad ignore glob {}: {}", glob, e);
+        }
+    }
+    overrides.build().unwrap_or_else(|_| Override::empty())
+}
+//Every file under `root`, relative to it, sorted by path.
+#[must_use]
+pub fn files(root: &Path, ignore: &[String]) -> Vec<PathBuf> {
+    let walk: Walk = walker(root, ignore).build();
+    let mut files: Vec<PathBuf> = walk
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_some_and(|t| t.is_file()))
+        .map(|entry| {
+            entry
+                .path()
+                .strip_prefix(root)
+                .unwrap_or_else(|_| entry.path())
+                .to_path_buf()
+        })
+        .collect();
+    files.sort();
+    files
+}
+//The first `lines` lines of a file, or nothing if it can't be read as text.
+#[must_use]
+pub fn preview(path: &Path, lines: usize) -> Vec<String> {
+    std::fs::read_to_string(path)
+        .map(|content| content.lines().take(lines).map(str::to_string).collect())
+        .unwrap_or_default()
+}
+//Searches every file under `root` on all cores. Files that aren't UTF-8 text are skipped.
+//Hits come back sorted by path and line.
+#[must_use]
+pub fn grep(root: &Path, ignore: &[String], regex: &Regex) -> Vec<Hit> {
+    let (sender, receiver) = channel();
+    walker(root, ignore).build_parallel().run(|| {
+        let sender = sender.clone();
+        Box::new(move |entry| {
+            let entry = match entry {
+                Ok(entry) if entry.file_type().is_some_and(|t| t.is_file()) => entry,
+                _ => return WalkState::Continue,
+            };
+            let content = match read_to_string(entry.path()) {
+                Ok(content) if !content.contains('\0') => content,
+                _ => return WalkState::Continue,
+            };
+            let path = entry
+                .path()
+                .strip_prefix(root)
+                .unwrap_or_else(|_| entry.path())
+                .to_path_buf();
+            for (i, line) in content.lines().enumerate() {
+                if let Some(found) = regex.find(line) {
+                    let hit = Hit {
+                        path: path.clone(),
+                        line: i + 1,
+                        byte: found.start(),
+                        text: line.to_string(),
+                    };
+                    if sender.send(hit).is_err() {
+                        return WalkState::Quit;
+                    }
+                }
+            }
+            WalkState::Continue
+        })
+    });
+    drop(sender);
+    let mut hits: Vec<Hit> = receiver.into_iter().collect();
+    hits.sort_by(|a, b| a.path.cmp(&b.path).then(a.line.cmp(&b.line)));
+    hits
+}
+//(line number, old text, new text)
+pub type LineChange = (usize, String, String);
+//The lines of `content` a replacement would change.
+#[must_use]
+pub fn replacements(content: &str, regex: &Regex, replacement: &str) -> Vec<LineChange> {
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| regex.is_match(line))
+        .map(|(i, line)| {
+            (
+                i + 1,
+                line.to_string(),
+                regex.replace_all(line, replacement).to_string(),
+            )
+        })
+        .filter(|(_, old, new)| old != new)
+        .collect()
+}
+//Applies a replacement to one file line by line so line endings are kept.
+///# Errors
+///
+/// returns the error if the file can't be read or written
+pub fn replace_in_file(path: &Path, regex: &Regex, replacement: &str) -> std::io::Result<()> {
+    let content = read_to_string(path)?;
+    let replaced: String = content
+        .split_inclusive('\n')
+        .map(|line| {
+            let body = line.trim_end_matches(&['\r', '\n'][..]);
+            format!(
+                "{}{}",
+                regex.replace_all(body, replacement),
+                &line[body.len()..]
+            )
+        })
+        .collect();
+    write(path, replaced)
+}
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use super::*;
+
+    fn change(line: usize, old: &str, new: &str) -> LineChange {
+        (line, old.to_string(), new.to_string())
+    }
+    //A scratch directory of its own for each test.
+    fn scratch(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("saphire-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+    #[test]
+    fn replacements_list_the_changed_lines() {
+        let regex = Regex::new("o+").unwrap();
+        let content = "foo\nbar\nboo too\n";
+        assert_eq!(
+            replacements(content, &regex, "0"),
+            vec![change(1, "foo", "f0"), change(3, "boo too", "b0 t0")]
+        );
+    }
+    #[test]
+    fn replacements_use_groups_and_skip_unchanged_lines() {
+        let regex = Regex::new(r"(\w+)=(\w+)").unwrap();
+        let content = "a=b\nc=c\nnone\n";
+        assert_eq!(
+            replacements(content, &regex, "${2}=${1}"),
+            vec![change(1, "a=b", "b=a")]
+        );
+        assert!(replacements("", &regex, "x").is_empty());
+    }
+    #[test]
+    fn replace_in_file_keeps_line_endings() {
+        let dir = scratch("replace");
+        let path = dir.join("a.txt");
+        fs::write(&path, "one\r\ntwo\none").unwrap();
+        replace_in_file(&path, &Regex::new("one").unwrap(), "1").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "1\r\ntwo\n1");
+        let _ = fs::remove_dir_all(&dir);
+    }
+    #[test]
+    fn grep_finds_hits_in_order_and_skips_binary_files() {
+        let dir = scratch("grep");
+        fs::create_dir(dir.join("sub")).unwrap();
+        fs::write(dir.join("b.txt"), "x\nneedle here\n").unwrap();
+        fs::write(dir.join("sub/a.txt"), "a needle\nno\nneedle\n").unwrap();
+        fs::write(dir.join("c.bin"), "needle\0").unwrap();
+        let hits = grep(&dir, &[], &Regex::new("needle").unwrap());
+        let found: Vec<(String, usize, usize)> = hits
+            .iter()
+            .map(|hit| (hit.path.to_string_lossy().to_string(), hit.line, hit.byte))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("b.txt".to_string(), 2, 0),
+                ("sub/a.txt".to_string(), 1, 2),
+                ("sub/a.txt".to_string(), 3, 0)
+            ]
+        );
+        let ignored = grep(&dir, &["sub".to_string()], &Regex::new("needle").unwrap());
+        assert_eq!(ignored.len(), 1);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
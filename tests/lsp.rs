@@ -0,0 +1,322 @@
+//Drives the scripted server in examples/fake_lsp.rs through the client the way
+//the editor does, with the messages coming back over a channel instead of as
+//editor jobs.
+use std::{
+    env,
+    path::PathBuf,
+    sync::mpsc::{self, Receiver, RecvTimeoutError},
+    time::{Duration, Instant},
+};
+
+use serde_json::{json, Value};
+use text_editor::{
+    document::Document,
+    lsp::{self, Client, Diagnostic, LspPosition, Message, Request, Severity},
+};
+
+//Long enough for a loaded machine, short enough to notice a hang.
+const TIMEOUT: Duration = Duration::from_secs(10);
+const URI: &str = "file:///nowhere/test.fake";
+
+struct Server {
+    client: Client,
+    messages: Receiver<Option<Value>>,
+}
+//Cargo builds the examples next to the test binaries, one directory up.
+fn fake_lsp() -> PathBuf {
+    let mut path = env::current_exe().expect("the test binary has a path");
+    path.pop();
+    path.pop();
+    path.push("examples");
+    path.push(format!("fake_lsp{}", env::consts::EXE_SUFFIX));
+    assert!(
+        path.exists(),
+        "build the fake server with cargo build --examples"
+    );
+    path
+}
+fn at(line: usize, character: usize) -> LspPosition {
+    LspPosition { line, character }
+}
+fn position(line: usize, character: usize) -> Value {
+    json!({"textDocument": {"uri": URI}, "position": at(line, character).to_json()})
+}
+impl Server {
+    //Starts the fake server with `flags` and gets through initializing it.
+    fn start(flags: &str) -> Server {
+        let (sender, messages) = mpsc::channel();
+        let command = format!("{} {}", fake_lsp().display(), flags);
+        let client = Client::spawn(&command, move |message| sender.send(message).is_ok())
+            .expect("the fake server starts");
+        let mut server = Server { client, messages };
+        let result = server.response(Request::Initialize);
+        assert_eq!(result["capabilities"]["hoverProvider"], true);
+        server.client.initialized(&result);
+        assert!(server.client.initialized);
+        server
+    }
+    fn open(&mut self, text: &str) -> Vec<Diagnostic> {
+        self.client.did_open(URI, "fake", text);
+        self.diagnostics()
+    }
+    //The next message for the client, skipping responses to nothing it asked.
+    fn next(&mut self) -> Message {
+        loop {
+            let message = self
+                .messages
+                .recv_timeout(TIMEOUT)
+                .expect("the server answers in time")
+                .expect("the server is still running");
+            if let Some(message) = self.client.receive(message) {
+                return message;
+            }
+        }
+    }
+    fn notification(&mut self, wanted: &str) -> Value {
+        loop {
+            if let Message::Notification { method, params } = self.next() {
+                if method == wanted {
+                    return params;
+                }
+            }
+        }
+    }
+    fn response(&mut self, wanted: Request) -> Value {
+        loop {
+            if let Message::Response { request, result } = self.next() {
+                if request == wanted {
+                    return result.expect("the request succeeds");
+                }
+            }
+        }
+    }
+    fn diagnostics(&mut self) -> Vec<Diagnostic> {
+        let (uri, diagnostics) =
+            lsp::diagnostics(&self.notification("textDocument/publishDiagnostics"));
+        assert_eq!(uri, URI);
+        diagnostics
+    }
+    //Waits for the server to stop, failing if it keeps talking.
+    fn stopped(&mut self) {
+        loop {
+            match self.messages.recv_timeout(TIMEOUT) {
+                Ok(Some(_)) => continue,
+                Ok(None) | Err(RecvTimeoutError::Disconnected) => return,
+                Err(RecvTimeoutError::Timeout) => panic!("the server kept running"),
+            }
+        }
+    }
+}
+fn ranges(diagnostics: &[Diagnostic]) -> Vec<(Severity, LspPosition, LspPosition)> {
+    diagnostics
+        .iter()
+        .map(|d| (d.severity, d.start.clone(), d.end.clone()))
+        .collect()
+}
+#[test]
+fn initializes_and_opens_a_document() {
+    let mut server = Server::start("");
+    let params = server.notification("window/showMessage");
+    assert_eq!(params["message"], "fake server ready");
+    let diagnostics = server.open("fn main() {\n    // TODO\n}\n");
+    assert_eq!(
+        ranges(&diagnostics),
+        [(Severity::Warning, at(1, 7), at(1, 11))]
+    );
+    assert_eq!(diagnostics[0].message, "unfinished work");
+}
+#[test]
+fn sends_incremental_edits() {
+    let mut server = Server::start("");
+    assert!(server.open("a\nb\n").is_empty());
+    server.client.edited(&at(1, 0), &at(1, 1), "éé error TODO");
+    server
+        .client
+        .sync(|| unreachable!("the server takes edits"));
+    assert_eq!(
+        ranges(&server.diagnostics()),
+        [
+            (Severity::Error, at(1, 3), at(1, 8)),
+            (Severity::Warning, at(1, 9), at(1, 13)),
+        ]
+    );
+    server.client.edited(&at(0, 1), &at(1, 8), "");
+    server
+        .client
+        .sync(|| unreachable!("the server takes edits"));
+    assert_eq!(
+        ranges(&server.diagnostics()),
+        [(Severity::Warning, at(0, 2), at(0, 6))]
+    );
+}
+#[test]
+fn sends_whole_documents_to_servers_that_want_them() {
+    let mut server = Server::start("--full");
+    assert!(server.open("a\n").is_empty());
+    server.client.edited(&at(0, 0), &at(0, 1), "TODO");
+    server.client.edited(&at(0, 4), &at(0, 4), " error");
+    server.client.sync(|| "TODO error\n".to_string());
+    assert_eq!(
+        ranges(&server.diagnostics()),
+        [
+            (Severity::Warning, at(0, 0), at(0, 4)),
+            (Severity::Error, at(0, 5), at(0, 10)),
+        ]
+    );
+}
+#[test]
+fn diagnostics_move_with_edits_above_them() {
+    let mut server = Server::start("");
+    let diagnostics = server.open("\n\nTODO\n");
+    server
+        .client
+        .diagnostics
+        .insert(URI.to_string(), diagnostics);
+    server.client.edited(&at(0, 0), &at(0, 0), "one\ntwo\n");
+    let moved = ranges(&server.client.diagnostics[URI]);
+    assert_eq!(moved, [(Severity::Warning, at(4, 0), at(4, 4))]);
+    //the server sees the same text, so it agrees
+    server.client.sync(String::new);
+    assert_eq!(ranges(&server.diagnostics()), moved);
+    //edits on the same line as a diagnostic leave it be
+    server.client.edited(&at(4, 4), &at(4, 4), "!");
+    let unmoved = ranges(&server.client.diagnostics[URI]);
+    assert_eq!(unmoved, [(Severity::Warning, at(4, 0), at(4, 4))]);
+}
+#[test]
+fn answers_hover_definition_and_references() {
+    let mut server = Server::start("");
+    server.open("let value = 1;\nvalue + value\n");
+    server
+        .client
+        .request(Request::Hover, "textDocument/hover", position(1, 2));
+    let hover = lsp::hover_text(&server.response(Request::Hover));
+    assert_eq!(hover, ["value", "`value` appears 3 times"]);
+    server.client.request(
+        Request::Definition,
+        "textDocument/definition",
+        position(1, 9),
+    );
+    let definition = lsp::locations(&server.response(Request::Definition));
+    assert_eq!(definition.len(), 1);
+    assert_eq!(definition[0].path, PathBuf::from("/nowhere/test.fake"));
+    assert_eq!(definition[0].start, at(0, 4));
+    let mut params = position(0, 5);
+    params["context"] = json!({"includeDeclaration": true});
+    server
+        .client
+        .request(Request::References, "textDocument/references", params);
+    let references = lsp::locations(&server.response(Request::References));
+    let starts: Vec<LspPosition> = references.into_iter().map(|l| l.start).collect();
+    assert_eq!(starts, [at(0, 4), at(1, 0), at(1, 8)]);
+    server
+        .client
+        .request(Request::Hover, "textDocument/hover", position(0, 11));
+    assert_eq!(server.response(Request::Hover), Value::Null);
+}
+#[test]
+fn renames_through_a_workspace_edit() {
+    let mut server = Server::start("");
+    let text = "let value = 1;\nvalue + value\n";
+    server.open(text);
+    let mut params = position(1, 0);
+    params["newName"] = json!("amount");
+    server
+        .client
+        .request(Request::Rename, "textDocument/rename", params);
+    let files = lsp::workspace_edit(&server.response(Request::Rename));
+    let (path, edits) = &files[0];
+    assert_eq!(path, &PathBuf::from("/nowhere/test.fake"));
+    assert_eq!(
+        lsp::apply_edits(text, edits),
+        "let amount = 1;\namount + amount\n"
+    );
+}
+#[test]
+fn carries_out_code_actions_through_server_requests() {
+    let mut server = Server::start("");
+    let diagnostics = server.open("x TODO\n");
+    let mut params =
+        json!({"textDocument": {"uri": URI}, "range": lsp::range(&at(0, 0), &at(0, 0))});
+    params["context"] = json!({"diagnostics": [diagnostics[0].raw]});
+    server
+        .client
+        .request(Request::CodeAction, "textDocument/codeAction", params);
+    let actions = server.response(Request::CodeAction);
+    let titles: Vec<&str> = actions
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter_map(|action| action["title"].as_str())
+        .collect();
+    assert_eq!(titles, ["Uppercase `x`", "Remove TODO"]);
+    server.client.request(
+        Request::ExecuteCommand,
+        "workspace/executeCommand",
+        actions[1]["command"].clone(),
+    );
+    let (id, params) = loop {
+        if let Message::Request { id, method, params } = server.next() {
+            assert_eq!(method, "workspace/applyEdit");
+            break (id, params);
+        }
+    };
+    let files = lsp::workspace_edit(&params["edit"]);
+    assert_eq!(lsp::apply_edits("x TODO\n", &files[0].1), "x \n");
+    server.client.respond(id, json!({"applied": true}));
+    assert_eq!(server.response(Request::ExecuteCommand), Value::Null);
+}
+#[test]
+fn completes_with_edit_ranges() {
+    let mut server = Server::start("");
+    let text = "value\nvalxyz\n";
+    server.open(text);
+    server.client.request(
+        Request::Completion,
+        "textDocument/completion",
+        position(1, 3),
+    );
+    let result = server.response(Request::Completion);
+    let mut document = Document::new(None, 4);
+    document.set_contents(text, 4);
+    let items = lsp::completion_items(&document, &result);
+    let value = items.iter().find(|item| item.label == "value").unwrap();
+    assert_eq!(value.insert, "value");
+    assert!(value.replace.is_some());
+    let call = items.iter().find(|item| item.snippet).unwrap();
+    assert_eq!(call.insert, "fake_call(${1:first}, ${2:second})$0");
+    assert!(call.replace.is_none());
+}
+#[test]
+fn survives_the_server_crashing() {
+    let mut server = Server::start("--crash");
+    assert!(server.open("fine\n").is_empty());
+    server.client.edited(&at(0, 0), &at(0, 0), "crash ");
+    server
+        .client
+        .sync(|| unreachable!("the server takes edits"));
+    server.stopped();
+    //talking to it afterwards goes nowhere without hanging or panicking
+    server
+        .client
+        .request(Request::Hover, "textDocument/hover", position(0, 0));
+    server.client.shutdown();
+    let start = Instant::now();
+    drop(server.client);
+    assert!(start.elapsed() < Duration::from_millis(100));
+}
+#[test]
+fn dropping_the_client_leaves_the_server_to_wind_down() {
+    let mut server = Server::start("");
+    server.open("a\n");
+    server.client.shutdown();
+    let start = Instant::now();
+    drop(server.client);
+    assert!(start.elapsed() < Duration::from_millis(100));
+    //its answer to shutdown still arrives, then it exits
+    let mut answered = false;
+    while let Ok(Some(message)) = server.messages.recv_timeout(TIMEOUT) {
+        answered |= message.get("id").is_some() && message["result"].is_null();
+    }
+    assert!(answered);
+}